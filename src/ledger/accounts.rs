@@ -1,24 +1,33 @@
-use super::structure::{Account, Ledger, TransactionMetadata};
+use super::structure::{
+    Account, CurrencyCode, Fee, Ledger, Money, Reconciliation, Transaction, TransactionID,
+    TransactionMetadata, TransferID,
+};
+use super::Error;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use chrono::{DateTime, Utc};
-
-use currency::Currency;
+use crypto::digest::Digest;
 
 #[allow(dead_code)]
 impl Ledger {
-    pub fn new_account(&mut self, name: &str, opening_balance: Currency, opening_date: DateTime<Utc>) {
+    /// Creates an account kept in the currency of its opening balance.
+    pub fn new_account(&mut self, name: &str, opening_balance: Money, opening_date: DateTime<Utc>) -> Result<(), Error> {
         for v in &self.accounts {
             if v.name == name {
-                panic!("An account with name `{}` already exists", name);
+                return Err(Error::DuplicateAccount(name.to_owned()));
             }
         }
 
         self.accounts.push(Account {
             name: name.to_owned(),
+            currency: opening_balance.currency(),
             opening_balance,
             opening_date,
             transactions: Vec::new(),
+            reconciliations: Vec::new(),
         });
+
+        Ok(())
     }
 
     pub fn get_account(&self, name: &str) -> Option<&Account> {
@@ -29,51 +38,621 @@ impl Ledger {
         self.accounts.iter().position(|x| x.name == name).and_then(move |x| self.accounts.get_mut(x))
     }
 
+    pub fn account(&self, name: &str) -> Result<&Account, Error> {
+        self.get_account(name).ok_or_else(|| Error::MissingAccount(name.to_owned()))
+    }
+
+    pub fn account_mut(&mut self, name: &str) -> Result<&mut Account, Error> {
+        self.get_account_mut(name).ok_or_else(|| Error::MissingAccount(name.to_owned()))
+    }
+
     /*pub fn remove_account(&mut self, name: &str) {
         if let Some(index) = self.accounts.iter().position(|x| x.name == name) {
             self.remove_account_at(index);
         }
     }*/
 
+    /// Removes an account along with the other leg of every transfer it was part of.
     pub fn remove_account_at(&mut self, position: usize) {
-        self.accounts.remove(position);
+        let account = self.accounts.remove(position);
+
+        for t in account.transactions {
+            if let TransactionMetadata::Transfer { id, from, to } = t.meta {
+                let other = if from == account.name { to } else { from };
+                if let Some(other) = self.get_account_mut(&other) {
+                    other.remove_transfer_leg(&id);
+                }
+            }
+        }
+    }
+
+    /// Records a transfer by pushing a leg on both its source and destination accounts.
+    /// Fails with `Error::NotATransfer` unless the transaction has `TransactionMetadata::Transfer`
+    /// metadata.
+    pub fn push_transfer(&mut self, transaction: Transaction) -> Result<(), Error> {
+        let (source, destination) = self.transfer_legs(transaction)?;
+        self.push_transfer_legs(source, destination)
+    }
+
+    /// Builds the source and destination legs of a transfer without touching the ledger, failing if
+    /// an account is missing or the amount cannot be converted.
+    fn transfer_legs(&self, transaction: Transaction) -> Result<(Transaction, Transaction), Error> {
+        let (from, to) = transfer_accounts(&transaction)?;
+
+        let mut destination_leg = transaction.clone();
+        destination_leg.id = new_transaction_id();
+        destination_leg.fees.clear();
+        destination_leg.cleared = false;
+
+        // Between accounts in different currencies, the destination receives the converted amount.
+        let destination_currency = self.account(to)?.currency;
+        self.account(from)?;
+        if transaction.amount.currency() != destination_currency {
+            destination_leg.amount = self.rates.convert(&transaction.amount, destination_currency, transaction.date)?;
+            destination_leg.original = Some(transaction.amount.clone());
+        }
+
+        Ok((transaction, destination_leg))
+    }
+
+    fn push_transfer_legs(&mut self, source: Transaction, destination: Transaction) -> Result<(), Error> {
+        let (from, to) = transfer_accounts(&source)?;
+        let (from, to) = (from.to_owned(), to.to_owned());
+
+        self.account_mut(&to)?.transactions.push(destination);
+        self.account_mut(&from)?.transactions.push(source);
+        Ok(())
+    }
+
+    /// Finds a transaction from the account name and the index or ID typed by the user.
+    /// The destination leg of a transfer resolves to its source leg, which holds the fees.
+    pub fn locate_transaction(&self, account: &str, index: &str) -> Result<(usize, usize), Error> {
+        let (account, position) = self.find_transaction(account, index)?;
+        self.locate_transaction_at(account, position)
+    }
+
+    /// Same as `locate_transaction`, but always finds the leg of a transfer on the given account.
+    pub fn find_transaction(&self, account: &str, index: &str) -> Result<(usize, usize), Error> {
+        let account_index = self
+            .accounts
+            .iter()
+            .position(|x| x.name == account)
+            .ok_or_else(|| Error::MissingAccount(account.to_owned()))?;
+        let transactions = &self.accounts[account_index].transactions;
+        let position = match index.parse::<usize>() {
+            Ok(position) => Some(position).filter(|x| *x < transactions.len()),
+            Err(_) => hex::decode(index)
+                .ok()
+                .and_then(|id| transactions.iter().position(|x| x.id == id)),
+        }
+        .ok_or_else(|| Error::MissingTransaction {
+            account: account.to_owned(),
+            index: index.to_owned(),
+        })?;
+
+        Ok((account_index, position))
+    }
+
+    /// Same as `locate_transaction`, with the account and transaction given by position.
+    pub fn locate_transaction_at(&self, account: usize, position: usize) -> Result<(usize, usize), Error> {
+        let name = &self.accounts[account].name;
+        let missing = || Error::MissingTransaction {
+            account: name.clone(),
+            index: position.to_string(),
+        };
+
+        let transaction = self.accounts[account]
+            .transactions
+            .get(position)
+            .ok_or_else(missing)?;
+
+        if let TransactionMetadata::Transfer { ref id, ref from, .. } = transaction.meta {
+            if from != name {
+                let source = self
+                    .accounts
+                    .iter()
+                    .position(|x| x.name == *from)
+                    .ok_or_else(|| Error::MissingAccount(from.clone()))?;
+                let leg = self.accounts[source]
+                    .transactions
+                    .iter()
+                    .position(|x| match x.meta {
+                        TransactionMetadata::Transfer { id: ref leg_id, .. } => leg_id == id,
+                        _ => false,
+                    })
+                    .ok_or_else(missing)?;
+                return Ok((source, leg));
+            }
+        }
+
+        Ok((account, position))
+    }
+
+    /// Replaces a transaction in place. Transfers are replaced on both of their legs.
+    pub fn replace_transaction_at(&mut self, account: usize, position: usize, transaction: Transaction) -> Result<(), Error> {
+        let is_transfer = |x: &Transaction| match x.meta {
+            TransactionMetadata::Transfer { .. } => true,
+            _ => false,
+        };
+
+        if is_transfer(&transaction) {
+            // Built before the old transaction is removed, so that a missing account or exchange
+            // rate leaves the ledger untouched.
+            let (source, mut destination) = self.transfer_legs(transaction)?;
+
+            // Keep the ID and cleared flag of the destination leg, even if it moves to another account.
+            if let Some(leg) = self.other_leg(account, position) {
+                destination.id = leg.id.clone();
+                destination.cleared = leg.cleared;
+            }
+
+            self.remove_transaction_at(account, position);
+            self.push_transfer_legs(source, destination)
+        } else if is_transfer(&self.accounts[account].transactions[position]) {
+            self.remove_transaction_at(account, position);
+            self.accounts[account].transactions.push(transaction);
+            Ok(())
+        } else {
+            self.accounts[account].transactions[position] = transaction;
+            Ok(())
+        }
+    }
+
+    /// The other leg of the transfer at the given position, if it is one.
+    fn other_leg(&self, account: usize, position: usize) -> Option<&Transaction> {
+        let account = &self.accounts[account];
+        if let TransactionMetadata::Transfer { ref id, ref from, ref to } = account.transactions[position].meta {
+            let other = if *from == account.name { to } else { from };
+            self.get_account(other)?.transactions.iter().find(|x| match x.meta {
+                TransactionMetadata::Transfer { id: ref leg_id, .. } => leg_id == id,
+                _ => false,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Last reconciliations of the transaction at the given position and of the other leg of its
+    /// transfer, if any, along with the name of their account.
+    pub fn reconciliations_of(&self, account: usize, position: usize) -> Vec<(&str, &Reconciliation)> {
+        let transaction = &self.accounts[account].transactions[position];
+        let mut legs = vec![(&self.accounts[account], transaction)];
+
+        if let (Some(leg), Ok((from, to))) = (self.other_leg(account, position), transfer_accounts(transaction)) {
+            let other = if from == self.accounts[account].name { to } else { from };
+            legs.extend(self.get_account(other).map(|x| (x, leg)));
+        }
+
+        legs.into_iter()
+            .filter_map(|(account, leg)| account.reconciliation_of(&leg.id).map(|x| (account.name.as_str(), x)))
+            .collect()
+    }
+
+    pub fn remove_transaction_at(&mut self, account: usize, position: usize) {
+        let account = &mut self.accounts[account];
+        let transaction = account.transactions.remove(position);
+
+        if let TransactionMetadata::Transfer { id, from, to } = transaction.meta {
+            let other = if from == account.name { to } else { from };
+            if let Some(other) = self.get_account_mut(&other) {
+                other.remove_transfer_leg(&id);
+            }
+        }
     }
 }
 
 #[allow(dead_code)]
 impl Account {
-    pub fn current_balance(&self) -> Currency {
-        let mut res = self.opening_balance.clone();
+    /// Fails if the total overflows, or if a transaction is not in the currency of the account.
+    pub fn current_balance(&self) -> Result<Money, Error> {
+        self.transactions
+            .iter()
+            .try_fold(self.opening_balance.clone(), |acc, t| {
+                acc.checked_add(&t.balance_change(&self.name)?)
+            })
+    }
 
-        for t in &self.transactions {
-            match t.meta {
-                TransactionMetadata::Income { .. } => {
-                    res = res + &t.amount;
-                }
-                TransactionMetadata::Expense { .. } => {
-                    res = res - &t.amount;
-                }
+    /// Balance of the account up to the given date, counting only the cleared transactions if asked.
+    pub fn balance_as_of(&self, date: DateTime<Utc>, cleared_only: bool) -> Result<Money, Error> {
+        self.transactions
+            .iter()
+            .filter(|t| t.date <= date && (t.cleared || !cleared_only))
+            .try_fold(self.opening_balance.clone(), |acc, t| {
+                acc.checked_add(&t.balance_change(&self.name)?)
+            })
+    }
+
+    pub fn sort_by_date(&mut self) {
+        self.transactions.sort_by(|x, y| x.date.cmp(&y.date));
+    }
+
+    /// The last reconciliation, if it covers the given transaction.
+    pub fn reconciliation_of(&self, id: &[u8]) -> Option<&Reconciliation> {
+        self.reconciliations
+            .last()
+            .filter(|r| r.transactions.iter().any(|x| x.id == id))
+    }
+
+    /// Describes every transaction that was changed or removed since the last reconciliation.
+    /// Each reconciliation covers every cleared transaction, so earlier ones are not checked.
+    pub fn reconciliation_changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if let Some(reconciliation) = self.reconciliations.last() {
+            for reconciled in &reconciliation.transactions {
+                let position = self.transactions.iter().position(|x| x.id == reconciled.id);
+                let change = match position {
+                    None => "was removed",
+                    Some(i) if !self.transactions[i].cleared => "is no longer cleared",
+                    Some(i) if self.transactions[i].fingerprint() != reconciled.fingerprint => "was changed",
+                    Some(_) => continue,
+                };
+
+                changes.push(format!(
+                    "Transaction {} {} after the reconciliation of {}",
+                    hex::encode(&reconciled.id),
+                    change,
+                    reconciliation.date.format(crate::DATE_FORMAT),
+                ));
             }
+        }
+
+        changes
+    }
+
+    fn remove_transfer_leg(&mut self, id: &TransferID) {
+        if let Some(position) = self.transactions.iter().position(|x| match &x.meta {
+            TransactionMetadata::Transfer { id: leg_id, .. } => leg_id == id,
+            _ => false,
+        }) {
+            self.transactions.remove(position);
+        }
+    }
+}
 
-            for f in &t.fees {
-                res = res - &f.amount;
+impl Transaction {
+    /// Amount by which this transaction changes the balance of the given account, fees included.
+    pub fn balance_change(&self, account: &str) -> Result<Money, Error> {
+        let mut res = match self.meta {
+            TransactionMetadata::Income { .. } => self.amount.clone(),
+            TransactionMetadata::Expense { .. } => -self.amount.clone(),
+            TransactionMetadata::Transfer { ref from, .. } => {
+                if from == account {
+                    -self.amount.clone()
+                } else {
+                    self.amount.clone()
+                }
             }
+        };
+
+        for f in &self.fees {
+            res = res.checked_sub(&f.amount)?;
         }
 
-        res
+        Ok(res)
     }
 
-    pub fn sort_by_date(&mut self) {
-        self.transactions.sort_by(|x, y| x.date.cmp(&y.date));
+    /// Hash of everything a reconciliation relies on, which is all but the cleared flag.
+    pub fn fingerprint(&self) -> Vec<u8> {
+        // Categories are not on statements.
+        let fees: Vec<Fee> = self
+            .fees
+            .iter()
+            .map(|x| Fee {
+                category: None,
+                ..x.clone()
+            })
+            .collect();
+        let fields = (&self.date, &self.description, &self.amount, &self.meta, &fees);
+        fingerprint(&serde_json::to_value(&fields).expect("Unreachable: transaction serialization"))
+    }
+}
+
+/// Source and destination accounts of a transfer.
+fn transfer_accounts(transaction: &Transaction) -> Result<(&str, &str), Error> {
+    match transaction.meta {
+        TransactionMetadata::Transfer {
+            ref from, ref to, ..
+        } => Ok((from, to)),
+        _ => Err(Error::NotATransfer),
     }
 }
 
-pub fn new(ledger_path: PathBuf, name: &str, opening_balance: Currency, opening_date: DateTime<Utc>) {
-    let mut ledger = Ledger::load(&ledger_path).expect("Could not open the ledger");
+/// Hashes the serialized fields of a transaction. Going through a `Value` sorts the keys of objects,
+/// so that migrations can fingerprint transactions they only have as JSON.
+pub fn fingerprint(fields: &serde_json::Value) -> Vec<u8> {
+    let mut hasher = crypto::sha2::Sha256::new();
+    hasher.input_str(&fields.to_string());
+
+    let mut hash = vec![0; 32];
+    hasher.result(&mut hash);
+    hash
+}
+
+/// A random-looking 128-bit ID, unique even for transactions created in the same instant.
+pub fn new_transaction_id() -> TransactionID {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut hasher = crypto::sha2::Sha256::new();
+    hasher.input_str("Transaction");
+    hasher.input_str(&Utc::now().timestamp_nanos().to_string());
+    hasher.input_str(&COUNTER.fetch_add(1, Ordering::Relaxed).to_string());
+
+    let mut hash = vec![0; 32];
+    hasher.result(&mut hash);
+    hash.truncate(16);
+    hash
+}
+
+/// Generates an identifier for a transfer that does not come from an external platform.
+pub fn new_transfer_id(from: &str, to: &str) -> TransferID {
+    let mut hasher = crypto::sha2::Sha256::new();
+    hasher.input_str("Transfer");
+    hasher.input_str(from);
+    hasher.input_str(to);
+    hasher.input_str(&Utc::now().timestamp_nanos().to_string());
+
+    let mut hash = vec![0; 32];
+    hasher.result(&mut hash);
+    hash
+}
+
+/// Creates an account, kept in the base currency of the ledger if none is given.
+pub fn new(
+    ledger_path: PathBuf,
+    name: &str,
+    currency: Option<&str>,
+    opening_balance: Option<&str>,
+    opening_date: DateTime<Utc>,
+) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+
+    let bad_argument = |argument: &str, value: &str| Error::BadArgument {
+        argument: argument.to_owned(),
+        value: value.to_owned(),
+    };
+    let currency = match currency {
+        Some(currency) => CurrencyCode::parse(currency).ok_or_else(|| bad_argument("currency", currency))?,
+        None => ledger.settings.base_currency,
+    };
+    let opening_balance = match opening_balance {
+        Some(balance) => Money::parse(balance, currency).ok_or_else(|| bad_argument("balance", balance))?,
+        None => Money::zero(currency),
+    };
+
+    ledger.new_account(name, opening_balance, opening_date)?;
+    ledger.save(&ledger_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+    use crate::ledger::rates::RateTable;
+    use crate::ledger::{ExpenseKind, Fee, ReconciledTransaction};
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// Stripe pays out to Chase, and Chase sends money to Savings.
+    fn transfers() -> Ledger {
+        let mut ledger = ledger(vec![
+            account("Stripe", Vec::new()),
+            account("Chase", Vec::new()),
+            account("Savings", Vec::new()),
+        ]);
+
+        let mut payout = transfer(1, "Stripe", "Chase", 1000, date(2019, 1, 2));
+        payout.fees.push(Fee {
+            towards: "Stripe".to_owned(),
+            amount: usd(30),
+            category: None,
+        });
+        ledger.push_transfer(payout).unwrap();
+        ledger.push_transfer(transfer(2, "Chase", "Savings", 500, date(2019, 1, 3))).unwrap();
+        ledger
+    }
+
+    fn transfer_ids(account: &Account) -> Vec<TransferID> {
+        account
+            .transactions
+            .iter()
+            .filter_map(|x| match &x.meta {
+                TransactionMetadata::Transfer { id, .. } => Some(id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pushes_a_leg_on_both_accounts() {
+        let ledger = transfers();
+
+        assert_eq!(transfer_ids(&ledger.accounts[0]), vec![vec![1]]);
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![1], vec![2]]);
+        assert_eq!(transfer_ids(&ledger.accounts[2]), vec![vec![2]]);
+
+        // Only the source leg carries the fees.
+        assert_eq!(ledger.accounts[0].transactions[0].fees.len(), 1);
+        assert!(ledger.accounts[1].transactions[0].fees.is_empty());
+        assert_eq!(ledger.accounts[0].current_balance().unwrap(), usd(-1030));
+        assert_eq!(ledger.accounts[1].current_balance().unwrap(), usd(500));
+        assert_eq!(ledger.accounts[2].current_balance().unwrap(), usd(500));
+    }
+
+    #[test]
+    fn removes_both_legs_from_either_leg() {
+        let mut ledger = transfers();
+        ledger.remove_transaction_at(0, 0);
+        assert!(transfer_ids(&ledger.accounts[0]).is_empty());
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![2]]);
+
+        let mut ledger = transfers();
+        ledger.remove_transaction_at(2, 0);
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![1]]);
+        assert!(transfer_ids(&ledger.accounts[2]).is_empty());
+    }
+
+    #[test]
+    fn removes_the_other_legs_of_a_removed_account() {
+        let mut ledger = transfers();
+        ledger.remove_account_at(1);
+
+        assert_eq!(ledger.accounts.len(), 2);
+        assert!(ledger.accounts.iter().all(|x| x.transactions.is_empty()));
+    }
+
+    #[test]
+    fn moves_both_legs_of_an_edited_transfer() {
+        let mut ledger = transfers();
+        let edited = transfer(2, "Chase", "Stripe", 700, date(2019, 1, 4));
+        ledger.replace_transaction_at(2, 0, edited).unwrap();
+
+        assert_eq!(transfer_ids(&ledger.accounts[0]), vec![vec![1], vec![2]]);
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![1], vec![2]]);
+        assert!(transfer_ids(&ledger.accounts[2]).is_empty());
+        assert_eq!(ledger.accounts[0].transactions[1].amount, usd(700));
+        assert_eq!(ledger.accounts[1].current_balance().unwrap(), usd(300));
+    }
+
+    #[test]
+    fn removes_the_other_leg_of_a_transfer_edited_into_an_expense() {
+        let mut ledger = transfers();
+        let expense = TransactionMetadata::Expense {
+            kind: ExpenseKind::General,
+            towards: "Landlord".to_owned(),
+            requester: String::new(),
+        };
+        ledger.replace_transaction_at(1, 1, transaction(500, date(2019, 1, 3), expense)).unwrap();
+
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![1]]);
+        assert!(ledger.accounts[2].transactions.is_empty());
+        assert_eq!(ledger.accounts[1].current_balance().unwrap(), usd(500));
+    }
+
+    #[test]
+    fn keeps_the_ledger_intact_when_an_edited_transfer_has_no_destination() {
+        let mut ledger = transfers();
+        let edited = transfer(2, "Chase", "Wells Fargo", 500, date(2019, 1, 3));
+
+        match ledger.replace_transaction_at(1, 1, edited) {
+            Err(Error::MissingAccount(name)) => assert_eq!(name, "Wells Fargo"),
+            _ => panic!("the missing destination should be reported"),
+        }
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![1], vec![2]]);
+        assert_eq!(transfer_ids(&ledger.accounts[2]), vec![vec![2]]);
+    }
 
-    ledger.new_account(name, opening_balance, opening_date);
+    #[test]
+    fn keeps_the_ids_of_both_legs_of_an_edited_transfer() {
+        let mut ledger = transfers();
+        let source = ledger.accounts[1].transactions[1].clone();
+        let destination = ledger.accounts[2].transactions[0].id.clone();
+
+        let mut edited = transfer(2, "Chase", "Stripe", 700, date(2019, 1, 4));
+        edited.id = source.id.clone();
+        ledger.replace_transaction_at(1, 1, edited).unwrap();
+
+        assert_eq!(ledger.accounts[1].transactions[1].id, source.id);
+        assert_eq!(ledger.accounts[0].transactions[1].id, destination);
+        assert_ne!(source.id, destination);
+    }
+
+    #[test]
+    fn converts_the_destination_leg_of_a_transfer_between_currencies() {
+        let path = env::temp_dir().join(format!("backertrack-{}-rates.csv", process::id()));
+        fs::write(&path, "date,from,to,rate\n2019-01-01,USD,EUR,0.9\n").unwrap();
+        let rates = RateTable::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let eur = CurrencyCode::parse("EUR").unwrap();
+        let mut euros = account("Euros", Vec::new());
+        euros.currency = eur;
+        let mut ledger = ledger(vec![account("Stripe", Vec::new()), euros]);
+        ledger.rates = rates.unwrap();
+        ledger.push_transfer(transfer(1, "Stripe", "Euros", 1000, date(2019, 1, 2))).unwrap();
+
+        assert_eq!(ledger.accounts[0].transactions[0].amount, usd(1000));
+        assert!(ledger.accounts[0].transactions[0].original.is_none());
+        let leg = &ledger.accounts[1].transactions[0];
+        assert_eq!(leg.amount, Money::from_minor(900, eur));
+        assert_eq!(leg.original, Some(usd(1000)));
+
+        // Without a rate, the transfer is not recorded at all.
+        let mut ledger = ledger;
+        ledger.rates = RateTable::default();
+        match ledger.push_transfer(transfer(2, "Stripe", "Euros", 500, date(2019, 1, 3))) {
+            Err(Error::MissingRate { .. }) => {}
+            _ => panic!("the transfer should need a rate"),
+        }
+        assert_eq!(ledger.accounts[0].transactions.len(), 1);
+        assert_eq!(ledger.accounts[1].transactions.len(), 1);
+    }
 
-    ledger
-        .save(&ledger_path)
-        .expect("Could not save the ledger");
+    #[test]
+    fn keeps_the_ledger_intact_when_an_edited_transfer_cannot_be_converted() {
+        let mut ledger = transfers();
+        let mut euros = account("Euros", Vec::new());
+        euros.currency = CurrencyCode::parse("EUR").unwrap();
+        ledger.accounts.push(euros);
+        let edited = transfer(2, "Chase", "Euros", 500, date(2019, 1, 3));
+
+        match ledger.replace_transaction_at(1, 1, edited) {
+            Err(Error::MissingRate { .. }) => {}
+            _ => panic!("the missing rate should be reported"),
+        }
+        assert_eq!(transfer_ids(&ledger.accounts[1]), vec![vec![1], vec![2]]);
+        assert_eq!(transfer_ids(&ledger.accounts[2]), vec![vec![2]]);
+        assert!(ledger.accounts[3].transactions.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_cleared_flag_of_a_moved_destination_leg() {
+        let mut ledger = transfers();
+        ledger.accounts[2].transactions[0].cleared = true;
+
+        let edited = transfer(2, "Chase", "Stripe", 700, date(2019, 1, 4));
+        ledger.replace_transaction_at(1, 1, edited).unwrap();
+
+        assert!(ledger.accounts[0].transactions[1].cleared);
+        assert!(!ledger.accounts[1].transactions[1].cleared);
+    }
+
+    #[test]
+    fn rejects_pushing_a_donation_as_a_transfer() {
+        let mut ledger = transfers();
+
+        match ledger.push_transfer(donation(3, "Ann", 1000, date(2019, 1, 4))) {
+            Err(Error::NotATransfer) => {}
+            _ => panic!("the donation should be rejected"),
+        }
+        assert_eq!(ledger.accounts[0].transactions.len(), 1);
+    }
+
+
+    #[test]
+    fn finds_the_reconciliations_of_both_legs_of_a_transfer() {
+        let mut ledger = transfers();
+        assert!(ledger.reconciliations_of(0, 0).is_empty());
+
+        let leg = &ledger.accounts[1].transactions[0];
+        let reconciled = ReconciledTransaction {
+            id: leg.id.clone(),
+            fingerprint: leg.fingerprint(),
+        };
+        ledger.accounts[1].reconciliations.push(Reconciliation {
+            date: date(2019, 1, 31),
+            balance: usd(1000),
+            made_on: date(2019, 2, 1),
+            transactions: vec![reconciled],
+        });
+
+        for (account, position) in &[(0, 0), (1, 0)] {
+            let reconciliations = ledger.reconciliations_of(*account, *position);
+            assert_eq!(reconciliations.len(), 1);
+            assert_eq!(reconciliations[0].0, "Chase");
+            assert_eq!(reconciliations[0].1.date, date(2019, 1, 31));
+        }
+        assert!(ledger.reconciliations_of(1, 1).is_empty());
+    }
 }
@@ -0,0 +1,123 @@
+use super::{Error, Ledger};
+use chrono::{offset::TimeZone, DateTime, Utc};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Down to the nanosecond, so that saving twice in a row does not overwrite the first backup.
+const TIMESTAMP_FORMAT: &'static str = "%Y%m%d-%H%M%S%.9f";
+/// Backups made by earlier versions, down to the second.
+const LEGACY_TIMESTAMP_FORMAT: &'static str = "%Y%m%d-%H%M%S";
+
+pub struct Backup {
+    pub path: PathBuf,
+    pub date: DateTime<Utc>,
+}
+
+pub fn temp_path(ledger: &Path) -> PathBuf {
+    let mut name = file_name(ledger);
+    name.push(".tmp");
+    ledger.with_file_name(name)
+}
+
+/// Copies the ledger file to a new timestamped backup, then deletes the oldest backups
+/// so that only `keep` of them remain.
+pub fn backup(ledger: &Path, keep: usize) -> Result<(), Error> {
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let mut name = file_name(ledger);
+    name.push(format!(".{}.bak", Utc::now().format(TIMESTAMP_FORMAT)));
+    fs::copy(ledger, ledger.with_file_name(name))?;
+
+    let backups = list(ledger)?;
+    if backups.len() > keep {
+        for old in &backups[keep..] {
+            fs::remove_file(&old.path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the backups of a ledger file, most recent first.
+pub fn list(ledger: &Path) -> Result<Vec<Backup>, Error> {
+    let prefix = format!("{}.", file_name(ledger).to_string_lossy());
+    let directory = match ledger.parent() {
+        Some(x) if x != Path::new("") => x.to_owned(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let file_name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        if file_name.starts_with(&prefix) && file_name.ends_with(".bak") {
+            let timestamp = file_name[prefix.len()..].trim_end_matches(".bak");
+            let date = Utc
+                .datetime_from_str(timestamp, TIMESTAMP_FORMAT)
+                .or_else(|_| Utc.datetime_from_str(timestamp, LEGACY_TIMESTAMP_FORMAT));
+            if let Ok(date) = date {
+                backups.push(Backup { path, date });
+            }
+        }
+    }
+
+    backups.sort_by(|x, y| y.date.cmp(&x.date));
+    Ok(backups)
+}
+
+/// Lists the backups of a ledger, or rolls it back to one of them.
+/// The backup can be designated by its number in the list or by its file name.
+pub fn restore(ledger_path: PathBuf, backup: Option<&str>) -> Result<(), Error> {
+    let backups = list(&ledger_path)?;
+
+    match backup {
+        None => {
+            if backups.len() == 0 {
+                println!("No backup found for `{}`.", ledger_path.display());
+            }
+
+            for (i, x) in backups.iter().enumerate() {
+                println!(
+                    "{}: {} ({})",
+                    i,
+                    x.date.format(crate::DATE_FORMAT),
+                    x.path.display()
+                );
+            }
+        }
+        Some(backup) => {
+            let path = backup
+                .parse::<usize>()
+                .ok()
+                .and_then(|x| backups.get(x))
+                .map(|x| x.path.clone())
+                .or_else(|| {
+                    backups
+                        .iter()
+                        .find(|x| x.path.file_name().map(|x| x == backup).unwrap_or(false))
+                        .map(|x| x.path.clone())
+                })
+                .ok_or_else(|| Error::MissingBackup(backup.to_owned()))?;
+
+            // Saving goes through the regular backup rotation, so the current ledger is kept.
+            Ledger::load(&path)?.save(&ledger_path)?;
+            println!("Restored `{}` from `{}`.", ledger_path.display(), path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn file_name(ledger: &Path) -> OsString {
+    ledger
+        .file_name()
+        .map(|x| x.to_owned())
+        .unwrap_or_else(|| OsString::from("ledger"))
+}
@@ -0,0 +1,1021 @@
+use super::accounts::new_transaction_id;
+use super::import::{parse_amount, parse_date, Import};
+use super::{
+    CurrencyCode, Error, ExpenseKind, Fee, IncomeKind, Ledger, Money, Transaction, TransactionMetadata,
+};
+use chrono::{DateTime, Duration, Utc};
+use crypto::digest::Digest;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub enum StatementFormat {
+    Unknown,
+    /// OFX, and Quicken's QFX which is the same format.
+    Ofx,
+    Qif,
+    /// ISO 20022 CAMT.053 bank to customer statements, as given by European banks.
+    Camt,
+}
+
+impl From<&str> for StatementFormat {
+    fn from(from: &str) -> Self {
+        match from.to_lowercase().as_ref() {
+            "ofx" | "qfx" => StatementFormat::Ofx,
+            "qif" => StatementFormat::Qif,
+            "camt" | "camt053" | "xml" => StatementFormat::Camt,
+            _ => StatementFormat::Unknown,
+        }
+    }
+}
+
+impl StatementFormat {
+    /// Guesses the format of a statement from its file extension.
+    pub fn of(path: &Path) -> Self {
+        path.extension()
+            .and_then(|x| x.to_str())
+            .map(StatementFormat::from)
+            .unwrap_or(StatementFormat::Unknown)
+    }
+}
+
+/// Days a statement line may be apart from the transfer it is the bank side of, such as a payout
+/// recorded on the day it was sent and landing on the account a few days later.
+const TRANSFER_MATCH_DAYS: i64 = 5;
+
+/// A line of a bank statement. Credits have a positive amount, debits a negative one.
+/// The amount is what the line moved on the account, bank charges included.
+struct StatementLine {
+    line: u64,
+    /// The bank's own ID for the line, or the fields that identify it if it has none.
+    id: String,
+    date: DateTime<Utc>,
+    raw_date: String,
+    amount: Money,
+    name: String,
+    memo: String,
+    fees: Vec<Fee>,
+}
+
+pub fn import(
+    ledger_path: PathBuf,
+    data: PathBuf,
+    account: &str,
+    format: StatementFormat,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let currency = ledger.account(account)?.currency;
+    let mut import = Import::new(&ledger, &[]);
+
+    let text = fs::read_to_string(&data)?;
+    let lines = match format {
+        StatementFormat::Ofx => read_ofx(&text, currency)?,
+        StatementFormat::Qif => read_qif(&text, currency)?,
+        StatementFormat::Camt => read_camt(&text, currency)?,
+        StatementFormat::Unknown => {
+            println!("Unknown statement format");
+            return Ok(());
+        }
+    };
+
+    let mut matched = 0;
+    for x in lines {
+        let mut hasher = crypto::sha2::Sha256::new();
+        hasher.input_str("Bank");
+        hasher.input_str(account);
+        hasher.input_str(&x.id);
+
+        let mut hash = vec![0; 32];
+        hasher.result(&mut hash);
+
+        // Payouts and transfers imported from the other account already have a leg on this one.
+        if !import.is_known(&hash) {
+            if let Some(leg) = transfer_leg(&mut ledger, account, &x)? {
+                leg.bank_id = Some(hash);
+                leg.cleared = true;
+                matched += 1;
+                continue;
+            }
+        }
+
+        let counterparty = if x.name != "" { x.name.clone() } else { x.memo.clone() };
+        let description = if x.memo != "" { x.memo.clone() } else { x.name.clone() };
+
+        let fees = Money::sum(currency, x.fees.iter().map(|fee| &fee.amount))?;
+
+        // The fees are taken on top of debits and out of credits.
+        let (amount, meta) = if x.amount.is_negative() {
+            (
+                (-x.amount).checked_sub(&fees)?,
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::General,
+                    towards: counterparty,
+                    requester: String::new(),
+                },
+            )
+        } else {
+            (
+                x.amount.checked_add(&fees)?,
+                TransactionMetadata::Income {
+                    kind: IncomeKind::General,
+                    from: counterparty,
+                },
+            )
+        };
+
+        let queued = import.push(
+            account,
+            Transaction {
+                id: new_transaction_id(),
+                bank_id: Some(hash),
+                original: None,
+                cleared: true,
+                category: None,
+                amount,
+                date: x.date,
+                meta,
+                description,
+                fees: x.fees,
+            },
+        )?;
+
+        if !queued {
+            println!(
+                "WARNING: Statement line `{}` on {} (line {}) is already in the ledger.",
+                x.name, x.raw_date, x.line
+            );
+        }
+    }
+
+    if matched > 0 {
+        println!("{}: {} statement line(s) matched to transfers already in the ledger", account, matched);
+    }
+    import.print_summary();
+    if dry_run {
+        println!("Dry run: the ledger was not modified.");
+        return Ok(());
+    }
+
+    import.commit(&mut ledger)?;
+    ledger.save(&ledger_path)
+}
+
+/// Finds the transfer leg a statement line is the bank side of: the closest in date of those moving
+/// the same amount on the account, and not matched to a statement line yet.
+fn transfer_leg<'a>(
+    ledger: &'a mut Ledger,
+    account: &str,
+    line: &StatementLine,
+) -> Result<Option<&'a mut Transaction>, Error> {
+    let transactions = &mut ledger.account_mut(account)?.transactions;
+
+    let mut closest: Option<(usize, Duration)> = None;
+    for (i, x) in transactions.iter().enumerate() {
+        let is_transfer = match x.meta {
+            TransactionMetadata::Transfer { .. } => true,
+            _ => false,
+        };
+        if !is_transfer || x.bank_id.is_some() || x.balance_change(account)? != line.amount {
+            continue;
+        }
+
+        let distance = if x.date > line.date { x.date - line.date } else { line.date - x.date };
+        if distance <= Duration::days(TRANSFER_MATCH_DAYS) && closest.map(|x| distance < x.1).unwrap_or(true) {
+            closest = Some((i, distance));
+        }
+    }
+
+    Ok(closest.map(move |(i, _)| &mut transactions[i]))
+}
+
+/// Reads the closing balance of a statement in the currency of its account, and the date it was taken at.
+pub fn closing_balance(
+    data: &Path,
+    format: StatementFormat,
+    currency: CurrencyCode,
+) -> Result<(DateTime<Utc>, Money), Error> {
+    let text = fs::read_to_string(data)?;
+    match format {
+        StatementFormat::Ofx => ofx_balance(&text, currency),
+        StatementFormat::Camt => camt_balance(&text, currency),
+        StatementFormat::Qif => Err(Error::BadStatement {
+            line: 1,
+            message: "QIF statements have no closing balance".to_owned(),
+        }),
+        StatementFormat::Unknown => Err(Error::BadStatement {
+            line: 1,
+            message: "unknown statement format".to_owned(),
+        }),
+    }
+}
+
+/// Reads the `LEDGERBAL` aggregate of an OFX statement.
+fn ofx_balance(text: &str, currency: CurrencyCode) -> Result<(DateTime<Utc>, Money), Error> {
+    let start = text.find("<LEDGERBAL>").ok_or_else(|| Error::BadStatement {
+        line: 1,
+        message: "missing LEDGERBAL".to_owned(),
+    })?;
+    let line = text[..start].matches('\n').count() as u64 + 1;
+    let block = &text[start..];
+    let block = &block[..block.find("</LEDGERBAL>").unwrap_or(block.len())];
+
+    let raw_amount = ofx_element(block, "BALAMT").ok_or_else(|| Error::BadStatement {
+        line,
+        message: "missing BALAMT".to_owned(),
+    })?;
+    let raw_date = ofx_element(block, "DTASOF").ok_or_else(|| Error::BadStatement {
+        line,
+        message: "missing DTASOF".to_owned(),
+    })?;
+
+    // A balance without a time is taken at the end of the day.
+    let date = if raw_date.chars().take_while(|x| x.is_ascii_digit()).count() == 8 {
+        parse_ofx_date(&format!("{}2359", &raw_date[..8]), line, "DTASOF")?
+    } else {
+        parse_ofx_date(&raw_date, line, "DTASOF")?
+    };
+
+    Ok((date, parse_amount(&raw_amount, currency, line, "BALAMT")?))
+}
+
+/// Reads the closing booked balance, `CLBD`, of a CAMT.053 statement.
+fn camt_balance(text: &str, currency: CurrencyCode) -> Result<(DateTime<Utc>, Money), Error> {
+    for (offset, balance) in xml_elements(text, "Bal") {
+        let line = text[..offset].matches('\n').count() as u64 + 1;
+        let kind = xml_element(balance, "Tp").and_then(|x| xml_text(x, "Cd"));
+        if kind.as_deref() != Some("CLBD") {
+            continue;
+        }
+
+        let mut amount = camt_amount(balance, currency, line, "Amt")?.1;
+        if xml_text(balance, "CdtDbtInd").as_deref() == Some("DBIT") {
+            amount = -amount;
+        }
+
+        // The date is either `<Dt><DtTm>...</DtTm></Dt>` or `<Dt><Dt>...</Dt></Dt>`, where the
+        // inner element ends the outer one as far as `xml_element` is concerned.
+        let raw_date = xml_text(balance, "DtTm")
+            .or_else(|| xml_element(balance, "Dt").and_then(|x| x.rsplit('>').next()).map(|x| unescape(x.trim())))
+            .filter(|x| x != "")
+            .ok_or_else(|| Error::BadStatement {
+                line,
+                message: "missing Dt".to_owned(),
+            })?;
+        // Balances are taken at the end of the day.
+        let date = parse_date(
+            &format!("{} 23:59", raw_date.get(..10).unwrap_or(&raw_date)),
+            "%Y-%m-%d %H:%M",
+            line,
+            "Dt",
+        )?;
+
+        return Ok((date, amount));
+    }
+
+    Err(Error::BadStatement {
+        line: 1,
+        message: "missing closing balance".to_owned(),
+    })
+}
+
+/// Reads the `STMTTRN` aggregates of an OFX statement. Works with both the SGML flavour of OFX 1,
+/// where elements are not closed, and the XML flavour of OFX 2.
+fn read_ofx(text: &str, currency: CurrencyCode) -> Result<Vec<StatementLine>, Error> {
+    let mut lines = Vec::new();
+
+    let mut rest = text;
+    while let Some(start) = rest.find("<STMTTRN>") {
+        let block_start = text.len() - rest.len() + start;
+        let block = &rest[start..];
+        let end = block.find("</STMTTRN>").unwrap_or(block.len());
+        let block = &block[..end];
+        rest = &rest[start + end..];
+
+        let line = text[..block_start].matches('\n').count() as u64 + 1;
+        let element = |name: &str| ofx_element(block, name);
+
+        let raw_date = element("DTPOSTED").ok_or_else(|| Error::BadStatement {
+            line,
+            message: "missing DTPOSTED".to_owned(),
+        })?;
+        let raw_amount = element("TRNAMT").ok_or_else(|| Error::BadStatement {
+            line,
+            message: "missing TRNAMT".to_owned(),
+        })?;
+
+        let date = parse_ofx_date(&raw_date, line, "DTPOSTED")?;
+        let amount = parse_amount(&raw_amount, currency, line, "TRNAMT")?;
+
+        let name = element("NAME")
+            .or_else(|| element("PAYEE"))
+            .unwrap_or_default();
+        let memo = element("MEMO").unwrap_or_default();
+        let id = match element("FITID") {
+            Some(x) => x,
+            None => format!("{}|{}|{}|{}", raw_date, raw_amount, name, memo),
+        };
+
+        lines.push(StatementLine {
+            line,
+            id,
+            date,
+            raw_date,
+            amount,
+            name,
+            memo,
+            fees: Vec::new(),
+        });
+    }
+
+    Ok(lines)
+}
+
+/// Dates look like 20190131, 20190131120000 or 20190131120000.000[-5:EST]. Times are in GMT
+/// unless followed by the offset of their time zone, in hours. Dates without a time are left as is.
+fn parse_ofx_date(value: &str, line: u64, column: &'static str) -> Result<DateTime<Utc>, Error> {
+    let digits: String = value.chars().take_while(|x| x.is_ascii_digit()).collect();
+    if digits.len() < 12 {
+        return parse_date(&format!("{}0000", digits), "%Y%m%d%H%M", line, column);
+    }
+
+    let date = parse_date(&digits[..12], "%Y%m%d%H%M", line, column)?;
+    let zone = match value.find('[') {
+        Some(start) => &value[start + 1..],
+        None => return Ok(date),
+    };
+    let hours = zone[..zone.find(|x| x == ':' || x == ']').unwrap_or(zone.len())].trim();
+    let hours: f64 = hours.parse().map_err(|_| Error::BadDate {
+        line: Some(line),
+        column,
+        value: value.to_owned(),
+    })?;
+
+    Ok(date - Duration::minutes((hours * 60.0).round() as i64))
+}
+
+/// Value of an element of an OFX aggregate, up to the next tag or line break.
+fn ofx_element(block: &str, name: &str) -> Option<String> {
+    let tag = format!("<{}>", name);
+    let start = block.find(&tag)? + tag.len();
+    let value = &block[start..];
+    let end = value.find(|x| x == '<' || x == '\n' || x == '\r').unwrap_or(value.len());
+    Some(unescape(value[..end].trim()))
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads the records of a QIF bank statement. QIF has no line IDs, so identical records on the
+/// same statement are told apart by how many came before them.
+fn read_qif(text: &str, currency: CurrencyCode) -> Result<Vec<StatementLine>, Error> {
+    let mut lines = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+
+    let mut start = 0;
+    let mut date = None;
+    let mut amount = None;
+    let mut name = String::new();
+    let mut memo = String::new();
+    let mut number = String::new();
+
+    for (i, row) in text.lines().enumerate() {
+        let line = i as u64 + 1;
+        let row = row.trim_end();
+        if row.starts_with('!') || row == "" {
+            continue;
+        }
+        if start == 0 {
+            start = line;
+        }
+
+        let mut chars = row.chars();
+        let code = chars.next();
+        let value = chars.as_str().trim();
+        match code {
+            Some('D') => date = Some((value.to_owned(), parse_qif_date(value, line)?)),
+            Some('T') | Some('U') => {
+                amount = Some((value.to_owned(), parse_amount(value, currency, line, "T")?))
+            }
+            Some('P') => name = value.to_owned(),
+            Some('M') => memo = value.to_owned(),
+            Some('N') => number = value.to_owned(),
+            Some('^') => {
+                let (raw_date, date) = date.take().ok_or_else(|| Error::BadStatement {
+                    line: start,
+                    message: "record without a date".to_owned(),
+                })?;
+                let (raw_amount, amount) = amount.take().ok_or_else(|| Error::BadStatement {
+                    line: start,
+                    message: "record without an amount".to_owned(),
+                })?;
+
+                let fields = format!("{}|{}|{}|{}|{}", raw_date, raw_amount, number, name, memo);
+                let id = format!("{}|{}", fields, seen.iter().filter(|x| **x == fields).count());
+                seen.push(fields);
+
+                lines.push(StatementLine {
+                    line: start,
+                    id,
+                    date,
+                    raw_date,
+                    amount,
+                    name: name.split_off(0),
+                    memo: memo.split_off(0),
+                    fees: Vec::new(),
+                });
+                number.clear();
+                start = 0;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(lines)
+}
+
+/// QIF dates are US style, with either a slash or an apostrophe before the year:
+/// 1/31/2019, 01/31/19 or 1/31'19.
+fn parse_qif_date(value: &str, line: u64) -> Result<DateTime<Utc>, Error> {
+    let normalized = value.replace('\'', "/").replace('-', "/").replace(' ', "");
+    let parts: Vec<&str> = normalized.split('/').collect();
+    let bad_date = || Error::BadDate {
+        line: Some(line),
+        column: "D",
+        value: value.to_owned(),
+    };
+
+    if parts.len() != 3 {
+        return Err(bad_date());
+    }
+
+    let year: i32 = parts[2].parse().map_err(|_| bad_date())?;
+    let year = if parts[2].len() <= 2 {
+        if year < 70 {
+            2000 + year
+        } else {
+            1900 + year
+        }
+    } else {
+        year
+    };
+
+    parse_date(
+        &format!("{}/{}/{} 00:00", year, parts[0], parts[1]),
+        "%Y/%m/%d %H:%M",
+        line,
+        "D",
+    )
+}
+
+/// Reads the booked entries of a CAMT.053 statement.
+fn read_camt(text: &str, currency: CurrencyCode) -> Result<Vec<StatementLine>, Error> {
+    let mut lines = Vec::new();
+
+    for (offset, entry) in xml_elements(text, "Ntry") {
+        let line = text[..offset].matches('\n').count() as u64 + 1;
+
+        let status = xml_element(entry, "Sts")
+            .map(|x| xml_element(x, "Cd").unwrap_or(x).trim())
+            .unwrap_or("BOOK");
+        if status != "BOOK" {
+            println!("WARNING: Skipped the {} entry on line {}, it is not booked yet.", status, line);
+            continue;
+        }
+
+        let (raw_amount, mut amount) = camt_amount(entry, currency, line, "Amt")?;
+        let credit = match xml_text(entry, "CdtDbtInd").as_deref() {
+            Some("CRDT") => true,
+            Some("DBIT") => false,
+            x => {
+                return Err(Error::BadStatement {
+                    line,
+                    message: format!("invalid CdtDbtInd `{}`", x.unwrap_or_default()),
+                })
+            }
+        };
+        if !credit {
+            amount = -amount;
+        }
+
+        let raw_date = xml_element(entry, "BookgDt")
+            .or_else(|| xml_element(entry, "ValDt"))
+            .and_then(|x| xml_text(x, "DtTm").or_else(|| xml_text(x, "Dt")))
+            .ok_or_else(|| Error::BadStatement {
+                line,
+                message: "missing BookgDt".to_owned(),
+            })?;
+        let date = parse_date(
+            &format!("{} 00:00", raw_date.get(..10).unwrap_or(&raw_date)),
+            "%Y-%m-%d %H:%M",
+            line,
+            "BookgDt",
+        )?;
+
+        // The debtor pays us on credits, and we pay the creditor on debits.
+        let parties = xml_element(entry, "RltdPties").unwrap_or_default();
+        let party = |role: &str| xml_element(parties, role).and_then(|x| xml_text(x, "Nm"));
+        let name = if credit {
+            party("Dbtr").or_else(|| party("Cdtr"))
+        } else {
+            party("Cdtr").or_else(|| party("Dbtr"))
+        }
+        .unwrap_or_default();
+
+        let remittance: Vec<String> = xml_elements(entry, "Ustrd")
+            .into_iter()
+            .map(|(_, x)| unescape(x.trim()))
+            .collect();
+        let memo = if !remittance.is_empty() {
+            remittance.join(" ")
+        } else {
+            xml_text(entry, "AddtlNtryInf").unwrap_or_default()
+        };
+
+        // Charges booked as their own entry are not part of this one.
+        let mut fees = Vec::new();
+        if let Some(charges) = xml_element(entry, "Chrgs") {
+            for (_, record) in xml_elements(charges, "Rcrd") {
+                if xml_text(record, "ChrgInclInd").map(|x| x == "false").unwrap_or(false) {
+                    continue;
+                }
+                if xml_element(record, "Amt").is_some() {
+                    fees.push(Fee {
+                        amount: camt_amount(record, currency, line, "Chrgs")?.1,
+                        towards: "Bank charges".to_owned(),
+                        category: None,
+                    });
+                }
+            }
+        }
+
+        let id = xml_text(entry, "NtryRef")
+            .or_else(|| xml_text(entry, "AcctSvcrRef"))
+            .or_else(|| xml_text(entry, "EndToEndId").filter(|x| x != "NOTPROVIDED"))
+            .unwrap_or_else(|| format!("{}|{}|{}|{}|{}", raw_date, raw_amount, credit, name, memo));
+
+        lines.push(StatementLine {
+            line,
+            id,
+            date,
+            raw_date,
+            amount,
+            name,
+            memo,
+            fees,
+        });
+    }
+
+    Ok(lines)
+}
+
+/// Reads the first `Amt` element, whose `Ccy` attribute must be the currency of the account.
+/// Returns the amount as written along with the parsed one.
+fn camt_amount(
+    text: &str,
+    currency: CurrencyCode,
+    line: u64,
+    column: &'static str,
+) -> Result<(String, Money), Error> {
+    let raw_amount = xml_text(text, "Amt").ok_or_else(|| Error::BadStatement {
+        line,
+        message: "missing Amt".to_owned(),
+    })?;
+
+    if let Some(code) = xml_attribute(text, "Amt", "Ccy") {
+        if CurrencyCode::parse(&code) != Some(currency) {
+            return Err(Error::BadStatement {
+                line,
+                message: format!("{} amount `{}` in a {} account", code, raw_amount, currency),
+            });
+        }
+    }
+
+    let amount = parse_amount(&raw_amount, currency, line, column)?;
+    Ok((raw_amount, amount))
+}
+
+/// Contents and offsets of the XML elements with the given name, without their namespace prefix.
+/// Nested elements with the same name are not supported, CAMT.053 only nests dates of balances.
+fn xml_elements<'a>(text: &'a str, name: &str) -> Vec<(usize, &'a str)> {
+    let mut elements = Vec::new();
+
+    let mut position = 0;
+    while let Some((start, content)) = find_start_tag(&text[position..], name) {
+        let content = position + content;
+        let end = match find_end_tag(&text[content..], name) {
+            Some(end) => content + end,
+            None => break,
+        };
+        elements.push((position + start, &text[content..end]));
+        position = end;
+    }
+
+    elements
+}
+
+fn xml_element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    xml_elements(text, name).into_iter().next().map(|x| x.1)
+}
+
+/// Unescaped text of the first element with the given name.
+fn xml_text(text: &str, name: &str) -> Option<String> {
+    xml_element(text, name).map(|x| unescape(x.trim()))
+}
+
+/// Unescaped value of an attribute of the first element with the given name.
+fn xml_attribute(text: &str, name: &str, attribute: &str) -> Option<String> {
+    let (start, content) = find_start_tag(text, name)?;
+    let tag = &text[start + 1..content - 1];
+
+    let mut rest = tag;
+    while let Some(position) = rest.find(attribute) {
+        let before = rest[..position].chars().last();
+        rest = &rest[position + attribute.len()..];
+        let value = rest.trim_start();
+        if !before.map_or(false, char::is_whitespace) || !value.starts_with('=') {
+            continue;
+        }
+
+        let value = value[1..].trim_start();
+        let quote = value.chars().next().filter(|x| *x == '"' || *x == '\'')?;
+        let end = value[1..].find(quote)?;
+        return Some(unescape(&value[1..end + 1]));
+    }
+
+    None
+}
+
+/// Finds `<name>`, `<name attr="...">` or `<prefix:name ...>`.
+/// Returns the offsets of the tag and of the element's contents.
+fn find_start_tag(text: &str, name: &str) -> Option<(usize, usize)> {
+    let mut position = 0;
+    while let Some(start) = text[position..].find('<') {
+        let start = position + start;
+        let end = start + text[start..].find('>')?;
+        let tag = text[start + 1..end].split_whitespace().next().unwrap_or_default();
+        let local = tag.rsplit(':').next().unwrap_or_default();
+
+        if local == name && !tag.starts_with('/') {
+            return Some((start, end + 1));
+        }
+        position = end + 1;
+    }
+
+    None
+}
+
+fn find_end_tag(text: &str, name: &str) -> Option<usize> {
+    let mut position = 0;
+    while let Some(start) = text[position..].find("</") {
+        let start = position + start;
+        let end = start + text[start..].find('>')?;
+        let local = text[start + 2..end].trim().rsplit(':').next().unwrap_or_default();
+
+        if local == name {
+            return Some(start);
+        }
+        position = end + 1;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+    use chrono::TimeZone;
+
+    const OFX_SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+
+<OFX>
+<BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>FEE
+<DTPOSTED>20190131120000.000[-5:EST]
+<TRNAMT>-15.00
+<FITID>2019013101
+<NAME>MONTHLY SERVICE FEE
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>INT
+<DTPOSTED>20190131
+<TRNAMT>0.42
+<NAME>INTEREST PAYMENT
+<MEMO>Interest &amp; dividends
+</STMTTRN>
+</BANKTRANLIST>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>
+";
+
+    #[test]
+    fn reads_ofx_1_statements() {
+        let lines = read_ofx(OFX_SGML, CurrencyCode::USD).unwrap();
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(lines[0].line, 6);
+        assert_eq!(lines[0].id, "2019013101");
+        assert_eq!(lines[0].date, Utc.ymd(2019, 1, 31).and_hms(17, 0, 0));
+        assert_eq!(lines[0].amount, usd(-1500));
+        assert_eq!(lines[0].name, "MONTHLY SERVICE FEE");
+        assert_eq!(lines[0].memo, "");
+
+        // Lines without a FITID are identified by their fields.
+        assert_eq!(lines[1].id, "20190131|0.42|INTEREST PAYMENT|Interest & dividends");
+        assert_eq!(lines[1].date, date(2019, 1, 31));
+        assert_eq!(lines[1].amount, usd(42));
+        assert_eq!(lines[1].memo, "Interest & dividends");
+    }
+
+    #[test]
+    fn reads_ofx_2_statements() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN><TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20190205</DTPOSTED><TRNAMT>1250.00</TRNAMT><FITID>A1</FITID><PAYEE>STRIPE</PAYEE></STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>"#;
+        let lines = read_ofx(text, CurrencyCode::USD).unwrap();
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].line, 3);
+        assert_eq!(lines[0].id, "A1");
+        assert_eq!(lines[0].amount, usd(125_000));
+        assert_eq!(lines[0].name, "STRIPE");
+    }
+
+    #[test]
+    fn rejects_incomplete_ofx_lines() {
+        let text = OFX_SGML.replace("<TRNAMT>0.42\n", "");
+        match read_ofx(&text, CurrencyCode::USD) {
+            Err(Error::BadStatement { line: 13, message }) => assert_eq!(message, "missing TRNAMT"),
+            _ => panic!("the line without an amount should be rejected"),
+        }
+    }
+
+    #[test]
+    fn applies_ofx_time_zone_offsets() {
+        let date = |value| parse_ofx_date(value, 1, "DTPOSTED").unwrap();
+
+        assert_eq!(date("20190131"), Utc.ymd(2019, 1, 31).and_hms(0, 0, 0));
+        assert_eq!(date("201901311230"), Utc.ymd(2019, 1, 31).and_hms(12, 30, 0));
+        assert_eq!(date("20190131120000[0:GMT]"), Utc.ymd(2019, 1, 31).and_hms(12, 0, 0));
+        assert_eq!(date("20190131220000.000[-5:EST]"), Utc.ymd(2019, 2, 1).and_hms(3, 0, 0));
+        assert_eq!(date("20190201013000[+2]"), Utc.ymd(2019, 1, 31).and_hms(23, 30, 0));
+        assert_eq!(date("20190131120000[+5.5:IST]"), Utc.ymd(2019, 1, 31).and_hms(6, 30, 0));
+        // Offsets are for times, dates alone stay on their day.
+        assert_eq!(date("20190131[-5:EST]"), Utc.ymd(2019, 1, 31).and_hms(0, 0, 0));
+
+        match parse_ofx_date("20190131120000[EST]", 4, "DTPOSTED") {
+            Err(Error::BadDate { line: Some(4), .. }) => {}
+            _ => panic!("a time zone without an offset should be rejected"),
+        }
+    }
+
+    #[test]
+    fn reads_qif_statements() {
+        let text = "!Type:Bank
+D1/31/2019
+T-15.00
+PMONTHLY SERVICE FEE
+^
+D02/01'19
+T1,250.00
+N1001
+PSTRIPE
+MPayout
+^
+D2/1/2019
+T-3.00
+^
+D2/1/2019
+T-3.00
+^
+";
+        let lines = read_qif(text, CurrencyCode::USD).unwrap();
+        assert_eq!(lines.len(), 4);
+
+        assert_eq!(lines[0].line, 2);
+        assert_eq!(lines[0].date, date(2019, 1, 31));
+        assert_eq!(lines[0].amount, usd(-1500));
+        assert_eq!(lines[0].name, "MONTHLY SERVICE FEE");
+
+        assert_eq!(lines[1].line, 6);
+        assert_eq!(lines[1].date, date(2019, 2, 1));
+        assert_eq!(lines[1].amount, usd(125_000));
+        assert_eq!((lines[1].name.as_str(), lines[1].memo.as_str()), ("STRIPE", "Payout"));
+
+        // Identical records are told apart by their order.
+        assert_eq!(lines[2].name, "");
+        assert_ne!(lines[2].id, lines[3].id);
+        assert_eq!(read_qif(text, CurrencyCode::USD).unwrap()[3].id, lines[3].id);
+    }
+
+    #[test]
+    fn reads_qif_dates() {
+        let parse = |value| parse_qif_date(value, 1).unwrap();
+
+        assert_eq!(parse("1/31/2019"), date(2019, 1, 31));
+        assert_eq!(parse("01/31/19"), date(2019, 1, 31));
+        assert_eq!(parse("1/31'19"), date(2019, 1, 31));
+        assert_eq!(parse("1/31' 5"), date(2005, 1, 31));
+        assert_eq!(parse("12-31-99"), date(1999, 12, 31));
+        assert!(parse_qif_date("31/01/2019", 1).is_err());
+        assert!(parse_qif_date("2019-01", 1).is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_qif_records() {
+        match read_qif("!Type:Bank\nD1/31/2019\nPFEE\n^\n", CurrencyCode::USD) {
+            Err(Error::BadStatement { line: 2, message }) => assert_eq!(message, "record without an amount"),
+            _ => panic!("the record without an amount should be rejected"),
+        }
+    }
+
+    const CAMT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+<BkToCstmrStmt><Stmt>
+<Ntry>
+  <NtryRef>E1</NtryRef>
+  <Amt Ccy="USD">1250.00</Amt>
+  <CdtDbtInd>CRDT</CdtDbtInd>
+  <Sts>BOOK</Sts>
+  <BookgDt><Dt>2019-02-01</Dt></BookgDt>
+  <NtryDtls><TxDtls>
+    <RltdPties><Dbtr><Nm>Stripe &amp; Co</Nm></Dbtr><Cdtr><Nm>Us</Nm></Cdtr></RltdPties>
+    <RmtInf><Ustrd>Payout</Ustrd><Ustrd>February</Ustrd></RmtInf>
+  </TxDtls></NtryDtls>
+</Ntry>
+<Ntry>
+  <Amt Ccy="USD">15.00</Amt>
+  <CdtDbtInd>DBIT</CdtDbtInd>
+  <Sts><Cd>BOOK</Cd></Sts>
+  <BookgDt><DtTm>2019-02-03T10:15:00</DtTm></BookgDt>
+  <AcctSvcrRef>S2</AcctSvcrRef>
+  <Chrgs>
+    <Rcrd><Amt Ccy="USD">1.00</Amt><ChrgInclInd>true</ChrgInclInd></Rcrd>
+    <Rcrd><Amt Ccy="USD">2.00</Amt><ChrgInclInd>false</ChrgInclInd></Rcrd>
+  </Chrgs>
+  <NtryDtls><TxDtls>
+    <RltdPties><Dbtr><Nm>Us</Nm></Dbtr><Cdtr><Nm>Hosting</Nm></Cdtr></RltdPties>
+  </TxDtls></NtryDtls>
+  <AddtlNtryInf>Invoice 42</AddtlNtryInf>
+</Ntry>
+<Ntry>
+  <Amt Ccy="USD">99.00</Amt>
+  <CdtDbtInd>CRDT</CdtDbtInd>
+  <Sts>PDNG</Sts>
+  <BookgDt><Dt>2019-02-04</Dt></BookgDt>
+</Ntry>
+</Stmt></BkToCstmrStmt>
+</Document>"#;
+
+    #[test]
+    fn reads_booked_camt_entries() {
+        let lines = read_camt(CAMT, CurrencyCode::USD).unwrap();
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(lines[0].line, 4);
+        assert_eq!(lines[0].id, "E1");
+        assert_eq!(lines[0].date, date(2019, 2, 1));
+        assert_eq!(lines[0].amount, usd(125_000));
+        assert_eq!(lines[0].name, "Stripe & Co");
+        assert_eq!(lines[0].memo, "Payout February");
+        assert!(lines[0].fees.is_empty());
+
+        assert_eq!(lines[1].id, "S2");
+        assert_eq!(lines[1].date, date(2019, 2, 3));
+        assert_eq!(lines[1].raw_date, "2019-02-03T10:15:00");
+        assert_eq!(lines[1].amount, usd(-1500));
+        assert_eq!(lines[1].name, "Hosting");
+        assert_eq!(lines[1].memo, "Invoice 42");
+        // Charges booked as their own entry are left out.
+        assert_eq!(lines[1].fees.len(), 1);
+        assert_eq!(lines[1].fees[0].amount, usd(100));
+    }
+
+    #[test]
+    fn reads_prefixed_camt_elements() {
+        let text = CAMT
+            .replace("<Document xmlns=", "<c:Document xmlns:c=")
+            .replace("</Document>", "</c:Document>")
+            .replace("<Ntry>", "<c:Ntry>")
+            .replace("</Ntry>", "</c:Ntry>")
+            .replace("<Amt Ccy", "<c:Amt Ccy")
+            .replace("</Amt>", "</c:Amt>");
+        let lines = read_camt(&text, CurrencyCode::USD).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].amount, usd(125_000));
+        assert_eq!(lines[1].fees[0].amount, usd(100));
+    }
+
+    #[test]
+    fn identifies_camt_entries_without_references() {
+        let text = CAMT.replace("<NtryRef>E1</NtryRef>", "");
+        let lines = read_camt(&text, CurrencyCode::USD).unwrap();
+        assert_eq!(lines[0].id, "2019-02-01|1250.00|true|Stripe & Co|Payout February");
+    }
+
+    #[test]
+    fn rejects_invalid_camt_entries() {
+        match read_camt(&CAMT.replace("DBIT", "DEBIT"), CurrencyCode::USD) {
+            Err(Error::BadStatement { line: 15, message }) => assert_eq!(message, "invalid CdtDbtInd `DEBIT`"),
+            _ => panic!("the credit or debit indicator should be rejected"),
+        }
+        match read_camt(&CAMT.replace("<BookgDt><Dt>2019-02-01</Dt></BookgDt>", ""), CurrencyCode::USD) {
+            Err(Error::BadStatement { line: 4, message }) => assert_eq!(message, "missing BookgDt"),
+            _ => panic!("the entry without a date should be rejected"),
+        }
+        match read_camt(&CAMT.replace("2019-02-01", "2019-02-1é"), CurrencyCode::USD) {
+            Err(Error::BadDate { line: Some(4), .. }) => {}
+            _ => panic!("the malformed date should be rejected"),
+        }
+    }
+
+    #[test]
+    fn rejects_camt_amounts_in_another_currency() {
+        match read_camt(&CAMT.replace(r#"Ccy="USD">1250.00"#, r#"Ccy="EUR">1250.00"#), CurrencyCode::USD) {
+            Err(Error::BadStatement { line: 4, message }) => {
+                assert_eq!(message, "EUR amount `1250.00` in a USD account")
+            }
+            _ => panic!("the amount in euros should be rejected"),
+        }
+        match read_camt(&CAMT.replace(r#"Ccy="USD">1.00"#, r#"Ccy='EUR'>1.00"#), CurrencyCode::USD) {
+            Err(Error::BadStatement { line: 15, message }) => assert_eq!(message, "EUR amount `1.00` in a USD account"),
+            _ => panic!("the charge in euros should be rejected"),
+        }
+
+        // Charges booked as their own entry are not read.
+        assert!(read_camt(&CAMT.replace(r#"Ccy="USD">2.00"#, r#"Ccy="EUR">2.00"#), CurrencyCode::USD).is_ok());
+    }
+
+    fn statement_line(amount: i64, day: u32) -> StatementLine {
+        StatementLine {
+            line: 1,
+            id: String::new(),
+            date: date(2019, 2, day),
+            raw_date: String::new(),
+            amount: usd(amount),
+            name: String::new(),
+            memo: String::new(),
+            fees: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn matches_statement_lines_to_the_closest_transfer_leg() {
+        let mut ledger = ledger(vec![account(
+            "Chase",
+            vec![
+                transfer(1, "Stripe", "Chase", 1000, date(2019, 2, 1)),
+                transfer(2, "Stripe", "Chase", 1000, date(2019, 2, 10)),
+                transfer(3, "Chase", "Savings", 1000, date(2019, 2, 10)),
+                transfer(4, "Stripe", "Chase", 2000, date(2019, 2, 20)),
+            ],
+        )]);
+        let id = |leg: Option<&mut Transaction>| {
+            leg.map(|x| match x.meta {
+                TransactionMetadata::Transfer { ref id, .. } => id.clone(),
+                _ => unreachable!(),
+            })
+        };
+
+        assert_eq!(id(transfer_leg(&mut ledger, "Chase", &statement_line(1000, 12)).unwrap()), Some(vec![2]));
+        assert_eq!(id(transfer_leg(&mut ledger, "Chase", &statement_line(-1000, 12)).unwrap()), Some(vec![3]));
+        assert_eq!(id(transfer_leg(&mut ledger, "Chase", &statement_line(1000, 3)).unwrap()), Some(vec![1]));
+        // Too far from any leg of the same amount.
+        assert_eq!(id(transfer_leg(&mut ledger, "Chase", &statement_line(2000, 28)).unwrap()), None);
+
+        // Legs already matched to a statement line are left alone.
+        ledger.accounts[0].transactions[1].bank_id = Some(vec![9]);
+        assert_eq!(id(transfer_leg(&mut ledger, "Chase", &statement_line(1000, 12)).unwrap()), None);
+    }
+
+
+    #[test]
+    fn reads_the_closing_balance_of_camt_statements() {
+        let balance = |date: &str| {
+            format!(
+                "<Stmt>\n<Bal><Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp><Amt Ccy=\"USD\">5.00</Amt></Bal>\n\
+                 <Bal><Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp><Amt Ccy=\"USD\">12.50</Amt>\
+                 <CdtDbtInd>DBIT</CdtDbtInd><Dt><Dt>{}</Dt></Dt></Bal>\n</Stmt>",
+                date
+            )
+        };
+
+        let (date, amount) = camt_balance(&balance("2019-02-28"), CurrencyCode::USD).unwrap();
+        assert_eq!(date, Utc.ymd(2019, 2, 28).and_hms(23, 59, 0));
+        assert_eq!(amount, usd(-1250));
+
+        match camt_balance(&balance("2019-02-2é"), CurrencyCode::USD) {
+            Err(Error::BadDate { line: Some(3), .. }) => {}
+            _ => panic!("the malformed date should be rejected"),
+        }
+    }
+}
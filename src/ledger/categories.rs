@@ -0,0 +1,178 @@
+use super::{Category, Error, Ledger};
+use regex::{Regex, RegexBuilder};
+use std::path::PathBuf;
+
+impl Ledger {
+    pub fn category(&self, name: &str) -> Result<&Category, Error> {
+        self.categories
+            .iter()
+            .find(|x| x.name == name)
+            .ok_or_else(|| Error::MissingCategory(name.to_owned()))
+    }
+
+    /// A category followed by its parents, up to the top level one.
+    pub fn category_ancestry<'a>(&'a self, name: &'a str) -> Vec<&'a str> {
+        let mut ancestry = vec![name];
+        while let Some(parent) = self
+            .categories
+            .iter()
+            .find(|x| Some(x.name.as_str()) == ancestry.last().cloned())
+            .and_then(|x| x.parent.as_ref())
+        {
+            // Hand edited ledgers may have cycles.
+            if ancestry.contains(&parent.as_str()) {
+                break;
+            }
+            ancestry.push(parent);
+        }
+        ancestry
+    }
+
+    /// Every category with its depth, each one followed by its children.
+    /// Categories whose parent is missing are top level ones.
+    pub fn category_tree(&self) -> Vec<(&Category, usize)> {
+        let mut tree = Vec::new();
+        let top_level = self
+            .categories
+            .iter()
+            .filter(|x| x.parent.as_ref().map(|p| self.category(p).is_err()).unwrap_or(true));
+        for category in top_level {
+            self.push_subtree(category, 0, &mut tree);
+        }
+        tree
+    }
+
+    fn push_subtree<'a>(&'a self, category: &'a Category, depth: usize, tree: &mut Vec<(&'a Category, usize)>) {
+        if tree.iter().any(|x| x.0.name == category.name) {
+            return;
+        }
+        tree.push((category, depth));
+        for child in self.categories.iter().filter(|x| x.parent.as_ref() == Some(&category.name)) {
+            self.push_subtree(child, depth + 1, tree);
+        }
+    }
+}
+
+fn compile(rule: &str) -> Result<Regex, Error> {
+    RegexBuilder::new(rule)
+        .case_insensitive(true)
+        .build()
+        .map_err(|_| Error::BadArgument {
+            argument: "rule".to_owned(),
+            value: rule.to_owned(),
+        })
+}
+
+/// The rules of every category, which imported transactions and fees are categorized by.
+#[derive(Clone)]
+pub struct Rules(Vec<(String, Vec<Regex>)>);
+
+impl Rules {
+    /// Rules are checked when added, so the ones that do not compile were edited by hand and are
+    /// left out.
+    pub fn new(categories: &[Category]) -> Self {
+        Rules(
+            categories
+                .iter()
+                .map(|x| (x.name.clone(), x.rules.iter().filter_map(|r| compile(r).ok()).collect()))
+                .collect(),
+        )
+    }
+
+    /// The first category with a rule matching one of the texts.
+    pub fn categorize(&self, texts: &[&str]) -> Option<String> {
+        self.0
+            .iter()
+            .find(|(_, rules)| rules.iter().any(|r| texts.iter().any(|x| r.is_match(x))))
+            .map(|x| x.0.clone())
+    }
+}
+
+/// Prints every category under its parent, with its rules.
+pub fn list(ledger_path: PathBuf) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+
+    if ledger.categories.is_empty() {
+        println!("There are no categories yet, add one with `ledger categories add LEDGER NAME`.");
+        return Ok(());
+    }
+
+    for (category, depth) in ledger.category_tree() {
+        let indent = "  ".repeat(depth);
+        println!("{}{}", indent, category.name);
+        if !category.rules.is_empty() {
+            println!("{}  Rules: {}", indent, category.rules.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn add(ledger_path: PathBuf, name: &str, parent: Option<&str>, rules: &[&str]) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+
+    if name.trim() == "" {
+        return Err(Error::BadArgument {
+            argument: "NAME".to_owned(),
+            value: name.to_owned(),
+        });
+    }
+    if ledger.category(name).is_ok() {
+        return Err(Error::DuplicateCategory(name.to_owned()));
+    }
+    let parent = parent.filter(|x| x.trim() != "");
+    if let Some(parent) = parent {
+        ledger.category(parent)?;
+    }
+    for rule in rules {
+        compile(rule)?;
+    }
+
+    ledger.categories.push(Category {
+        name: name.to_owned(),
+        parent: parent.map(str::to_owned),
+        rules: rules.iter().map(|x| x.to_string()).collect(),
+    });
+    ledger.save(&ledger_path)
+}
+
+/// Moves a category under another parent, an empty one making it a top level category,
+/// and adds rules to it.
+pub fn edit(ledger_path: PathBuf, name: &str, parent: Option<&str>, rules: &[&str]) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    ledger.category(name)?;
+
+    let parent = match parent {
+        Some(parent) if parent.trim() == "" => Some(None),
+        Some(parent) => {
+            ledger.category(parent)?;
+            if ledger.category_ancestry(parent).contains(&name) {
+                return Err(Error::BadArgument {
+                    argument: "parent".to_owned(),
+                    value: parent.to_owned(),
+                });
+            }
+            Some(Some(parent.to_owned()))
+        }
+        None => None,
+    };
+    for rule in rules {
+        compile(rule)?;
+    }
+
+    let category = ledger
+        .categories
+        .iter_mut()
+        .find(|x| x.name == name)
+        .expect("Unreachable: category exists");
+    if let Some(parent) = parent {
+        category.parent = parent;
+    }
+    for rule in rules {
+        if !category.rules.iter().any(|x| x == rule) {
+            category.rules.push(rule.to_string());
+        }
+    }
+
+    ledger.save(&ledger_path)
+}
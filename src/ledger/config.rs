@@ -0,0 +1,117 @@
+use super::report::form990_line;
+use super::{CurrencyCode, Error, Ledger, Settings};
+use std::path::PathBuf;
+
+impl Settings {
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = vec![
+            "backups".to_owned(),
+            "base_currency".to_owned(),
+            "rates".to_owned(),
+            "fiscal_year_start".to_owned(),
+            "organization".to_owned(),
+            "ein".to_owned(),
+        ];
+        keys.extend(self.accounts.keys().map(|x| format!("accounts.{}", x)));
+        keys.extend(self.form990.keys().map(|x| format!("form990.{}", x)));
+        keys
+    }
+
+    /// Month the fiscal year starts in, which may be out of range in a ledger edited by hand.
+    pub fn fiscal_year_start(&self) -> Result<u32, Error> {
+        Some(self.fiscal_year_start)
+            .filter(|x| (1..=12).contains(x))
+            .ok_or_else(|| Error::BadSetting {
+                key: "fiscal_year_start".to_owned(),
+                value: self.fiscal_year_start.to_string(),
+            })
+    }
+
+    pub fn get(&self, key: &str) -> Result<String, Error> {
+        match key {
+            "backups" => Ok(self.backups.to_string()),
+            "base_currency" => Ok(self.base_currency.to_string()),
+            "rates" => Ok(self.rates.clone()),
+            "fiscal_year_start" => Ok(self.fiscal_year_start.to_string()),
+            "organization" => Ok(self.organization.clone()),
+            "ein" => Ok(self.ein.clone()),
+            _ if key.starts_with("accounts.") => self
+                .accounts
+                .get(&key["accounts.".len()..])
+                .cloned()
+                .ok_or_else(|| Error::UnknownSetting(key.to_owned())),
+            _ if key.starts_with("form990.") => self
+                .form990
+                .get(&key["form990.".len()..])
+                .map(u32::to_string)
+                .ok_or_else(|| Error::UnknownSetting(key.to_owned())),
+            _ => Err(Error::UnknownSetting(key.to_owned())),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let bad_value = || Error::BadSetting {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        };
+
+        match key {
+            "backups" => self.backups = value.parse().map_err(|_| bad_value())?,
+            "base_currency" => self.base_currency = CurrencyCode::parse(value).ok_or_else(bad_value)?,
+            "rates" => self.rates = value.to_owned(),
+            "fiscal_year_start" => {
+                self.fiscal_year_start = value
+                    .parse()
+                    .ok()
+                    .filter(|x| (1..=12).contains(x))
+                    .ok_or_else(bad_value)?
+            }
+            "organization" => self.organization = value.to_owned(),
+            "ein" => self.ein = value.to_owned(),
+            _ if key.starts_with("accounts.") && key.len() > "accounts.".len() => {
+                if value.trim() == "" {
+                    return Err(bad_value());
+                }
+                self.accounts
+                    .insert(key["accounts.".len()..].to_owned(), value.to_owned());
+            }
+            // An empty line removes the entry, reporting on the default line again.
+            _ if key.starts_with("form990.") && key.len() > "form990.".len() => {
+                let name = key["form990.".len()..].to_owned();
+                if value.trim() == "" {
+                    self.form990.remove(&name);
+                } else {
+                    let line = value
+                        .parse()
+                        .ok()
+                        .filter(|x| form990_line(*x).is_some())
+                        .ok_or_else(bad_value)?;
+                    self.form990.insert(name, line);
+                }
+            }
+            _ => return Err(Error::UnknownSetting(key.to_owned())),
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints every setting of the ledger, prints a single one, or changes it.
+pub fn config(ledger_path: PathBuf, key: Option<&str>, value: Option<&str>) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+
+    match (key, value) {
+        (None, _) => {
+            for key in ledger.settings.keys() {
+                println!("{} = {}", key, ledger.settings.get(&key)?);
+            }
+        }
+        (Some(key), None) => println!("{}", ledger.settings.get(key)?),
+        (Some(key), Some(value)) => {
+            ledger.settings.set(key, value)?;
+            ledger.save(&ledger_path)?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,14 @@
-use super::{DonationID, Fee, IncomeKind, Ledger, Transaction, TransactionMetadata};
-use chrono::offset::TimeZone;
+use super::import::{
+    parse_amount, parse_any_date, parse_date, read_csv, read_json,
+    string_or_number, Import,
+};
+use super::accounts::new_transaction_id;
+use super::mapping::Mapping;
+use super::{
+    DonationID, Error, ExpenseKind, Fee, IncomeKind, Ledger, Money, RefundID, Transaction,
+    TransactionMetadata,
+};
+use chrono::{DateTime, Utc};
 use crypto::digest::Digest;
 use serde_derive::Deserialize;
 use std::path::PathBuf;
@@ -8,6 +17,12 @@ pub enum DonationOrigin {
     Unknown,
     DonorBox,
     OpenCollective,
+    GitHubSponsors,
+    Patreon,
+    Liberapay,
+    KoFi,
+    /// Any CSV export, read through a mapping file.
+    Mapping(PathBuf),
 }
 
 impl From<&str> for DonationOrigin {
@@ -15,24 +30,47 @@ impl From<&str> for DonationOrigin {
         match from {
             "donorbox" => DonationOrigin::DonorBox,
             "opencollective" => DonationOrigin::OpenCollective,
+            "githubsponsors" => DonationOrigin::GitHubSponsors,
+            "patreon" => DonationOrigin::Patreon,
+            "liberapay" => DonationOrigin::Liberapay,
+            "kofi" => DonationOrigin::KoFi,
             _ => DonationOrigin::Unknown,
         }
     }
 }
 
-pub fn import(ledger_path: PathBuf, data: PathBuf, origin: DonationOrigin) {
-    let mut ledger = Ledger::load(&ledger_path).expect("Could not read the ledger file");
+pub fn import(
+    ledger_path: PathBuf,
+    data: PathBuf,
+    origin: DonationOrigin,
+    overrides: &[(String, String)],
+    dry_run: bool,
+) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let import = Import::new(&ledger, overrides);
+
+    let import = match origin {
+        DonationOrigin::DonorBox => import_donorbox(import, &data)?,
+        DonationOrigin::OpenCollective => import_opencollective(import, &data)?,
+        DonationOrigin::GitHubSponsors => import_githubsponsors(import, &data)?,
+        DonationOrigin::Patreon => import_patreon(import, &data)?,
+        DonationOrigin::Liberapay => import_liberapay(import, &data)?,
+        DonationOrigin::KoFi => import_kofi(import, &data)?,
+        DonationOrigin::Mapping(ref mapping) => import_mapped(import, &data, &Mapping::load(mapping)?)?,
+        DonationOrigin::Unknown => {
+            println!("Unknown origin");
+            return Ok(());
+        }
+    };
 
-    match origin {
-        DonationOrigin::DonorBox => import_donorbox(&mut ledger, &data),
-        DonationOrigin::OpenCollective => import_opencollective(&mut ledger, &data),
-        DonationOrigin::Unknown => println!("Unknown origin"),
+    import.print_summary();
+    if dry_run {
+        println!("Dry run: the ledger was not modified.");
+        return Ok(());
     }
 
-    ledger.sort_by_date();
-    ledger
-        .save(&ledger_path)
-        .expect("Could not save the ledger");
+    import.commit(&mut ledger)?;
+    ledger.save(&ledger_path)
 }
 
 #[derive(Deserialize)]
@@ -53,48 +91,16 @@ struct OpenCollectiveRow {
     net_amount: String,
 }
 
-fn import_opencollective(ledger: &mut Ledger, data: &PathBuf) {
-    let account = ledger
-        .get_account_mut("Stripe")
-        .expect("Account for Stripe not found");
-
-    let known_donations: Vec<DonationID> = account
-        .transactions
-        .iter()
-        .filter_map(|x| match &x.meta {
-            TransactionMetadata::Income {
-                kind: IncomeKind::Donation(x),
-                ..
-            } => Some(x),
-            _ => None,
-        })
-        .map(Clone::clone)
-        .collect();
-
-    let mut donations: Vec<Transaction> = Vec::new();
-
-    let mut reader = csv::Reader::from_path(data).expect("Could not read the CSV file");
-
-    let records = reader.deserialize();
-    for (i, x) in records.enumerate() {
-        let x: OpenCollectiveRow =
-            x.unwrap_or_else(|e| panic!("Could not deserialize entry on entry {}!\n{}", i, e));
-        let mut amount = currency::Currency::from_str(&x.amount)
-            .unwrap_or_else(|e| panic!("Could not parse transaction amount on entry {}!\n{}", i, e));
-        let mut host_fee = currency::Currency::from_str(&x.host_fee)
-            .unwrap_or_else(|e| panic!("Could not parse host fee on entry {}!\n{}", i, e));
-        let mut oc_fee = currency::Currency::from_str(&x.oc_fee)
-            .unwrap_or_else(|e| panic!("Could not parse OpenCollective fee on entry {}!\n{}", i, e));
-        let mut processor_fee = currency::Currency::from_str(&x.processor_fee)
-            .unwrap_or_else(|e| panic!("Could not parse processor fee fee on entry {}!\n{}", i, e));
-        let date = chrono::Utc
-            .datetime_from_str(&x.date, "%Y-%m-%d %H:%M:%S")
-            .unwrap_or_else(|e| panic!("Could not parse transaction date on entry {}!\n{}", i, e));
-
-        amount.set_symbol('$');
-        host_fee.set_symbol('$');
-        oc_fee.set_symbol('$');
-        processor_fee.set_symbol('$');
+fn import_opencollective(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    let account = import.account("opencollective")?;
+    let currency = import.currency(&account)?;
+
+    for (line, x) in read_csv::<OpenCollectiveRow>(data)? {
+        let amount = parse_amount(&x.amount, currency, line, "Transaction Amount")?;
+        let host_fee = parse_amount(&x.host_fee, currency, line, "Host Fee (USD)")?;
+        let oc_fee = parse_amount(&x.oc_fee, currency, line, "Open Collective Fee (USD)")?;
+        let processor_fee = parse_amount(&x.processor_fee, currency, line, "Payment Processor Fee (USD)")?;
+        let date = parse_date(&x.date, "%Y-%m-%d %H:%M:%S", line, "Transaction Date")?;
 
         let mut hasher = crypto::sha2::Sha256::new();
         hasher.input_str("OpenCollective");
@@ -105,48 +111,48 @@ fn import_opencollective(ledger: &mut Ledger, data: &PathBuf) {
         let mut hash = vec![0; 32];
         hasher.result(&mut hash);
 
-        if !known_donations.contains(&hash) {
-            let meta = TransactionMetadata::Income {
-                kind: IncomeKind::Donation(hash),
-                from: x.user.to_owned(),
-            };
-
-            //println!("Processor: {}\nOC: {}\nHost: {}\n\n", processor_fee, oc_fee, host_fee);
-
-            donations.push(Transaction {
-                amount,
-                date,
-                meta,
-                description: "Donation made through the OpenCollective platform".to_owned(),
-                fees: vec![
-                    Fee {
-                        amount: host_fee.clone(),
-                        towards: "Collective Host (Amethyst Foundation)".to_owned(),
-                    },
-                    Fee {
-                        amount: -host_fee,
-                        towards: "Collective Host (Amethyst Foundation)".to_owned(),
-                    },
-                    Fee {
-                        amount: -oc_fee,
-                        towards: "OpenCollective".to_owned(),
-                    },
-                    Fee {
-                        amount: -processor_fee,
-                        towards: "Payment Processor".to_owned(),
-                    },
-                ],
-            });
+        //println!("Processor: {}\nOC: {}\nHost: {}\n\n", processor_fee, oc_fee, host_fee);
+
+        let fees = vec![
+            Fee {
+                amount: host_fee.clone(),
+                towards: "Collective Host (Amethyst Foundation)".to_owned(),
+                category: None,
+            },
+            Fee {
+                amount: -host_fee,
+                towards: "Collective Host (Amethyst Foundation)".to_owned(),
+                category: None,
+            },
+            Fee {
+                amount: -oc_fee,
+                towards: "OpenCollective".to_owned(),
+                category: None,
+            },
+            Fee {
+                amount: -processor_fee,
+                towards: "Payment Processor".to_owned(),
+                category: None,
+            },
+        ];
+
+        let donation = Donation {
+            id: hash,
+            from: &x.user,
+            amount,
+            date,
+            platform: "OpenCollective",
+            fees,
+        };
+        // Refunded contributions show up as negative ones.
+        if donation.amount.is_negative() {
+            donation.refund(RefundKind::Refund).push(&mut import, &account, line)?;
         } else {
-            println!(
-                "WARNING: Donation from `{}` on {} (entry {}) is already in the ledger.",
-                x.user, x.date, i
-            );
+            donation.push(&mut import, &account, line)?;
         }
     }
 
-    donations.sort_by(|x, y| x.date.cmp(&y.date));
-    account.transactions.append(&mut donations);
+    Ok(import)
 }
 
 #[derive(Deserialize)]
@@ -163,50 +169,19 @@ struct DonorBoxRow {
     receipt: String,
     #[serde(rename = "Donation Type")]
     processor: String,
+    /// Only present in exports that include refunded or disputed donations.
+    #[serde(rename = "Status", default)]
+    status: String,
 }
 
-fn import_donorbox(ledger: &mut Ledger, data: &PathBuf) {
-    let stripe = ledger
-        .get_account("Stripe")
-        .expect("Account for Stripe not found");
-
-    let paypal = ledger
-        .get_account("PayPal")
-        .expect("Account for PayPal not found");
-
-    let known_donations: Vec<DonationID> = stripe
-        .transactions
-        .iter()
-        .chain(&paypal.transactions)
-        .filter_map(|x| match &x.meta {
-            TransactionMetadata::Income {
-                kind: IncomeKind::Donation(x),
-                ..
-            } => Some(x),
-            _ => None,
-        })
-        .map(Clone::clone)
-        .collect();
-
-    let mut donations_stripe: Vec<Transaction> = Vec::new();
-    let mut donations_paypal: Vec<Transaction> = Vec::new();
-
-    let mut reader = csv::Reader::from_path(data).expect("Could not read the CSV file");
-
-    let records = reader.deserialize();
-    for (i, x) in records.enumerate() {
-        let x: DonorBoxRow =
-            x.unwrap_or_else(|e| panic!("Could not deserialize entry on entry {}!\n{}", i, e));
-        let mut amount = currency::Currency::from_str(&x.amount)
-            .unwrap_or_else(|e| panic!("Could not parse transaction amount on entry {}!\n{}", i, e));
-        let mut fee = currency::Currency::from_str(&x.fee)
-            .unwrap_or_else(|e| panic!("Could not parse fee on entry {}!\n{}", i, e));
-        let date = chrono::Utc
-            .datetime_from_str(&x.date.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S")
-            .unwrap_or_else(|e| panic!("Could not parse transaction date on entry {}!\n{}", i, e));
-
-        amount.set_symbol('$');
-        fee.set_symbol('$');
+fn import_donorbox(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    for (line, x) in read_csv::<DonorBoxRow>(data)? {
+        let date = parse_date(
+            &x.date.trim_end_matches(" UTC"),
+            "%Y-%m-%d %H:%M:%S",
+            line,
+            "Date Donated",
+        )?;
 
         let mut hasher = crypto::sha2::Sha256::new();
         hasher.input_str("DonorBox");
@@ -218,58 +193,655 @@ fn import_donorbox(ledger: &mut Ledger, data: &PathBuf) {
         let mut hash = vec![0; 32];
         hasher.result(&mut hash);
 
-        if !known_donations.contains(&hash) {
-            let meta = TransactionMetadata::Income {
-                kind: IncomeKind::Donation(hash),
-                from: x.name.to_owned(),
-            };
-
-            match x.processor.as_ref() {
-                "stripe" => {
-                    donations_stripe.push(Transaction {
-                        amount,
-                        date,
-                        meta,
-                        description: "Donation made through the DonorBox platform".to_owned(),
-                        fees: vec![Fee {
-                            amount: fee,
-                            towards: "DonorBox Processing".to_owned(),
-                        }],
-                    });
-                }
-                "paypal" | "paypal_express" => {
-                    donations_paypal.push(Transaction {
-                        amount,
-                        date,
-                        meta,
-                        description: "Donation made through the DonorBox platform".to_owned(),
-                        fees: vec![Fee {
-                            amount: fee,
-                            towards: "DonorBox Processing".to_owned(),
-                        }],
-                    });
-                }
-                mtd => println!("WARNING: Unknown donation method `{}` for donation from `{}` on {} (entry {}).", mtd, x.name, x.date, i),
+        let account = match x.processor.as_ref() {
+            "stripe" => import.account("stripe")?,
+            "paypal" | "paypal_express" => import.account("paypal")?,
+            mtd => {
+                println!("WARNING: Unknown donation method `{}` for donation from `{}` on {} (line {}).", mtd, x.name, x.date, line);
+                import.unknown_processor(mtd);
+                continue;
+            }
+        };
+
+        let currency = import.currency(&account)?;
+        let amount = parse_amount(&x.amount, currency, line, "Amount")?;
+        let fee = parse_amount(&x.fee, currency, line, "Processing Fee")?;
+
+        let status = x.status.to_lowercase();
+        let refund_kind = if status.contains("chargeback") || status.contains("dispute") {
+            Some(RefundKind::Chargeback)
+        } else if status.contains("refund") || amount.is_negative() {
+            Some(RefundKind::Refund)
+        } else {
+            None
+        };
+
+        let donation = Donation {
+            id: hash,
+            from: &x.name,
+            amount,
+            date,
+            platform: "DonorBox",
+            fees: vec![Fee {
+                amount: fee,
+                towards: "DonorBox Processing".to_owned(),
+                category: None,
+            }],
+        };
+        match refund_kind {
+            Some(kind) => donation.refund(kind).push(&mut import, &account, line)?,
+            None => donation.push(&mut import, &account, line)?,
+        }
+    }
+
+    Ok(import)
+}
+
+/// GitHub Sponsors exports the same fields in CSV and JSON, with different names.
+#[derive(Deserialize)]
+struct GitHubSponsorsRow {
+    #[serde(rename = "Sponsor Handle", alias = "sponsor_handle")]
+    handle: String,
+    #[serde(rename = "Transaction ID", alias = "transaction_id", deserialize_with = "string_or_number")]
+    transaction: String,
+    #[serde(rename = "Transaction Date", alias = "transaction_date")]
+    date: String,
+    #[serde(rename = "Processed Amount", alias = "processed_amount", deserialize_with = "string_or_number")]
+    amount: String,
+    /// Only charged on some sponsorships, such as those paid by organizations.
+    #[serde(
+        rename = "Processing Fee",
+        alias = "processing_fee",
+        default,
+        deserialize_with = "string_or_number"
+    )]
+    fee: String,
+    #[serde(rename = "Status", alias = "status", default)]
+    status: String,
+}
+
+fn import_githubsponsors(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    let account = import.account("githubsponsors")?;
+    let currency = import.currency(&account)?;
+
+    let rows = if data.extension().map(|x| x == "json").unwrap_or(false) {
+        read_json::<GitHubSponsorsRow>(data)?
+    } else {
+        read_csv::<GitHubSponsorsRow>(data)?
+    };
+
+    for (line, x) in rows {
+        let amount = parse_amount(&x.amount, currency, line, "Processed Amount")?;
+        let fee = if x.fee.trim() == "" {
+            Money::zero(currency)
+        } else {
+            parse_amount(&x.fee, currency, line, "Processing Fee")?
+        };
+        let date = parse_any_date(&x.date, line, "Transaction Date")?;
+
+        let mut hasher = crypto::sha2::Sha256::new();
+        hasher.input_str("GitHubSponsors");
+        hasher.input_str(&x.handle);
+        hasher.input_str(&x.transaction);
+        hasher.input_str(&x.date);
+        hasher.input_str(&x.amount);
+
+        let mut hash = vec![0; 32];
+        hasher.result(&mut hash);
+
+        let fees = vec![Fee {
+            amount: fee,
+            towards: "GitHub Sponsors Processing".to_owned(),
+            category: None,
+        }];
+
+        let donation = Donation {
+            id: hash,
+            from: &x.handle,
+            amount,
+            date,
+            platform: "GitHub Sponsors",
+            fees,
+        };
+        if x.status.to_lowercase().contains("refund") || donation.amount.is_negative() {
+            donation.refund(RefundKind::Refund).push(&mut import, &account, line)?;
+        } else {
+            donation.push(&mut import, &account, line)?;
+        }
+    }
+
+    Ok(import)
+}
+
+#[derive(Deserialize)]
+struct PatreonRow {
+    #[serde(rename = "Charge ID")]
+    charge: String,
+    #[serde(rename = "Patron Name")]
+    name: String,
+    #[serde(rename = "Charge Date")]
+    date: String,
+    #[serde(rename = "Pledge Amount")]
+    amount: String,
+    #[serde(rename = "Patreon Fee")]
+    platform_fee: String,
+    #[serde(rename = "Processing Fee")]
+    processing_fee: String,
+    #[serde(rename = "Charge Status", default)]
+    status: String,
+}
+
+/// Patreon keeps the pledges and pays them out as a whole, so they all go to one account.
+fn import_patreon(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    let account = import.account("patreon")?;
+    let currency = import.currency(&account)?;
+
+    for (line, x) in read_csv::<PatreonRow>(data)? {
+        let amount = parse_amount(&x.amount, currency, line, "Pledge Amount")?;
+        let platform_fee = parse_amount(&x.platform_fee, currency, line, "Patreon Fee")?;
+        let processing_fee = parse_amount(&x.processing_fee, currency, line, "Processing Fee")?;
+        let date = parse_any_date(&x.date, line, "Charge Date")?;
+
+        let mut hasher = crypto::sha2::Sha256::new();
+        hasher.input_str("Patreon");
+        hasher.input_str(&x.charge);
+        hasher.input_str(&x.name);
+        hasher.input_str(&x.date);
+        hasher.input_str(&x.amount);
+
+        let mut hash = vec![0; 32];
+        hasher.result(&mut hash);
+
+        let fees = vec![
+            Fee {
+                amount: platform_fee,
+                towards: "Patreon".to_owned(),
+                category: None,
+            },
+            Fee {
+                amount: processing_fee,
+                towards: "Payment Processor".to_owned(),
+                category: None,
+            },
+        ];
+
+        let donation = Donation {
+            id: hash,
+            from: &x.name,
+            amount,
+            date,
+            platform: "Patreon",
+            fees,
+        };
+        let status = x.status.to_lowercase();
+        if status.contains("refund") || status.contains("fraud") || donation.amount.is_negative() {
+            donation.refund(RefundKind::Refund).push(&mut import, &account, line)?;
+        } else if status != "" && status != "paid" {
+            println!(
+                "WARNING: Skipped the {} pledge from `{}` on {} (line {}).",
+                status, x.name, x.date, line
+            );
+        } else {
+            donation.push(&mut import, &account, line)?;
+        }
+    }
+
+    Ok(import)
+}
+
+#[derive(Deserialize)]
+struct LiberapayRow {
+    #[serde(rename = "Payin ID")]
+    payin: String,
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Donor")]
+    donor: String,
+    #[serde(rename = "Amount")]
+    amount: String,
+    #[serde(rename = "Fee")]
+    fee: String,
+    #[serde(rename = "Network")]
+    network: String,
+}
+
+/// Liberapay takes no cut, donors pay us directly through Stripe or PayPal.
+fn import_liberapay(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    for (line, x) in read_csv::<LiberapayRow>(data)? {
+        let date = parse_any_date(&x.date, line, "Date")?;
+
+        let mut hasher = crypto::sha2::Sha256::new();
+        hasher.input_str("Liberapay");
+        hasher.input_str(&x.payin);
+        hasher.input_str(&x.donor);
+        hasher.input_str(&x.date);
+        hasher.input_str(&x.amount);
+
+        let mut hash = vec![0; 32];
+        hasher.result(&mut hash);
+
+        let network = x.network.to_lowercase();
+        let (account, processor) = if network.starts_with("stripe") {
+            (import.account("stripe")?, "Stripe")
+        } else if network.starts_with("paypal") {
+            (import.account("paypal")?, "PayPal")
+        } else {
+            println!(
+                "WARNING: Unknown payment network `{}` for donation from `{}` on {} (line {}).",
+                x.network, x.donor, x.date, line
+            );
+            import.unknown_processor(&x.network);
+            continue;
+        };
+
+        let currency = import.currency(&account)?;
+        let amount = parse_amount(&x.amount, currency, line, "Amount")?;
+        let fee = parse_amount(&x.fee, currency, line, "Fee")?;
+
+        let fees = vec![Fee {
+            amount: fee,
+            towards: format!("{} Processing", processor),
+            category: None,
+        }];
+
+        let donation = Donation {
+            id: hash,
+            from: &x.donor,
+            amount,
+            date,
+            platform: "Liberapay",
+            fees,
+        };
+        if donation.amount.is_negative() {
+            donation.refund(RefundKind::Refund).push(&mut import, &account, line)?;
+        } else {
+            donation.push(&mut import, &account, line)?;
+        }
+    }
+
+    Ok(import)
+}
+
+#[derive(Deserialize)]
+struct KoFiRow {
+    #[serde(rename = "TransactionId")]
+    transaction: String,
+    #[serde(rename = "DateTime (UTC)")]
+    date: String,
+    #[serde(rename = "From")]
+    from: String,
+    #[serde(rename = "Received")]
+    amount: String,
+    /// Only charged on memberships and shop sales without Ko-fi Gold.
+    #[serde(rename = "Ko-fi Fee", default)]
+    platform_fee: String,
+    #[serde(rename = "Processing Fee", default)]
+    processing_fee: String,
+    #[serde(rename = "Payment Method")]
+    method: String,
+}
+
+/// Ko-fi sends every payment straight to our own PayPal or Stripe account.
+fn import_kofi(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    for (line, x) in read_csv::<KoFiRow>(data)? {
+        let date = parse_any_date(&x.date, line, "DateTime (UTC)")?;
+
+        let mut hasher = crypto::sha2::Sha256::new();
+        hasher.input_str("Ko-fi");
+        hasher.input_str(&x.transaction);
+        hasher.input_str(&x.from);
+        hasher.input_str(&x.date);
+        hasher.input_str(&x.amount);
+
+        let mut hash = vec![0; 32];
+        hasher.result(&mut hash);
+
+        let account = match x.method.to_lowercase().as_ref() {
+            "stripe" | "card" => import.account("stripe")?,
+            "paypal" => import.account("paypal")?,
+            mtd => {
+                println!("WARNING: Unknown payment method `{}` for donation from `{}` on {} (line {}).", mtd, x.from, x.date, line);
+                import.unknown_processor(mtd);
+                continue;
             }
+        };
+
+        let currency = import.currency(&account)?;
+        let amount = parse_amount(&x.amount, currency, line, "Received")?;
+
+        let mut fees = Vec::new();
+        if x.platform_fee.trim() != "" {
+            fees.push(Fee {
+                amount: parse_amount(&x.platform_fee, currency, line, "Ko-fi Fee")?,
+                towards: "Ko-fi".to_owned(),
+                category: None,
+            });
+        }
+        if x.processing_fee.trim() != "" {
+            fees.push(Fee {
+                amount: parse_amount(&x.processing_fee, currency, line, "Processing Fee")?,
+                towards: "Payment Processor".to_owned(),
+                category: None,
+            });
+        }
+
+        let donation = Donation {
+            id: hash,
+            from: &x.from,
+            amount,
+            date,
+            platform: "Ko-fi",
+            fees,
+        };
+        if donation.amount.is_negative() {
+            donation.refund(RefundKind::Refund).push(&mut import, &account, line)?;
+        } else {
+            donation.push(&mut import, &account, line)?;
+        }
+    }
+
+    Ok(import)
+}
+
+/// Rows with a negative amount once the mapping's sign convention applied are refunds.
+fn import_mapped(mut import: Import, data: &PathBuf, mapping: &Mapping) -> Result<Import, Error> {
+    let account = mapping.account(&import);
+    let currency = import.currency(&account)?;
+
+    for x in mapping.read(data, currency)? {
+        let donation = Donation {
+            id: x.hash,
+            from: &x.counterparty,
+            amount: x.amount,
+            date: x.date,
+            platform: &mapping.platform,
+            fees: x.fees,
+        };
+        if donation.amount.is_negative() {
+            donation.refund(RefundKind::Refund).push(&mut import, &account, x.line)?;
         } else {
+            donation.push(&mut import, &account, x.line)?;
+        }
+    }
+
+    Ok(import)
+}
+
+/// An imported row that gives us a donation.
+struct Donation<'a> {
+    id: DonationID,
+    from: &'a str,
+    amount: Money,
+    date: DateTime<Utc>,
+    platform: &'a str,
+    fees: Vec<Fee>,
+}
+
+impl<'a> Donation<'a> {
+    fn push(self, import: &mut Import, account: &str, line: u64) -> Result<(), Error> {
+        let queued = import.push(
+            account,
+            Transaction {
+                id: new_transaction_id(),
+                bank_id: None,
+                original: None,
+                cleared: false,
+                category: None,
+                amount: self.amount,
+                date: self.date,
+                meta: TransactionMetadata::Income {
+                    kind: IncomeKind::Donation(self.id),
+                    from: self.from.to_owned(),
+                },
+                description: format!("Donation made through the {} platform", self.platform),
+                fees: self.fees,
+            },
+        )?;
+
+        if !queued {
             println!(
-                "WARNING: Donation from `{}` on {} (entry {}) is already in the ledger.",
-                x.name, x.date, i
+                "WARNING: Donation from `{}` on {} (line {}) is already in the ledger.",
+                self.from, self.date, line
             );
         }
+
+        Ok(())
     }
 
-    donations_stripe.sort_by(|x, y| x.date.cmp(&y.date));
-    ledger
-        .get_account_mut("Stripe")
-        .expect("Account for Stripe not found")
-        .transactions
-        .append(&mut donations_stripe);
-
-    donations_paypal.sort_by(|x, y| x.date.cmp(&y.date));
-    ledger
-        .get_account_mut("PayPal")
-        .expect("Account for PayPal not found")
-        .transactions
-        .append(&mut donations_paypal);
+    /// A row with a negative amount is a refund of its own, of a donation made on another row.
+    /// Otherwise the row is the donation itself, reported as refunded by its status.
+    fn refund(self, kind: RefundKind) -> Refund<'a> {
+        if self.amount.is_negative() {
+            Refund {
+                kind,
+                hash: self.id,
+                from: self.from,
+                amount: -self.amount,
+                date: self.date,
+                platform: self.platform,
+                fees: self.fees,
+                donation: None,
+            }
+        } else {
+            // The fees were charged on the donation, not on giving it back.
+            Refund {
+                kind,
+                hash: self.id.clone(),
+                from: self.from,
+                amount: self.amount.clone(),
+                date: self.date,
+                platform: self.platform,
+                fees: Vec::new(),
+                donation: Some(self),
+            }
+        }
+    }
+}
+
+enum RefundKind {
+    Refund,
+    Chargeback,
+}
+
+/// An imported row that gives a donation back, to be linked to that donation.
+struct Refund<'a> {
+    kind: RefundKind,
+    /// Hash of the row, which the refund ID is derived from.
+    hash: Vec<u8>,
+    from: &'a str,
+    amount: Money,
+    date: DateTime<Utc>,
+    platform: &'a str,
+    fees: Vec<Fee>,
+    /// The refunded donation, when the row is that donation.
+    donation: Option<Donation<'a>>,
+}
+
+impl<'a> Refund<'a> {
+    fn push(self, import: &mut Import, account: &str, line: u64) -> Result<(), Error> {
+        let name = match self.kind {
+            RefundKind::Refund => "Refund",
+            RefundKind::Chargeback => "Chargeback",
+        };
+        let id = refund_id(name, &self.hash);
+
+        let donation = match self.donation {
+            // Imported along with its refund if the ledger does not have it yet, so that they net out.
+            Some(donation) => {
+                let donation_id = donation.id.clone();
+                if !import.is_known(&donation_id) {
+                    donation.push(import, account, line)?;
+                }
+                Some(donation_id)
+            }
+            None => match import.find_donation(self.from, &self.amount, self.date) {
+                Some(x) => Some(x),
+                None if import.is_known(&id) => None,
+                None => {
+                    println!(
+                        "WARNING: No donation from `{}` found for the refund on {} (line {}), it will not be linked to one.",
+                        self.from, self.date, line
+                    );
+                    None
+                }
+            },
+        };
+
+        let kind = match self.kind {
+            RefundKind::Refund => ExpenseKind::Refund { id, donation },
+            RefundKind::Chargeback => ExpenseKind::Chargeback { id, donation },
+        };
+
+        let queued = import.push(
+            account,
+            Transaction {
+                id: new_transaction_id(),
+                bank_id: None,
+                original: None,
+                cleared: false,
+                category: None,
+                amount: self.amount,
+                date: self.date,
+                meta: TransactionMetadata::Expense {
+                    kind,
+                    towards: self.from.to_owned(),
+                    requester: String::new(),
+                },
+                description: format!("{} of a donation made through the {} platform", name, self.platform),
+                fees: self.fees,
+            },
+        )?;
+
+        if !queued {
+            println!(
+                "WARNING: {} to `{}` on {} (line {}) is already in the ledger.",
+                name,
+                self.from, self.date, line
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Salted with the kind of refund, since a refund reported on the row of the donation it reverses
+/// would otherwise get the donation's ID and be skipped as already imported.
+fn refund_id(kind: &str, hash: &[u8]) -> RefundID {
+    let mut hasher = crypto::sha2::Sha256::new();
+    hasher.input_str("Refund");
+    hasher.input_str(kind);
+    hasher.input(hash);
+
+    let mut id = vec![0; 32];
+    hasher.result(&mut id);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+
+    fn row(id: u8, amount: i64) -> Donation<'static> {
+        Donation {
+            id: vec![id],
+            from: "Ann",
+            amount: usd(amount),
+            date: date(2019, 1, 2),
+            platform: "DonorBox",
+            fees: vec![Fee {
+                amount: usd(59),
+                towards: "DonorBox Processing".to_owned(),
+                category: None,
+            }],
+        }
+    }
+
+    /// Pushes rows through a new import and commits it.
+    fn import(ledger: &mut Ledger, push: impl FnOnce(&mut Import) -> Result<(), Error>) {
+        let mut import = Import::new(ledger, &[]);
+        push(&mut import).unwrap();
+        import.commit(ledger).unwrap();
+    }
+
+    /// The ID of every imported donation or refund, along with the donation a refund reverses.
+    fn kinds(ledger: &Ledger) -> Vec<(Vec<u8>, Option<Vec<u8>>)> {
+        ledger.accounts[0]
+            .transactions
+            .iter()
+            .map(|x| match x.meta {
+                TransactionMetadata::Income {
+                    kind: IncomeKind::Donation(ref id),
+                    ..
+                } => (id.clone(), None),
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Refund { ref id, ref donation },
+                    ..
+                }
+                | TransactionMetadata::Expense {
+                    kind: ExpenseKind::Chargeback { ref id, ref donation },
+                    ..
+                } => (id.clone(), donation.clone()),
+                _ => panic!("only donations and refunds are imported"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn refunds_a_donation_already_imported() {
+        let mut ledger = ledger(vec![account("Stripe", Vec::new())]);
+        import(&mut ledger, |import| row(1, 1000).push(import, "Stripe", 2));
+        import(&mut ledger, |import| row(1, 1000).refund(RefundKind::Refund).push(import, "Stripe", 2));
+
+        let kinds = kinds(&ledger);
+        assert_eq!(kinds.len(), 2);
+        assert_eq!(kinds[0], (vec![1], None));
+        assert_ne!(kinds[1].0, vec![1]);
+        assert_eq!(kinds[1].1, Some(vec![1]));
+
+        // The fees were charged once, on the donation.
+        assert_eq!(ledger.accounts[0].current_balance().unwrap(), usd(-59));
+    }
+
+    #[test]
+    fn imports_a_refunded_donation_along_with_its_refund() {
+        let mut ledger = ledger(vec![account("Stripe", Vec::new())]);
+        import(&mut ledger, |import| row(1, 1000).refund(RefundKind::Chargeback).push(import, "Stripe", 2));
+        let first = kinds(&ledger);
+        assert_eq!(first.len(), 2);
+        assert!(first.contains(&(vec![1], None)));
+        assert!(first.iter().any(|x| x.1 == Some(vec![1])));
+
+        // Importing the same export again changes nothing.
+        import(&mut ledger, |import| {
+            row(1, 1000).refund(RefundKind::Chargeback).push(import, "Stripe", 2)?;
+            row(1, 1000).push(import, "Stripe", 2)
+        });
+        assert_eq!(kinds(&ledger), first);
+    }
+
+    #[test]
+    fn refunds_and_chargebacks_of_a_row_have_their_own_ids() {
+        assert_ne!(refund_id("Refund", &[1]), refund_id("Chargeback", &[1]));
+        assert_ne!(refund_id("Refund", &[1]), vec![1]);
+    }
+
+    #[test]
+    fn links_refund_rows_to_an_earlier_donation() {
+        let mut ledger = ledger(vec![account("Stripe", Vec::new())]);
+        import(&mut ledger, |import| {
+            row(1, 1000).push(import, "Stripe", 2)?;
+            row(2, -1000).refund(RefundKind::Refund).push(import, "Stripe", 3)
+        });
+
+        let kinds = kinds(&ledger);
+        assert_eq!(kinds.len(), 2);
+        assert_eq!(kinds[1].0, refund_id("Refund", &[2]));
+        assert_eq!(kinds[1].1, Some(vec![1]));
+
+        // A refund row is not a donation, and keeps the fees it was charged.
+        let refund = &ledger.accounts[0].transactions[1];
+        assert_eq!(refund.amount, usd(1000));
+        assert_eq!(refund.fees.len(), 1);
+    }
 }
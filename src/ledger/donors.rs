@@ -0,0 +1,369 @@
+use super::rates::report_currency;
+use super::{CurrencyCode, Donor, Error, Ledger, Money, Receipt};
+use chrono::{DateTime, Datelike, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Names are matched regardless of case and spacing.
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+impl Donor {
+    pub fn is_named(&self, name: &str) -> bool {
+        let name = normalize(name);
+        normalize(&self.name) == name || self.aliases.iter().any(|x| normalize(x) == name)
+    }
+}
+
+impl Ledger {
+    pub fn donor(&self, name: &str) -> Option<&Donor> {
+        self.donors.iter().find(|x| x.is_named(name))
+    }
+
+    /// Name a donation from the given name is recorded under: its donor's if one is registered.
+    pub fn donor_name(&self, name: &str) -> String {
+        donor_name(&self.donors, name)
+    }
+}
+
+/// Whether gifts from the given name were made without telling who gave them.
+pub fn is_anonymous(name: &str) -> bool {
+    match normalize(name).as_str() {
+        "" | "anonymous" | "anon" | "anonymous donor" | "unknown" | "n/a" | "-" => true,
+        _ => false,
+    }
+}
+
+pub fn donor_name(donors: &[Donor], name: &str) -> String {
+    donors
+        .iter()
+        .find(|x| x.is_named(name))
+        .map(|x| x.name.clone())
+        .unwrap_or_else(|| name.to_owned())
+}
+
+/// A donation, or a refund or chargeback of one, with its amount in the currency of a report.
+pub struct Gift {
+    pub date: DateTime<Utc>,
+    /// Negative for refunds and chargebacks.
+    pub amount: Money,
+    pub platform: String,
+    pub description: String,
+}
+
+impl Gift {
+    pub fn is_refund(&self) -> bool {
+        self.amount.is_negative()
+    }
+}
+
+/// Every gift of the ledger by donor name, sorted by date. Donors are either registered ones,
+/// which gather the gifts made under any of their aliases, or unregistered names.
+pub fn gifts(ledger: &Ledger, currency: CurrencyCode) -> Result<BTreeMap<String, Vec<Gift>>, Error> {
+    let mut gifts: BTreeMap<String, Vec<Gift>> = BTreeMap::new();
+
+    for account in &ledger.accounts {
+        for transaction in &account.transactions {
+            let (name, refund) = match Receipt::of(&transaction.meta) {
+                Some(Receipt::Donation { from }) => (from, false),
+                Some(Receipt::Refund { towards }) => (towards, true),
+                _ => continue,
+            };
+
+            let amount = ledger.rates.convert(&transaction.amount, currency, transaction.date)?;
+            gifts.entry(ledger.donor_name(name)).or_insert_with(Vec::new).push(Gift {
+                date: transaction.date,
+                amount: if refund { -amount } else { amount },
+                platform: platform(&transaction.description).unwrap_or(&account.name).to_owned(),
+                description: transaction.description.clone(),
+            });
+        }
+    }
+
+    for x in gifts.values_mut() {
+        x.sort_by_key(|x| x.date);
+    }
+
+    Ok(gifts)
+}
+
+/// Platform named in the description importers give to donations, refunds and chargebacks.
+fn platform(description: &str) -> Option<&str> {
+    let start = description.find("made through the ")? + "made through the ".len();
+    let end = description[start..].rfind(" platform")?;
+    Some(&description[start..start + end])
+}
+
+/// Prints the giving history of every donor, registered or not, from the largest lifetime total.
+pub fn list(ledger_path: PathBuf, currency: Option<&str>) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+
+    let gifts = gifts(&ledger, base)?;
+    let idle: Vec<&Donor> = ledger.donors.iter().filter(|x| !gifts.contains_key(&x.name)).collect();
+
+    let mut donors = Vec::new();
+    for (name, gifts) in gifts {
+        let total = Money::sum(base, gifts.iter().map(|x| &x.amount))?;
+        donors.push((name, gifts, total));
+    }
+    donors.sort_by(|x, y| y.2.minor().cmp(&x.2.minor()).then_with(|| x.0.cmp(&y.0)));
+
+    println!("Amounts in {}, converted on the date of each gift, net of refunds.", base);
+    for (name, gifts, total) in donors {
+        let donor = ledger.donor(&name);
+        let donations: Vec<&Gift> = gifts.iter().filter(|x| !x.is_refund()).collect();
+
+        println!();
+        match donor.and_then(|x| x.email.as_ref()) {
+            Some(email) => println!("{} <{}>", name, email),
+            None => println!("{}", name),
+        }
+        if let Some(donor) = donor.filter(|x| x.aliases.len() != 0) {
+            println!("   Also known as: {}", donor.aliases.join(", "));
+        }
+        match (donations.first(), donations.last()) {
+            (Some(first), Some(last)) => println!(
+                "   Lifetime: {} in {} gift(s), first on {}, last on {}",
+                total,
+                donations.len(),
+                first.date.format(crate::DATE_FORMAT),
+                last.date.format(crate::DATE_FORMAT)
+            ),
+            _ => println!("   Lifetime: {} in no gift", total),
+        }
+
+        let platforms: BTreeSet<&str> = gifts.iter().map(|x| x.platform.as_str()).collect();
+        println!("   Platforms: {}", platforms.into_iter().collect::<Vec<_>>().join(", "));
+
+        let mut years: BTreeMap<i32, Money> = BTreeMap::new();
+        for gift in &gifts {
+            let year = years.entry(gift.date.year()).or_insert_with(|| Money::zero(base));
+            *year = year.checked_add(&gift.amount)?;
+        }
+        for (year, amount) in years {
+            println!("   {}: {}", year, amount);
+        }
+    }
+
+    for donor in idle {
+        println!();
+        println!("{} has not given anything yet.", donor.name);
+    }
+
+    Ok(())
+}
+
+/// Registers a donor. Neither the name nor the aliases may already be those of another donor.
+pub fn add(ledger_path: PathBuf, name: &str, email: Option<&str>, aliases: &[&str]) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+
+    for x in Some(&name).into_iter().chain(aliases) {
+        if x.trim() == "" {
+            return Err(Error::BadArgument {
+                argument: "name".to_owned(),
+                value: x.to_string(),
+            });
+        }
+        if ledger.donor(x).is_some() {
+            return Err(Error::DuplicateDonor(x.to_string()));
+        }
+    }
+
+    ledger.donors.push(Donor {
+        name: name.trim().to_owned(),
+        aliases: aliases.iter().map(|x| x.trim().to_owned()).collect(),
+        email: email.map(str::to_owned).filter(|x| x.trim() != ""),
+    });
+    ledger.donors.sort_by(|x, y| x.name.cmp(&y.name));
+    ledger.save(&ledger_path)
+}
+
+/// Adds aliases to a donor, or changes its email, which an empty one removes.
+pub fn edit(ledger_path: PathBuf, name: &str, email: Option<&str>, aliases: &[&str]) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let index = ledger
+        .donors
+        .iter()
+        .position(|x| x.is_named(name))
+        .ok_or_else(|| Error::MissingDonor(name.to_owned()))?;
+
+    for alias in aliases {
+        if alias.trim() == "" {
+            return Err(Error::BadArgument {
+                argument: "alias".to_owned(),
+                value: alias.to_string(),
+            });
+        }
+        match ledger.donors.iter().position(|x| x.is_named(alias)) {
+            Some(x) if x == index => {}
+            Some(_) => return Err(Error::DuplicateDonor(alias.to_string())),
+            None => ledger.donors[index].aliases.push(alias.trim().to_owned()),
+        }
+    }
+
+    if let Some(email) = email {
+        ledger.donors[index].email = Some(email.to_owned()).filter(|x| x.trim() != "");
+    }
+
+    ledger.save(&ledger_path)
+}
+
+/// Statement required on acknowledgments of donations that bought nothing in return.
+const NO_GOODS_STATEMENT: &str =
+    "No goods or services were provided in exchange for these contributions.";
+
+/// Placeholders every receipt template must contain, so that no receipt lacks what makes it one.
+const REQUIRED_PLACEHOLDERS: &[&str] = &["{{donor}}", "{{gifts}}", "{{total}}", "{{ein}}", "{{statement}}"];
+
+enum TemplateFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+impl TemplateFormat {
+    /// Guesses the format of a template from its file extension, plain text by default.
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()).map(str::to_lowercase).as_deref() {
+            Some("md") | Some("markdown") => TemplateFormat::Markdown,
+            Some("html") | Some("htm") => TemplateFormat::Html,
+            _ => TemplateFormat::Text,
+        }
+    }
+
+    fn escape(&self, value: &str) -> String {
+        match self {
+            TemplateFormat::Html => value
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;"),
+            TemplateFormat::Markdown => value.replace('|', "\\|"),
+            TemplateFormat::Text => value.to_owned(),
+        }
+    }
+
+    /// Lists gifts as a table with their date, amount and platform.
+    fn gifts(&self, gifts: &[&Gift]) -> String {
+        let rows = gifts.iter().map(|x| {
+            (
+                x.date.format("%Y/%m/%d").to_string(),
+                x.amount.to_string(),
+                self.escape(&x.platform),
+            )
+        });
+
+        match self {
+            TemplateFormat::Text => rows
+                .map(|(date, amount, platform)| format!("{}  {:>12}  {}", date, amount, platform))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            TemplateFormat::Markdown => {
+                let mut res = "| Date | Amount | Platform |\n|------|-------:|----------|".to_owned();
+                for (date, amount, platform) in rows {
+                    res.push_str(&format!("\n| {} | {} | {} |", date, amount, platform));
+                }
+                res
+            }
+            TemplateFormat::Html => {
+                let mut res = "<table>\n<tr><th>Date</th><th>Amount</th><th>Platform</th></tr>".to_owned();
+                for (date, amount, platform) in rows {
+                    res.push_str(&format!(
+                        "\n<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        date, amount, platform
+                    ));
+                }
+                res.push_str("\n</table>");
+                res
+            }
+        }
+    }
+}
+
+/// Writes one acknowledgment per donor of the gifts they made during a calendar year, from a
+/// template in which `{{donor}}`, `{{email}}`, `{{year}}`, `{{gifts}}`, `{{total}}`,
+/// `{{organization}}`, `{{ein}}`, `{{statement}}` and `{{date}}` are replaced. Refunded gifts are
+/// listed as negative amounts, and donors who got everything back get no receipt.
+pub fn receipts(
+    ledger_path: PathBuf,
+    year: i32,
+    template_path: PathBuf,
+    output: PathBuf,
+    currency: Option<&str>,
+) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+    if ledger.settings.ein.trim() == "" {
+        return Err(Error::MissingSetting("ein".to_owned()));
+    }
+    if ledger.settings.organization.trim() == "" {
+        return Err(Error::MissingSetting("organization".to_owned()));
+    }
+
+    let template = fs::read_to_string(&template_path)?;
+    for placeholder in REQUIRED_PLACEHOLDERS {
+        if !template.contains(placeholder) {
+            return Err(Error::BadTemplate(format!("missing the {} placeholder", placeholder)));
+        }
+    }
+    let format = TemplateFormat::of(&template_path);
+    let extension = template_path.extension().and_then(|x| x.to_str()).unwrap_or("txt");
+
+    fs::create_dir_all(&output)?;
+    let mut file_names = BTreeSet::new();
+    let mut count = 0;
+    for (name, gifts) in gifts(&ledger, base)? {
+        let gifts: Vec<&Gift> = gifts.iter().filter(|x| x.date.year() == year).collect();
+        let total = Money::sum(base, gifts.iter().map(|x| &x.amount))?;
+        if total.is_negative() || total.is_zero() {
+            continue;
+        }
+
+        let email = ledger.donor(&name).and_then(|x| x.email.clone()).unwrap_or_default();
+        let receipt = template
+            .replace("{{donor}}", &format.escape(&name))
+            .replace("{{email}}", &format.escape(&email))
+            .replace("{{year}}", &year.to_string())
+            .replace("{{gifts}}", &format.gifts(&gifts))
+            .replace("{{total}}", &total.to_string())
+            .replace("{{organization}}", &format.escape(&ledger.settings.organization))
+            .replace("{{ein}}", &format.escape(&ledger.settings.ein))
+            .replace("{{statement}}", &format.escape(NO_GOODS_STATEMENT))
+            .replace("{{date}}", &Utc::now().format("%Y/%m/%d").to_string());
+
+        // Donors whose names only differ in punctuation still get a file each.
+        let slug = file_slug(&name);
+        let mut file_name = format!("{}-{}.{}", year, slug, extension);
+        let mut n = 2;
+        while !file_names.insert(file_name.clone()) {
+            file_name = format!("{}-{}-{}.{}", year, slug, n, extension);
+            n += 1;
+        }
+
+        fs::write(output.join(&file_name), receipt)?;
+        count += 1;
+    }
+
+    println!("Wrote {} receipt(s) for {} to {}.", count, year, output.display());
+    Ok(())
+}
+
+fn file_slug(name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .chars()
+        .map(|x| if x.is_alphanumeric() { x } else { '-' })
+        .collect();
+    let slug = slug.split('-').filter(|x| *x != "").collect::<Vec<_>>().join("-");
+    if slug == "" {
+        "donor".to_owned()
+    } else {
+        slug
+    }
+}
@@ -0,0 +1,231 @@
+use super::CurrencyCode;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    MissingAccount(String),
+    UnmappedAccount(String),
+    DuplicateAccount(String),
+    MissingDonor(String),
+    DuplicateDonor(String),
+    MissingCategory(String),
+    DuplicateCategory(String),
+    MissingTransaction {
+        account: String,
+        index: String,
+    },
+    BadArgument {
+        argument: String,
+        value: String,
+    },
+    MissingArgument(String),
+    NotATransfer,
+    BadCsvRow {
+        line: u64,
+        column: Option<u64>,
+        message: String,
+    },
+    BadAmount {
+        line: u64,
+        column: &'static str,
+        value: String,
+    },
+    BadDate {
+        line: Option<u64>,
+        column: &'static str,
+        value: String,
+    },
+    BadStatement {
+        line: u64,
+        message: String,
+    },
+    MissingRate {
+        from: CurrencyCode,
+        to: CurrencyCode,
+        date: String,
+    },
+    BadRateTable(String),
+    CurrencyMismatch {
+        expected: CurrencyCode,
+        found: CurrencyCode,
+    },
+    AmountOverflow,
+    Unreconciled {
+        account: String,
+        statement: String,
+        cleared: String,
+    },
+    MissingBackup(String),
+    MissingFile(PathBuf),
+    UnknownSetting(String),
+    MissingSetting(String),
+    BadTemplate(String),
+    BadSetting {
+        key: String,
+        value: String,
+    },
+    UnsupportedVersion(String),
+    MalformedLedger(serde_json::Error),
+    MalformedImport(serde_json::Error),
+    MalformedMapping(serde_json::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingAccount(name) => write!(f, "Account `{}` not found in the ledger", name),
+            Error::UnmappedAccount(processor) => write!(
+                f,
+                "No account is set for `{0}`, use `ledger config LEDGER accounts.{0} NAME`",
+                processor
+            ),
+            Error::DuplicateAccount(name) => {
+                write!(f, "An account with name `{}` already exists", name)
+            }
+            Error::MissingDonor(name) => write!(f, "Donor `{}` not found in the registry", name),
+            Error::DuplicateDonor(name) => {
+                write!(f, "`{}` is already the name or an alias of a donor", name)
+            }
+            Error::MissingCategory(name) => write!(f, "Category `{}` not found", name),
+            Error::DuplicateCategory(name) => {
+                write!(f, "A category with name `{}` already exists", name)
+            }
+            Error::MissingTransaction { account, index } => {
+                write!(f, "Transaction `{}` not found in account `{}`", index, account)
+            }
+            Error::BadArgument { argument, value } => {
+                write!(f, "Invalid value `{}` for `{}`", value, argument)
+            }
+            Error::MissingArgument(argument) => {
+                write!(f, "`{}` is required for this kind of transaction", argument)
+            }
+            Error::NotATransfer => write!(f, "Only transfers can be recorded on two accounts"),
+            Error::BadCsvRow {
+                line,
+                column: Some(column),
+                message,
+            } => write!(f, "Invalid CSV row on line {}, column {}: {}", line, column, message),
+            Error::BadCsvRow {
+                line,
+                column: None,
+                message,
+            } => write!(f, "Invalid CSV row on line {}: {}", line, message),
+            Error::BadAmount {
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "Could not parse amount `{}` in column `{}` on line {}",
+                value, column, line
+            ),
+            Error::BadDate {
+                line: Some(line),
+                column,
+                value,
+            } => write!(
+                f,
+                "Could not parse date `{}` in column `{}` on line {}",
+                value, column, line
+            ),
+            Error::BadDate {
+                line: None,
+                column,
+                value,
+            } => write!(
+                f,
+                "Could not parse date `{}` for `{}`, expected format: YYYY/MM/DD HH:MM",
+                value, column
+            ),
+            Error::BadStatement { line, message } => {
+                write!(f, "Invalid bank statement on line {}: {}", line, message)
+            }
+            Error::MissingRate { from, to, date } => write!(
+                f,
+                "No exchange rate from {} to {} on or before {}, add one to the rate table",
+                from, to, date
+            ),
+            Error::BadRateTable(message) => write!(f, "Rate table failed to load: {}", message),
+            Error::CurrencyMismatch { expected, found } => write!(
+                f,
+                "Expected an amount in {}, found one in {}",
+                expected, found
+            ),
+            Error::AmountOverflow => write!(f, "Amount out of range"),
+            Error::Unreconciled {
+                account,
+                statement,
+                cleared,
+            } => write!(
+                f,
+                "The cleared balance of `{}` is {}, but the statement says {}",
+                account, cleared, statement
+            ),
+            Error::MissingBackup(name) => write!(f, "Backup `{}` not found", name),
+            Error::MissingFile(path) => write!(f, "File at `{}` does not exist", path.display()),
+            Error::UnknownSetting(key) => write!(f, "Unknown setting `{}`", key),
+            Error::MissingSetting(key) => write!(
+                f,
+                "The `{0}` setting is required, use `ledger config LEDGER {0} VALUE`",
+                key
+            ),
+            Error::BadTemplate(message) => write!(f, "Invalid template: {}", message),
+            Error::BadSetting { key, value } => {
+                write!(f, "Invalid value `{}` for setting `{}`", value, key)
+            }
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported ledger version `{}`, try updating backertrack",
+                version
+            ),
+            Error::MalformedLedger(e) => write!(f, "Malformed ledger file: {}", e),
+            Error::MalformedImport(e) => write!(f, "Malformed import file: {}", e),
+            Error::MalformedMapping(e) => write!(f, "Malformed mapping file: {}", e),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::MalformedLedger(error)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Self {
+        let message = error.to_string();
+        // Only errors reading a row have a position, IO, seek and writer errors do not.
+        let line = match error.position() {
+            Some(position) => position.line(),
+            None => match error.into_kind() {
+                csv::ErrorKind::Io(e) => return Error::Io(e),
+                _ => return Error::Io(io::Error::new(io::ErrorKind::Other, message)),
+            },
+        };
+
+        match error.into_kind() {
+            csv::ErrorKind::Deserialize { err, .. } => Error::BadCsvRow {
+                line,
+                column: err.field().map(|x| x + 1),
+                message: err.kind().to_string(),
+            },
+            _ => Error::BadCsvRow {
+                line,
+                column: None,
+                message,
+            },
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use super::{ExpenseKind, IncomeKind, Ledger, TransactionMetadata, Fee};
+use super::transactions::{format_donation, format_fees};
+use super::rates::report_currency;
+use super::{Error, ExpenseKind, Fee, IncomeKind, Ledger, TransactionMetadata};
 use serde_derive::Serialize;
 use std::path::PathBuf;
 
 #[derive(Serialize)]
 struct ExportRow<'a> {
+    id: &'a str,
     account: &'a str,
     kind: &'a str,
     date: &'a str,
@@ -12,24 +15,61 @@ struct ExportRow<'a> {
     description: &'a str,
     paid_to: &'a str,
     paid_by: &'a str,
+    /// Amount paid or received in another currency than the account's, with its ISO 4217 code.
+    original: &'a str,
+    fx_difference: &'a str,
+    category: &'a str,
+    /// Hex ID of the donation a refund or chargeback gives back, or `unlinked`.
+    refunded_donation: &'a str,
 }
 
-pub fn export(ledger: PathBuf, to: PathBuf) {
-    let ledger = Ledger::load(&ledger).expect("Could not read the ledger file");
-    let mut writer = csv::Writer::from_path(to).expect("Could not open the target file");
+/// Writes every transaction to a CSV file, with amounts in the given currency or the ledger's
+/// base currency, converted on the date of each transaction.
+pub fn export(ledger: PathBuf, to: PathBuf, currency: Option<&str>) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger)?;
+    let base = report_currency(&ledger, currency)?;
+    let mut writer = csv::Writer::from_path(to)?;
 
-    for account in ledger.accounts {
+    for account in &ledger.accounts {
+        let opening_balance = ledger.rates.convert(&account.opening_balance, base, account.opening_date)?;
         writer.serialize(ExportRow {
+            id: "",
             account: &account.name,
             kind: "Opening",
-            amount: &(-account.opening_balance).to_string(),
+            amount: &(-opening_balance).to_string(),
             date: &account.opening_date.to_rfc3339(),
             fees: "",
             description: "",
             paid_to: "",
             paid_by: "",
-        }).expect("Failed to serialize opening");
-        for transaction in account.transactions {
+            original: "",
+            fx_difference: "",
+            category: "",
+            refunded_donation: "",
+        })?;
+        for transaction in &account.transactions {
+            let id = hex::encode(&transaction.id);
+            let convert = |amount| ledger.rates.convert(amount, base, transaction.date);
+
+            let amount = convert(&transaction.amount)?;
+            let fees = transaction
+                .fees
+                .iter()
+                .map(|x| {
+                    Ok(Fee {
+                        amount: convert(&x.amount)?,
+                        towards: x.towards.clone(),
+                        category: x.category.clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let original = match transaction.original {
+                Some(ref x) => format!("{} {}", x.decimal(), x.currency()),
+                None => String::new(),
+            };
+            let fx_difference = ledger.rates.realized_difference(account, transaction, base)?;
+            let category = transaction.category.as_deref().unwrap_or("");
+
             match transaction.meta {
                 TransactionMetadata::Expense {
                     kind: ExpenseKind::General,
@@ -37,15 +77,20 @@ pub fn export(ledger: PathBuf, to: PathBuf) {
                     ..
                 } => {
                     writer.serialize(ExportRow {
+                        id: &id,
                         account: &account.name,
                         kind: "Expense",
-                        amount: &transaction.amount.to_string(),
+                        amount: &amount.to_string(),
                         date: &transaction.date.to_rfc3339(),
-                        fees: &format_fees(&transaction.fees),
+                        fees: &format_fees(&fees),
                         description: &transaction.description,
                         paid_to: towards,
                         paid_by: "",
-                    }).expect("Failed to serialize general expense");
+                        original: &original,
+                        fx_difference: &fx_difference.to_string(),
+                        category,
+                        refunded_donation: "",
+                    })?;
                 }
                 TransactionMetadata::Expense {
                     kind: ExpenseKind::Payout(_),
@@ -53,56 +98,123 @@ pub fn export(ledger: PathBuf, to: PathBuf) {
                     ..
                 } => {
                     writer.serialize(ExportRow {
+                        id: &id,
                         account: &account.name,
                         kind: "Payout",
-                        amount: &transaction.amount.to_string(),
+                        amount: &amount.to_string(),
                         date: &transaction.date.to_rfc3339(),
-                        fees: &format_fees(&transaction.fees),
+                        fees: &format_fees(&fees),
                         description: &transaction.description,
                         paid_to: towards,
                         paid_by: "",
-                    }).expect("Failed to serialize payout expense");
+                        original: &original,
+                        fx_difference: &fx_difference.to_string(),
+                        category,
+                        refunded_donation: "",
+                    })?;
+                }
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Refund { ref donation, .. },
+                    ref towards,
+                    ..
+                }
+                | TransactionMetadata::Expense {
+                    kind: ExpenseKind::Chargeback { ref donation, .. },
+                    ref towards,
+                    ..
+                } => {
+                    let kind = match transaction.meta {
+                        TransactionMetadata::Expense {
+                            kind: ExpenseKind::Chargeback { .. },
+                            ..
+                        } => "Chargeback",
+                        _ => "Refund",
+                    };
+                    writer.serialize(ExportRow {
+                        id: &id,
+                        account: &account.name,
+                        kind,
+                        amount: &amount.to_string(),
+                        date: &transaction.date.to_rfc3339(),
+                        fees: &format_fees(&fees),
+                        description: &transaction.description,
+                        paid_to: towards,
+                        paid_by: "",
+                        original: &original,
+                        fx_difference: &fx_difference.to_string(),
+                        category,
+                        refunded_donation: &format_donation(donation.as_deref().unwrap_or_default()),
+                    })?;
                 }
                 TransactionMetadata::Income {
                     kind: IncomeKind::General,
                     ref from,
                 } => {
                     writer.serialize(ExportRow {
+                        id: &id,
                         account: &account.name,
                         kind: "Income",
-                        amount: &(-transaction.amount).to_string(),
+                        amount: &(-amount).to_string(),
                         date: &transaction.date.to_rfc3339(),
-                        fees: &format_fees(&transaction.fees),
+                        fees: &format_fees(&fees),
                         description: &transaction.description,
                         paid_to: "",
                         paid_by: from,
-                    }).expect("Failed to serialize general income");
+                        original: &original,
+                        fx_difference: &fx_difference.to_string(),
+                        category,
+                        refunded_donation: "",
+                    })?;
                 }
                 TransactionMetadata::Income {
                     kind: IncomeKind::Donation(_),
                     ref from,
                 } => {
                     writer.serialize(ExportRow {
+                        id: &id,
                         account: &account.name,
                         kind: "Donation",
-                        amount: &(-transaction.amount).to_string(),
+                        amount: &(-amount).to_string(),
                         date: &transaction.date.to_rfc3339(),
-                        fees: &format_fees(&transaction.fees),
+                        fees: &format_fees(&fees),
                         description: &transaction.description,
                         paid_to: "",
                         paid_by: from,
-                    }).expect("Failed to serialize donation");
+                        original: &original,
+                        fx_difference: &fx_difference.to_string(),
+                        category,
+                        refunded_donation: "",
+                    })?;
+                }
+                TransactionMetadata::Transfer {
+                    ref from,
+                    ref to,
+                    ..
+                } => {
+                    let outgoing = *from == account.name;
+                    writer.serialize(ExportRow {
+                        id: &id,
+                        account: &account.name,
+                        kind: "Transfer",
+                        amount: &if outgoing {
+                            amount.to_string()
+                        } else {
+                            (-amount).to_string()
+                        },
+                        date: &transaction.date.to_rfc3339(),
+                        fees: &format_fees(&fees),
+                        description: &transaction.description,
+                        paid_to: if outgoing { to } else { "" },
+                        paid_by: if outgoing { "" } else { from },
+                        original: &original,
+                        fx_difference: &fx_difference.to_string(),
+                        category,
+                        refunded_donation: "",
+                    })?;
                 }
             }
         }
     }
-}
 
-fn format_fees(fees: &Vec<Fee>) -> String {
-    let mut res = String::new();
-    for f in fees {
-        res.push_str(&format!("{}[{}];", &f.amount.to_string(), f.towards));
-    }
-    res.pop();
-    res
-}
\ No newline at end of file
+    Ok(())
+}
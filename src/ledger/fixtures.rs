@@ -0,0 +1,76 @@
+//! Accounts and transactions shared by the tests of the ledger modules.
+
+use super::accounts::new_transaction_id;
+use super::{
+    Account, CurrencyCode, DonationID, ExpenseKind, IncomeKind, Ledger, Money, Transaction,
+    TransactionMetadata,
+};
+use chrono::{DateTime, TimeZone, Utc};
+
+pub fn usd(cents: i64) -> Money {
+    Money::from_minor(cents, CurrencyCode::USD)
+}
+
+/// Midnight UTC on the given day.
+pub fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+    Utc.ymd(year, month, day).and_hms(0, 0, 0)
+}
+
+pub fn transaction(amount: i64, date: DateTime<Utc>, meta: TransactionMetadata) -> Transaction {
+    Transaction {
+        id: new_transaction_id(),
+        bank_id: None,
+        date,
+        description: String::new(),
+        amount: usd(amount),
+        meta,
+        fees: Vec::new(),
+        original: None,
+        cleared: false,
+        category: None,
+    }
+}
+
+pub fn transfer(id: u8, from: &str, to: &str, amount: i64, date: DateTime<Utc>) -> Transaction {
+    let meta = TransactionMetadata::Transfer {
+        id: vec![id],
+        from: from.to_owned(),
+        to: to.to_owned(),
+    };
+    transaction(amount, date, meta)
+}
+
+pub fn donation(id: u8, from: &str, amount: i64, date: DateTime<Utc>) -> Transaction {
+    let meta = TransactionMetadata::Income {
+        kind: IncomeKind::Donation(vec![id]),
+        from: from.to_owned(),
+    };
+    transaction(amount, date, meta)
+}
+
+pub fn refund(id: u8, towards: &str, donation: Option<DonationID>, amount: i64, date: DateTime<Utc>) -> Transaction {
+    let meta = TransactionMetadata::Expense {
+        kind: ExpenseKind::Refund { id: vec![id], donation },
+        towards: towards.to_owned(),
+        requester: String::new(),
+    };
+    transaction(amount, date, meta)
+}
+
+pub fn account(name: &str, transactions: Vec<Transaction>) -> Account {
+    Account {
+        name: name.to_owned(),
+        currency: CurrencyCode::USD,
+        opening_date: date(2019, 1, 1),
+        opening_balance: usd(0),
+        transactions,
+        reconciliations: Vec::new(),
+    }
+}
+
+pub fn ledger(accounts: Vec<Account>) -> Ledger {
+    Ledger {
+        accounts,
+        ..Ledger::default()
+    }
+}
@@ -0,0 +1,573 @@
+use super::categories::Rules;
+use super::donors::donor_name;
+use super::rates::RateTable;
+use super::{
+    CurrencyCode, DonationID, Donor, Error, ExpenseKind, IncomeKind, Ledger, Money, Settings, Transaction,
+    TransactionMetadata,
+};
+use chrono::{offset::TimeZone, DateTime, Utc};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use std::fmt;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Transactions gathered by an importer, waiting to be committed to the ledger.
+/// Donations and payouts whose ID is already in the ledger are skipped.
+pub struct Import {
+    account_names: BTreeMap<String, String>,
+    /// Currency of every account of the ledger.
+    ledger_accounts: BTreeMap<String, CurrencyCode>,
+    rates: RateTable,
+    /// Registered donors, whose aliases donations and refunds are recorded under their name.
+    donors: Vec<Donor>,
+    /// Rules of the ledger's categories, which uncategorized transactions and fees are put in by.
+    rules: Rules,
+    known: Vec<Vec<u8>>,
+    donations: Vec<KnownDonation>,
+    accounts: Vec<PendingAccount>,
+    unknown_processors: Vec<(String, usize)>,
+}
+
+/// A donation a refund or chargeback may be linked to.
+struct KnownDonation {
+    id: DonationID,
+    from: String,
+    amount: Money,
+    /// Amount in the currency the donation was made in, if not the account's.
+    original: Option<Money>,
+    date: DateTime<Utc>,
+    refunded: bool,
+}
+
+struct PendingAccount {
+    name: String,
+    transactions: Vec<Transaction>,
+    incoming_transfers: usize,
+    duplicates: usize,
+    total: Money,
+}
+
+impl Import {
+    /// Starts an import using the ledger's account settings, with some entries overridden.
+    pub fn new(ledger: &Ledger, overrides: &[(String, String)]) -> Self {
+        // Platforms supported after the ledger was created fall back to their default account.
+        let mut account_names = Settings::default().accounts;
+        account_names.extend(ledger.settings.accounts.clone());
+        for (processor, account) in overrides {
+            account_names.insert(processor.clone(), account.clone());
+        }
+
+        let mut donations: Vec<KnownDonation> = ledger
+            .accounts
+            .iter()
+            .flat_map(|x| &x.transactions)
+            .filter_map(known_donation)
+            .collect();
+        for refunded in ledger.accounts.iter().flat_map(|x| &x.transactions).filter_map(refunded_donation) {
+            if let Some(x) = donations.iter_mut().find(|x| x.id.as_slice() == refunded) {
+                x.refunded = true;
+            }
+        }
+
+        Import {
+            account_names,
+            ledger_accounts: ledger
+                .accounts
+                .iter()
+                .map(|x| (x.name.clone(), x.currency))
+                .collect(),
+            rates: ledger.rates.clone(),
+            donors: ledger.donors.clone(),
+            rules: Rules::new(&ledger.categories),
+            known: ledger
+                .accounts
+                .iter()
+                .flat_map(|x| &x.transactions)
+                .flat_map(external_ids)
+                .map(|x| x.to_vec())
+                .collect(),
+            donations,
+            accounts: Vec::new(),
+            unknown_processors: Vec::new(),
+        }
+    }
+
+    /// Name of the account a platform or payment processor imports into.
+    pub fn account(&self, processor: &str) -> Result<String, Error> {
+        self.account_names
+            .get(processor)
+            .cloned()
+            .ok_or_else(|| Error::UnmappedAccount(processor.to_owned()))
+    }
+
+    /// Currency of an account of the ledger, which importers parse its amounts in.
+    pub fn currency(&self, account: &str) -> Result<CurrencyCode, Error> {
+        self.ledger_accounts
+            .get(account)
+            .cloned()
+            .ok_or_else(|| Error::MissingAccount(account.to_owned()))
+    }
+
+    /// Queues a transaction on an account, converting it to the account's currency if needed.
+    /// Returns whether it was queued, or skipped because it already is in the ledger.
+    pub fn push(&mut self, account: &str, mut transaction: Transaction) -> Result<bool, Error> {
+        let duplicate = external_ids(&transaction).iter().any(|id| self.is_known(id));
+
+        if duplicate {
+            self.pending(account)?.duplicates += 1;
+            return Ok(false);
+        }
+
+        self.convert(account, &mut transaction)?;
+        match transaction.meta {
+            TransactionMetadata::Income {
+                kind: IncomeKind::Donation(_),
+                ref mut from,
+            } => *from = donor_name(&self.donors, from),
+            TransactionMetadata::Expense {
+                kind: ExpenseKind::Refund { .. },
+                ref mut towards,
+                ..
+            }
+            | TransactionMetadata::Expense {
+                kind: ExpenseKind::Chargeback { .. },
+                ref mut towards,
+                ..
+            } => *towards = donor_name(&self.donors, towards),
+            _ => {}
+        }
+        if transaction.category.is_none() {
+            let counterpart = match transaction.meta {
+                TransactionMetadata::Income { ref from, .. } => from.as_str(),
+                TransactionMetadata::Expense { ref towards, .. } => towards,
+                TransactionMetadata::Transfer { .. } => "",
+            };
+            transaction.category = self.rules.categorize(&[&transaction.description, counterpart]);
+        }
+        for fee in transaction.fees.iter_mut().filter(|x| x.category.is_none()) {
+            fee.category = self.rules.categorize(&[&fee.towards]);
+        }
+
+        for id in external_ids(&transaction) {
+            self.known.push(id.to_vec());
+        }
+        if let Some(donation) = known_donation(&transaction) {
+            self.donations.push(donation);
+        }
+        if let Some(refunded) = refunded_donation(&transaction) {
+            if let Some(x) = self.donations.iter_mut().find(|x| x.id.as_slice() == refunded) {
+                x.refunded = true;
+            }
+        }
+
+        if let TransactionMetadata::Transfer { ref to, .. } = transaction.meta {
+            let amount = self.rates.convert(&transaction.amount, self.currency(to)?, transaction.date)?;
+            let destination = self.pending(to)?;
+            destination.incoming_transfers += 1;
+            destination.total = destination.total.checked_add(&amount)?;
+        }
+
+        let change = transaction.balance_change(account)?;
+        let pending = self.pending(account)?;
+        pending.total = pending.total.checked_add(&change)?;
+        pending.transactions.push(transaction);
+
+        Ok(true)
+    }
+
+    /// Whether a donation, payout, refund or transfer ID is already in the ledger or queued.
+    pub fn is_known(&self, id: &[u8]) -> bool {
+        self.known.iter().any(|x| x.as_slice() == id)
+    }
+
+    /// Finds the donation a refund or chargeback from a donor most likely reverses:
+    /// the latest one not yet refunded before the given date, preferably of the same amount.
+    pub fn find_donation(&self, from: &str, amount: &Money, date: DateTime<Utc>) -> Option<DonationID> {
+        let from = donor_name(&self.donors, from);
+        let candidates: Vec<&KnownDonation> = self
+            .donations
+            .iter()
+            .filter(|x| x.from == from && x.date <= date && !x.refunded)
+            .collect();
+
+        candidates
+            .iter()
+            .filter(|x| x.amount == *amount || x.original.as_ref() == Some(amount))
+            .max_by_key(|x| x.date)
+            .or_else(|| candidates.iter().max_by_key(|x| x.date))
+            .map(|x| x.id.clone())
+    }
+
+    pub fn unknown_processor(&mut self, processor: &str) {
+        if let Some(x) = self
+            .unknown_processors
+            .iter_mut()
+            .find(|x| x.0 == processor)
+        {
+            x.1 += 1;
+        } else {
+            self.unknown_processors.push((processor.to_owned(), 1));
+        }
+    }
+
+    pub fn print_summary(&self) {
+        for account in &self.accounts {
+            println!(
+                "{}: {} new transaction(s), {} incoming transfer(s), {} duplicate(s) skipped, total {}",
+                account.name,
+                account.transactions.len(),
+                account.incoming_transfers,
+                account.duplicates,
+                account.total,
+            );
+        }
+
+        for (processor, count) in &self.unknown_processors {
+            println!(
+                "Unknown processor `{}`: {} transaction(s) skipped",
+                processor, count
+            );
+        }
+    }
+
+    pub fn commit(self, ledger: &mut Ledger) -> Result<(), Error> {
+        for account in self.accounts {
+            for transaction in account.transactions {
+                if let TransactionMetadata::Transfer { .. } = transaction.meta {
+                    ledger.push_transfer(transaction)?;
+                } else {
+                    ledger.account_mut(&account.name)?.transactions.push(transaction);
+                }
+            }
+        }
+
+        ledger.sort_by_date();
+        Ok(())
+    }
+
+    /// Converts the amount and fees of a transaction made in another currency than its account's,
+    /// keeping the amount it was made for as the `original` amount.
+    fn convert(&self, account: &str, transaction: &mut Transaction) -> Result<(), Error> {
+        let currency = self.currency(account)?;
+        if transaction.amount.currency() == currency {
+            return Ok(());
+        }
+
+        let date = transaction.date;
+        let converted = self.rates.convert(&transaction.amount, currency, date)?;
+        transaction.original = Some(std::mem::replace(&mut transaction.amount, converted));
+        for fee in &mut transaction.fees {
+            fee.amount = self.rates.convert(&fee.amount, currency, date)?;
+        }
+        Ok(())
+    }
+
+    fn pending(&mut self, account: &str) -> Result<&mut PendingAccount, Error> {
+        let currency = self.currency(account)?;
+
+        match self.accounts.iter().position(|x| x.name == account) {
+            Some(i) => Ok(&mut self.accounts[i]),
+            None => {
+                self.accounts.push(PendingAccount {
+                    name: account.to_owned(),
+                    transactions: Vec::new(),
+                    incoming_transfers: 0,
+                    duplicates: 0,
+                    total: Money::zero(currency),
+                });
+                Ok(self.accounts.last_mut().expect("Unreachable: pending account"))
+            }
+        }
+    }
+}
+
+/// The bank, donation, payout, refund or transfer IDs an imported transaction is deduplicated on.
+/// Transfer legs matched to a bank statement line have both a transfer and a bank ID.
+fn external_ids(transaction: &Transaction) -> Vec<&[u8]> {
+    let mut ids: Vec<&[u8]> = transaction.bank_id.iter().map(Vec::as_slice).collect();
+    ids.extend(platform_id(transaction));
+    ids
+}
+
+fn platform_id(transaction: &Transaction) -> Option<&[u8]> {
+    match transaction.meta {
+        TransactionMetadata::Income {
+            kind: IncomeKind::Donation(ref id),
+            ..
+        }
+        | TransactionMetadata::Expense {
+            kind: ExpenseKind::Payout(ref id),
+            ..
+        }
+        | TransactionMetadata::Expense {
+            kind: ExpenseKind::Refund { ref id, .. },
+            ..
+        }
+        | TransactionMetadata::Expense {
+            kind: ExpenseKind::Chargeback { ref id, .. },
+            ..
+        }
+        | TransactionMetadata::Transfer { ref id, .. } => Some(id),
+        _ => None,
+    }
+}
+
+fn known_donation(transaction: &Transaction) -> Option<KnownDonation> {
+    match transaction.meta {
+        TransactionMetadata::Income {
+            kind: IncomeKind::Donation(ref id),
+            ref from,
+        } => Some(KnownDonation {
+            id: id.clone(),
+            from: from.clone(),
+            amount: transaction.amount.clone(),
+            original: transaction.original.clone(),
+            date: transaction.date,
+            refunded: false,
+        }),
+        _ => None,
+    }
+}
+
+fn refunded_donation(transaction: &Transaction) -> Option<&[u8]> {
+    match transaction.meta {
+        TransactionMetadata::Expense {
+            kind: ExpenseKind::Refund { ref donation, .. },
+            ..
+        }
+        | TransactionMetadata::Expense {
+            kind: ExpenseKind::Chargeback { ref donation, .. },
+            ..
+        } => donation.as_deref(),
+        _ => None,
+    }
+}
+
+/// Reads every row of a CSV file along with the line it starts on.
+pub fn read_csv<T: DeserializeOwned>(path: &Path) -> Result<Vec<(u64, T)>, Error> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|x| x.line()).unwrap_or(0);
+        rows.push((line, record.deserialize(Some(&headers))?));
+    }
+
+    Ok(rows)
+}
+
+/// Reads every entry of a JSON array along with its position, counted from 1.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Vec<(u64, T)>, Error> {
+    let file = std::fs::File::open(path)?;
+    let entries: Vec<T> = serde_json::from_reader(file).map_err(Error::MalformedImport)?;
+
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| (i as u64 + 1, x))
+        .collect())
+}
+
+/// Deserializes a field that some exports write as a string and others as a number.
+pub fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    struct StringOrNumber;
+
+    impl<'de> Visitor<'de> for StringOrNumber {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a string or a number")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+            Ok(value.to_owned())
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<String, E> {
+            Ok(value.to_string())
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<String, E> {
+            Ok(String::new())
+        }
+    }
+
+    deserializer.deserialize_any(StringOrNumber)
+}
+
+/// Parses an amount in the given currency, which may be negative either with a leading minus
+/// or between parentheses as in accounting exports.
+pub fn parse_amount(
+    value: &str,
+    currency: CurrencyCode,
+    line: u64,
+    column: &'static str,
+) -> Result<Money, Error> {
+    Money::parse(value, currency).ok_or_else(|| Error::BadAmount {
+        line,
+        column,
+        value: value.to_owned(),
+    })
+}
+
+/// Parses the currency of an imported row, the given default if it is empty.
+pub fn parse_currency(
+    value: &str,
+    default: CurrencyCode,
+    line: u64,
+    column: &'static str,
+) -> Result<CurrencyCode, Error> {
+    if value.trim() == "" {
+        return Ok(default);
+    }
+
+    CurrencyCode::parse(value).ok_or_else(|| Error::BadCsvRow {
+        line,
+        column: None,
+        message: format!("unknown currency `{}` in column `{}`", value.trim(), column),
+    })
+}
+
+pub fn parse_date(
+    value: &str,
+    format: &str,
+    line: u64,
+    column: &'static str,
+) -> Result<DateTime<Utc>, Error> {
+    Utc.datetime_from_str(value, format)
+        .map_err(|_| Error::BadDate {
+            line: Some(line),
+            column,
+            value: value.to_owned(),
+        })
+}
+
+/// Parses a UTC date written as RFC 3339, or as `YYYY-MM-DD` with an optional time and `UTC` suffix.
+pub fn parse_any_date(value: &str, line: u64, column: &'static str) -> Result<DateTime<Utc>, Error> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Utc));
+    }
+
+    let trimmed = value.trim().trim_end_matches(" UTC");
+    let date = if trimmed.len() == "YYYY-MM-DD".len() {
+        Utc.datetime_from_str(&format!("{} 00:00:00", trimmed), "%Y-%m-%d %H:%M:%S")
+    } else {
+        Utc.datetime_from_str(trimmed, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| Utc.datetime_from_str(trimmed, "%Y-%m-%d %H:%M"))
+    };
+
+    date.map_err(|_| Error::BadDate {
+        line: Some(line),
+        column,
+        value: value.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+
+    #[test]
+    fn skips_donations_already_in_the_ledger_or_queued() {
+        let ledger = ledger(vec![account("Stripe", vec![donation(1, "Ann", 1000, date(2019, 1, 2))])]);
+        let mut import = Import::new(&ledger, &[]);
+
+        assert!(!import.push("Stripe", donation(1, "Ann", 1000, date(2019, 1, 2))).unwrap());
+        assert!(import.push("Stripe", donation(2, "Ann", 1000, date(2019, 1, 2))).unwrap());
+        assert!(!import.push("Stripe", donation(2, "Ann", 1000, date(2019, 1, 2))).unwrap());
+
+        let pending = &import.accounts[0];
+        assert_eq!(pending.transactions.len(), 1);
+        assert_eq!(pending.duplicates, 2);
+        assert_eq!(pending.total, usd(1000));
+    }
+
+    #[test]
+    fn skips_refunds_already_in_the_ledger_or_queued() {
+        let ledger = ledger(vec![account(
+            "Stripe",
+            vec![donation(1, "Ann", 1000, date(2019, 1, 2)), refund(3, "Ann", Some(vec![1]), 1000, date(2019, 1, 3))],
+        )]);
+        let mut import = Import::new(&ledger, &[]);
+
+        assert!(!import.push("Stripe", refund(3, "Ann", Some(vec![1]), 1000, date(2019, 1, 3))).unwrap());
+        assert!(import.push("Stripe", refund(4, "Ann", None, 500, date(2019, 1, 4))).unwrap());
+        assert!(!import.push("Stripe", refund(4, "Ann", None, 500, date(2019, 1, 4))).unwrap());
+
+        // The donation a refund reverses is imported on its own ID.
+        assert!(import.push("Stripe", donation(7, "Ann", 500, date(2019, 1, 4))).unwrap());
+        assert_eq!(import.accounts[0].transactions.len(), 2);
+        assert_eq!(import.accounts[0].duplicates, 2);
+        assert_eq!(import.accounts[0].total, usd(0));
+    }
+
+    #[test]
+    fn finds_the_latest_donation_not_yet_refunded() {
+        let ledger = ledger(vec![account(
+            "Stripe",
+            vec![
+                donation(1, "Ann", 1000, date(2019, 1, 2)),
+                donation(2, "Ann", 2000, date(2019, 1, 3)),
+                donation(3, "Ann", 1000, date(2019, 1, 4)),
+                donation(4, "Bob", 1000, date(2019, 1, 5)),
+                refund(5, "Ann", Some(vec![3]), 1000, date(2019, 1, 6)),
+            ],
+        )]);
+        let mut import = Import::new(&ledger, &[]);
+
+        // The same amount is preferred, then the latest donation before the refund.
+        assert_eq!(import.find_donation("Ann", &usd(1000), date(2019, 1, 10)), Some(vec![1]));
+        assert_eq!(import.find_donation("Ann", &usd(700), date(2019, 1, 10)), Some(vec![2]));
+        assert_eq!(import.find_donation("Ann", &usd(1000), date(2019, 1, 1)), None);
+        assert_eq!(import.find_donation("Carol", &usd(1000), date(2019, 1, 10)), None);
+
+        // Queued refunds count too.
+        import.push("Stripe", refund(6, "Ann", Some(vec![1]), 1000, date(2019, 1, 7))).unwrap();
+        assert_eq!(import.find_donation("Ann", &usd(1000), date(2019, 1, 10)), Some(vec![2]));
+    }
+
+    #[test]
+    fn records_donations_and_refunds_under_the_donor_name() {
+        let mut ledger = ledger(vec![account("Stripe", vec![donation(1, "Ann Smith", 1000, date(2019, 1, 2))])]);
+        ledger.donors.push(Donor {
+            name: "Ann Smith".to_owned(),
+            aliases: vec!["asmith".to_owned()],
+            email: None,
+        });
+        let mut import = Import::new(&ledger, &[]);
+
+        assert_eq!(import.find_donation("asmith", &usd(1000), date(2019, 1, 10)), Some(vec![1]));
+        import.push("Stripe", donation(2, "asmith", 500, date(2019, 1, 3))).unwrap();
+        import.push("Stripe", refund(3, "ASMITH", Some(vec![1]), 1000, date(2019, 1, 4))).unwrap();
+
+        let transactions = &import.accounts[0].transactions;
+        match (&transactions[0].meta, &transactions[1].meta) {
+            (TransactionMetadata::Income { from, .. }, TransactionMetadata::Expense { towards, .. }) => {
+                assert_eq!(from, "Ann Smith");
+                assert_eq!(towards, "Ann Smith");
+            }
+            _ => panic!("a donation and a refund were pushed"),
+        }
+    }
+
+    #[test]
+    fn records_transfers_and_bank_lines_under_every_id() {
+        let mut leg = transfer(5, "PayPal", "Stripe", 1000, date(2019, 1, 2));
+        leg.bank_id = Some(vec![6]);
+        let import = Import::new(&ledger(vec![account("Stripe", vec![leg])]), &[]);
+
+        assert!(import.is_known(&[5]));
+        assert!(import.is_known(&[6]));
+    }
+}
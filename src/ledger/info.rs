@@ -1,29 +1,77 @@
-use super::{Account, Ledger, TransactionMetadata};
+use super::rates::report_currency;
+use super::{Account, Error, ExpenseKind, IncomeKind, Ledger, Money, TransactionMetadata};
 use std::path::PathBuf;
-use currency::Currency;
 
-pub fn info(ledger: PathBuf, accounts: &str) {
-    let ledger = Ledger::load(&ledger).expect("Could not read the ledger file");
+/// How a transaction counts towards gross receipts, which are the income of the ledger less the
+/// donations given back.
+pub enum Receipt<'a> {
+    Donation { from: &'a str },
+    OtherIncome,
+    /// A refund or chargeback of a donation from the given donor.
+    Refund { towards: &'a str },
+}
+
+impl<'a> Receipt<'a> {
+    pub fn of(meta: &'a TransactionMetadata) -> Option<Self> {
+        match meta {
+            TransactionMetadata::Income {
+                kind: IncomeKind::Donation(_),
+                from,
+            } => Some(Receipt::Donation { from }),
+            TransactionMetadata::Income {
+                kind: IncomeKind::General,
+                ..
+            } => Some(Receipt::OtherIncome),
+            TransactionMetadata::Expense {
+                kind: ExpenseKind::Refund { .. },
+                towards,
+                ..
+            }
+            | TransactionMetadata::Expense {
+                kind: ExpenseKind::Chargeback { .. },
+                towards,
+                ..
+            } => Some(Receipt::Refund { towards }),
+            _ => None,
+        }
+    }
+}
+
+/// Prints totals for some accounts, in the given currency or the ledger's base currency.
+pub fn info(ledger: PathBuf, accounts: &str, currency: Option<&str>) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger)?;
+    let base = report_currency(&ledger, currency)?;
 
     let accounts: Vec<&Account> = accounts
         .split(',')
-        .map(|x| {
-            ledger
-                .get_account(x)
-                .unwrap_or_else(|| panic!("Account `{}` not found in the ledger", x))
-        })
-        .collect();
-
-    let gross_receipts = accounts
-        .iter()
-        .map(|x| &x.transactions)
-        .flatten()
-        .filter(|x| match x.meta {
-            TransactionMetadata::Income { .. } => true,
-            _ => false,
-        })
-        .map(|x| &x.amount)
-        .fold(Currency::from(0, '$'), |acc, x| x + acc);
-
-    println!("Gross receipts: {}", gross_receipts);
+        .map(|x| ledger.account(x))
+        .collect::<Result<_, _>>()?;
+
+    let mut gross_receipts = Money::zero(base);
+    let mut refunds = Money::zero(base);
+    let mut fx_differences = Money::zero(base);
+    for account in accounts {
+        for transaction in &account.transactions {
+            let amount = ledger.rates.convert(&transaction.amount, base, transaction.date)?;
+            fx_differences = fx_differences.checked_add(&ledger.rates.realized_difference(account, transaction, base)?)?;
+
+            match Receipt::of(&transaction.meta) {
+                Some(Receipt::Donation { .. }) | Some(Receipt::OtherIncome) => {
+                    gross_receipts = gross_receipts.checked_add(&amount)?;
+                }
+                Some(Receipt::Refund { .. }) => {
+                    refunds = refunds.checked_add(&amount)?;
+                }
+                None => {}
+            }
+        }
+    }
+
+    println!("Amounts in {}, converted on the date of each transaction.", base);
+    // Refunded and charged back donations were never really received.
+    println!("Refunds and chargebacks: {}", refunds);
+    println!("Gross receipts: {}", gross_receipts.checked_sub(&refunds)?);
+    println!("Realized FX differences: {}", fx_differences);
+
+    Ok(())
 }
@@ -0,0 +1,311 @@
+use super::import::{parse_amount, parse_currency, read_csv, Import};
+use super::{CurrencyCode, Error, Fee, Money};
+use chrono::{offset::TimeZone, DateTime, Utc};
+use crypto::digest::Digest;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Describes how to read the CSV export of a platform without a dedicated importer.
+///
+/// ```json
+/// {
+///     "platform": "Buy Me a Coffee",
+///     "account": "stripe",
+///     "date": "Payment Date",
+///     "date_format": "%Y-%m-%d",
+///     "amount": "Amount",
+///     "fees": [{ "column": "Fee", "towards": "Buy Me a Coffee" }],
+///     "counterparty": "Supporter",
+///     "currency": "Currency",
+///     "id": ["Payment ID"]
+/// }
+/// ```
+#[derive(Deserialize)]
+pub struct Mapping {
+    /// Name of the platform, used in descriptions and to tell its IDs apart from other platforms'.
+    pub platform: String,
+    /// Either a processor from the ledger settings, or the name of an account.
+    pub account: String,
+    /// Account payouts are sent to, the `bank` account from the settings if missing.
+    #[serde(default)]
+    pub destination: Option<String>,
+    pub date: String,
+    pub date_format: String,
+    pub amount: String,
+    #[serde(default)]
+    pub fees: Vec<FeeColumn>,
+    /// Donor of a donation. Unused for payouts.
+    #[serde(default)]
+    pub counterparty: Option<String>,
+    /// ISO 4217 code of the amount and fees of a donation, in the account's currency if missing.
+    /// Unused for payouts, which are in the currency of the platform's account.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Columns that together identify a row. The date, amount and counterparty are used if empty.
+    #[serde(default)]
+    pub id: Vec<String>,
+    #[serde(default)]
+    pub sign: Sign,
+}
+
+#[derive(Deserialize)]
+pub struct FeeColumn {
+    pub column: String,
+    pub towards: String,
+}
+
+/// Sign of the money received by us in the amount column.
+/// Payouts count as received by the destination account.
+#[derive(Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sign {
+    Positive,
+    Negative,
+}
+
+impl Default for Sign {
+    fn default() -> Self {
+        Sign::Positive
+    }
+}
+
+/// A row read through a mapping.
+pub struct MappedRow {
+    pub line: u64,
+    pub hash: Vec<u8>,
+    pub date: DateTime<Utc>,
+    pub raw_date: String,
+    pub amount: Money,
+    pub fees: Vec<Fee>,
+    pub counterparty: String,
+}
+
+impl Mapping {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path)?;
+        serde_json::from_reader(file).map_err(Error::MalformedMapping)
+    }
+
+    /// Account the mapped rows are imported into.
+    pub fn account(&self, import: &Import) -> String {
+        import.account(&self.account).unwrap_or_else(|_| self.account.clone())
+    }
+
+    /// Reads the rows of an export, whose amounts are in the given currency
+    /// unless the mapping has a currency column.
+    pub fn read(&self, data: &Path, currency: CurrencyCode) -> Result<Vec<MappedRow>, Error> {
+        let mut rows = Vec::new();
+
+        for (line, x) in read_csv::<HashMap<String, String>>(data)? {
+            let column = |name: &str| {
+                x.get(name).ok_or_else(|| Error::BadCsvRow {
+                    line,
+                    column: None,
+                    message: format!("missing column `{}`", name),
+                })
+            };
+
+            let raw_date = column(&self.date)?;
+            let date = self.parse_date(raw_date, line)?;
+
+            let currency = match self.currency {
+                Some(ref name) => parse_currency(column(name)?, currency, line, "currency")?,
+                None => currency,
+            };
+
+            let mut amount = parse_amount(column(&self.amount)?, currency, line, "amount")?;
+            if self.sign == Sign::Negative {
+                amount = -amount;
+            }
+
+            let mut fees = Vec::new();
+            for fee in &self.fees {
+                let value = column(&fee.column)?;
+                if value.trim() != "" {
+                    fees.push(Fee {
+                        amount: parse_amount(value, currency, line, "fees")?,
+                        towards: fee.towards.clone(),
+                        category: None,
+                    });
+                }
+            }
+
+            let counterparty = match self.counterparty {
+                Some(ref name) => column(name)?.clone(),
+                None => String::new(),
+            };
+
+            let mut hasher = crypto::sha2::Sha256::new();
+            hasher.input_str("Mapped");
+            hasher.input_str(&self.platform);
+            if !self.id.is_empty() {
+                for name in &self.id {
+                    hasher.input_str(column(name)?);
+                }
+            } else {
+                hasher.input_str(raw_date);
+                hasher.input_str(column(&self.amount)?);
+                hasher.input_str(&counterparty);
+            }
+
+            let mut hash = vec![0; 32];
+            hasher.result(&mut hash);
+
+            rows.push(MappedRow {
+                line,
+                hash,
+                date,
+                raw_date: raw_date.clone(),
+                amount,
+                fees,
+                counterparty,
+            });
+        }
+
+        Ok(rows)
+    }
+
+    /// Dates without a time are taken at midnight UTC.
+    fn parse_date(&self, value: &str, line: u64) -> Result<DateTime<Utc>, Error> {
+        let date = if self.date_format.contains("%H") || self.date_format.contains("%s") {
+            Utc.datetime_from_str(value, &self.date_format)
+        } else {
+            Utc.datetime_from_str(
+                &format!("{} 00:00", value),
+                &format!("{} %H:%M", self.date_format),
+            )
+        };
+
+        date.map_err(|_| Error::BadDate {
+            line: Some(line),
+            column: "date",
+            value: value.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+    use std::env;
+    use std::process;
+
+    const COFFEE: &str = r#"{
+        "platform": "Buy Me a Coffee",
+        "account": "stripe",
+        "date": "Payment Date",
+        "date_format": "%Y-%m-%d",
+        "amount": "Amount",
+        "fees": [{ "column": "Fee", "towards": "Buy Me a Coffee" }],
+        "counterparty": "Supporter",
+        "currency": "Currency",
+        "id": ["Payment ID"]
+    }"#;
+
+    /// Reads an export through a mapping, from a file named after the test.
+    fn read(test: &str, mapping: &str, csv: &str) -> Result<Vec<MappedRow>, Error> {
+        let mapping: Mapping = serde_json::from_str(mapping).unwrap();
+        let path = env::temp_dir().join(format!("backertrack-{}-{}.csv", process::id(), test));
+        fs::write(&path, csv).unwrap();
+        let rows = mapping.read(&path, CurrencyCode::USD);
+        fs::remove_file(&path).unwrap();
+        rows
+    }
+
+    #[test]
+    fn reads_mapped_columns() {
+        let rows = read(
+            "columns",
+            COFFEE,
+            "Payment ID,Payment Date,Supporter,Amount,Fee,Currency\n\
+             p1,2019-03-04,Ann,5.00,0.25,\n\
+             p2,2019-03-05,Bob,\"1,000.00\",,EUR\n",
+        )
+        .unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].date, date(2019, 3, 4));
+        assert_eq!(rows[0].raw_date, "2019-03-04");
+        assert_eq!(rows[0].amount, usd(500));
+        assert_eq!(rows[0].counterparty, "Ann");
+        assert_eq!(rows[0].fees.len(), 1);
+        assert_eq!(rows[0].fees[0].amount, usd(25));
+        assert_eq!(rows[0].fees[0].towards, "Buy Me a Coffee");
+
+
+        // Empty fees are left out, and the currency column overrides the account's currency.
+        let eur = CurrencyCode::parse("EUR").unwrap();
+        assert_eq!(rows[1].amount, Money::from_minor(100_000, eur));
+        assert!(rows[1].fees.is_empty());
+    }
+
+    #[test]
+    fn flips_negative_amounts() {
+        let mapping = r#"{
+            "platform": "Payouts",
+            "account": "stripe",
+            "date": "Date",
+            "date_format": "%d/%m/%Y %H:%M",
+            "amount": "Amount",
+            "sign": "negative"
+        }"#;
+        let rows = read("sign", mapping, "Date,Amount\n31/01/2019 13:45,-12.50\n").unwrap();
+
+        assert_eq!(rows[0].amount, usd(1250));
+        assert_eq!(rows[0].date, Utc.ymd(2019, 1, 31).and_hms(13, 45, 0));
+        assert_eq!(rows[0].counterparty, "");
+    }
+
+    #[test]
+    fn hashes_rows_on_their_id_columns() {
+        let csv = "Payment ID,Payment Date,Supporter,Amount,Fee,Currency\n\
+                   p1,2019-03-04,Ann,5.00,0.25,\n\
+                   p1,2019-03-05,Ann Smith,5.00,0.30,\n\
+                   p2,2019-03-04,Ann,5.00,0.25,\n";
+        let rows = read("id", COFFEE, csv).unwrap();
+        assert_eq!(rows[0].hash, rows[1].hash);
+        assert_ne!(rows[0].hash, rows[2].hash);
+
+        // Without ID columns, rows are told apart by their date, amount and counterparty only.
+        let mapping = COFFEE.replace(r#""id": ["Payment ID"]"#, r#""id": []"#);
+        let rows = read("no-id", &mapping, csv).unwrap();
+        assert_ne!(rows[0].hash, rows[1].hash);
+        assert_eq!(rows[0].hash, rows[2].hash);
+
+        // The same ID on another platform is another row.
+        let mapping = COFFEE.replace("Buy Me a Coffee", "Ko-fi");
+        let other = read("platform", &mapping, csv).unwrap();
+        assert_ne!(rows[0].hash, other[0].hash);
+    }
+
+    #[test]
+    fn reports_the_line_of_bad_rows() {
+        let header = "Payment ID,Payment Date,Supporter,Amount,Fee,Currency\n";
+        let ok = "p1,2019-03-04,Ann,5.00,0.25,\n";
+
+        match read("date", COFFEE, &format!("{}{}p2,03/05/2019,Bob,5.00,,\n", header, ok)) {
+            Err(Error::BadDate { line: Some(3), .. }) => {}
+            _ => panic!("the date should be rejected"),
+        }
+        match read("amount", COFFEE, &format!("{}{}p2,2019-03-05,Bob,five,,\n", header, ok)) {
+            Err(Error::BadAmount { line: 3, column: "amount", .. }) => {}
+            _ => panic!("the amount should be rejected"),
+        }
+        match read("cents", COFFEE, &format!("{}{}p2,2019-03-05,Bob,5.005,,\n", header, ok)) {
+            Err(Error::BadAmount { line: 3, column: "amount", .. }) => {}
+            _ => panic!("the fraction of a cent should be rejected"),
+        }
+        match read("currency", COFFEE, &format!("{}{}p2,2019-03-05,Bob,5.00,,Euro\n", header, ok)) {
+            Err(Error::BadCsvRow { line: 3, .. }) => {}
+            _ => panic!("the currency should be rejected"),
+        }
+        match read("column", COFFEE, "Payment ID,Payment Date,Amount,Fee,Currency\np1,2019-03-04,5.00,,\n") {
+            Err(Error::BadCsvRow { line: 2, message, .. }) => assert_eq!(message, "missing column `Supporter`"),
+            _ => panic!("the missing column should be reported"),
+        }
+    }
+}
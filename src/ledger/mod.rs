@@ -1,19 +1,59 @@
 mod structure;
 pub use self::structure::*;
 
+mod error;
+pub use self::error::Error;
+
+mod import;
+mod mapping;
+
 pub mod accounts;
+pub mod bank;
+pub mod categories;
 pub mod donations;
+pub mod donors;
 pub mod payout;
+pub mod rates;
+pub mod reconcile;
+pub mod report;
+pub mod transactions;
+
+#[cfg(test)]
+mod fixtures;
 
 mod export;
 pub use self::export::export;
 
 mod info;
-pub use self::info::info;
+pub use self::info::{info, Receipt};
+
+mod backup;
+pub use self::backup::restore;
+
+mod config;
+pub use self::config::config;
 
 use std::path::PathBuf;
 
-pub fn new(ledger: PathBuf) {
-    self::structure::Ledger::default().save(&ledger).expect("Failed to save the ledger!");
+pub fn new(ledger: PathBuf) -> Result<(), Error> {
+    self::structure::Ledger::default().save(&ledger)
+}
+
+/// Rewrites a ledger file with the latest schema version.
+pub fn migrate(ledger_path: PathBuf) -> Result<(), Error> {
+    let (ledger, changes) = Ledger::load_migrated(&ledger_path)?;
+
+    if changes.len() == 0 {
+        println!("No changes were needed.");
+    }
+
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    ledger.save(&ledger_path)?;
+    println!("The ledger is now at version {}.", LEDGER_VERSION);
+
+    Ok(())
 }
 
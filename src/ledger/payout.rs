@@ -1,5 +1,7 @@
-use super::{ExpenseKind, IncomeKind, Ledger, PayoutID, Transaction, TransactionMetadata};
-use chrono::TimeZone;
+use super::import::{parse_amount, parse_date, read_csv, Import};
+use super::accounts::new_transaction_id;
+use super::mapping::Mapping;
+use super::{Error, Ledger, Transaction, TransactionMetadata};
 use crypto::digest::Digest;
 use serde_derive::Deserialize;
 use std::path::PathBuf;
@@ -8,6 +10,8 @@ pub enum PayoutOrigin {
     Unknown,
     PayPal,
     Stripe,
+    /// Any CSV export, read through a mapping file.
+    Mapping(PathBuf),
 }
 
 impl From<&str> for PayoutOrigin {
@@ -20,19 +24,34 @@ impl From<&str> for PayoutOrigin {
     }
 }
 
-pub fn payout(ledger_path: PathBuf, data: PathBuf, origin: PayoutOrigin) {
-    let mut ledger = Ledger::load(&ledger_path).expect("Could not read the ledger file");
+pub fn payout(
+    ledger_path: PathBuf,
+    data: PathBuf,
+    origin: PayoutOrigin,
+    overrides: &[(String, String)],
+    dry_run: bool,
+) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let import = Import::new(&ledger, overrides);
+
+    let import = match origin {
+        PayoutOrigin::PayPal => payout_paypal(import, &data)?,
+        PayoutOrigin::Stripe => payout_stripe(import, &data)?,
+        PayoutOrigin::Mapping(ref mapping) => payout_mapped(import, &data, &Mapping::load(mapping)?)?,
+        PayoutOrigin::Unknown => {
+            println!("Unknown origin");
+            return Ok(());
+        }
+    };
 
-    match origin {
-        PayoutOrigin::PayPal => payout_paypal(&mut ledger, &data),
-        PayoutOrigin::Stripe => payout_stripe(&mut ledger, &data),
-        PayoutOrigin::Unknown => println!("Unknown origin"),
+    import.print_summary();
+    if dry_run {
+        println!("Dry run: the ledger was not modified.");
+        return Ok(());
     }
 
-    ledger.sort_by_date();
-    ledger
-        .save(&ledger_path)
-        .expect("Could not save the ledger");
+    import.commit(&mut ledger)?;
+    ledger.save(&ledger_path)
 }
 
 #[derive(Deserialize)]
@@ -44,45 +63,14 @@ struct StripeRow {
     date: String,
 }
 
-fn payout_stripe(ledger: &mut Ledger, data: &PathBuf) {
-    let stripe = ledger
-        .get_account_mut("Stripe")
-        .expect("Account for Stripe not found");
-
-    let known_payouts: Vec<PayoutID> = stripe
-        .transactions
-        .iter()
-        .filter_map(|x| match &x.meta {
-            TransactionMetadata::Expense {
-                kind: ExpenseKind::Payout(x),
-                ..
-            } => Some(x),
-            _ => None,
-        })
-        .map(Clone::clone)
-        .collect();
-
-    let mut payouts: Vec<Transaction> = Vec::new();
-    let mut incomes: Vec<Transaction> = Vec::new();
-
-    let mut reader = csv::Reader::from_path(data).expect("Could not read the CSV file");
-
-    let records = reader.deserialize();
-    for (i, x) in records.enumerate() {
-        let x: StripeRow =
-            x.unwrap_or_else(|e| panic!("Could not deserialize entry on entry {}!\n{}", i, e));
-        let mut amount = currency::Currency::from_str(&x.amount)
-            .unwrap_or_else(|e| panic!("Could not parse payout amount on entry {}!\n{}", i, e));
-        let date = chrono::Utc
-            .datetime_from_str(&x.date, "%Y-%m-%d %H:%M")
-            .unwrap_or_else(|e| {
-                panic!(
-                    "Could not parse payout date `{}` on entry {}!\n{}",
-                    &x.date, i, e
-                )
-            });
-
-        amount.set_symbol('$');
+fn payout_stripe(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    let source = import.account("stripe")?;
+    let destination = import.account("bank")?;
+    let currency = import.currency(&source)?;
+
+    for (line, x) in read_csv::<StripeRow>(data)? {
+        let amount = parse_amount(&x.amount, currency, line, "Amount")?;
+        let date = parse_date(&x.date, "%Y-%m-%d %H:%M", line, "Created (UTC)")?;
 
         let mut hasher = crypto::sha2::Sha256::new();
         hasher.input_str("Stripe");
@@ -91,48 +79,37 @@ fn payout_stripe(ledger: &mut Ledger, data: &PathBuf) {
         let mut hash = vec![0; 32];
         hasher.result(&mut hash);
 
-        if !known_payouts.contains(&hash) {
-            let meta = TransactionMetadata::Expense {
-                kind: ExpenseKind::Payout(hash),
-                towards: "Chase".to_owned(),
-                requester: "Treasurer".to_owned(),
-            };
-
-            payouts.push(Transaction {
-                amount: amount.clone(),
-                date: date.clone(),
-                meta,
-                description: "Payout to the Chase account".to_owned(),
-                fees: Vec::new(),
-            });
-
-            incomes.push(Transaction {
+        let meta = TransactionMetadata::Transfer {
+            id: hash,
+            from: source.clone(),
+            to: destination.clone(),
+        };
+
+        let queued = import.push(
+            &source,
+            Transaction {
+                id: new_transaction_id(),
+                bank_id: None,
+                original: None,
+                cleared: false,
+                category: None,
                 amount,
                 date,
-                description: "Payout from Stripe".to_owned(),
+                meta,
+                description: format!("Stripe payout to the {} account", destination),
                 fees: Vec::new(),
-                meta: TransactionMetadata::Income {
-                    kind: IncomeKind::General,
-                    from: "Stripe payout".to_owned(),
-                },
-            })
-        } else {
+            },
+        )?;
+
+        if !queued {
             println!(
-                "WARNING: Payout from made on {} (entry {}) is already in the ledger.",
-                x.date, i
+                "WARNING: Payout from made on {} (line {}) is already in the ledger.",
+                x.date, line
             );
         }
     }
 
-    payouts.sort_by(|x, y| x.date.cmp(&y.date));
-    stripe.transactions.append(&mut payouts);
-
-    incomes.sort_by(|x, y| x.date.cmp(&y.date));
-    ledger
-        .get_account_mut("Chase")
-        .expect("Account for Chase not found")
-        .transactions
-        .append(&mut incomes);
+    Ok(import)
 }
 
 #[derive(Deserialize)]
@@ -145,45 +122,15 @@ struct PayPalRow {
     date: String,
 }
 
-fn payout_paypal(ledger: &mut Ledger, data: &PathBuf) {
-    let paypal = ledger
-        .get_account_mut("PayPal")
-        .expect("Account for PayPal not found");
-
-    let known_payouts: Vec<PayoutID> = paypal
-        .transactions
-        .iter()
-        .filter_map(|x| match &x.meta {
-            TransactionMetadata::Expense {
-                kind: ExpenseKind::Payout(x),
-                ..
-            } => Some(x),
-            _ => None,
-        })
-        .map(Clone::clone)
-        .collect();
-
-    let mut payouts: Vec<Transaction> = Vec::new();
-    let mut incomes: Vec<Transaction> = Vec::new();
-
-    let mut reader = csv::Reader::from_path(data).expect("Could not read the CSV file");
-
-    let records = reader.deserialize();
-    for (i, x) in records.enumerate() {
-        let x: PayPalRow =
-            x.unwrap_or_else(|e| panic!("Could not deserialize entry on entry {}!\nHave you done the necessary preprocessing for PayPal exported data?\n{}", i, e));
-        let mut amount = currency::Currency::from_str(&x.amount)
-            .unwrap_or_else(|e| panic!("Could not parse payout amount on entry {}!\n{}", i, e));
-        let date = chrono::Utc
-            .datetime_from_str(&format!("{} 00:00", &x.date), "%m/%d/%Y %H:%M")
-            .unwrap_or_else(|e| {
-                panic!(
-                    "Could not parse payout date `{}` on entry {}!\n{}",
-                    &x.date, i, e
-                )
-            });
-
-        amount.set_symbol('$');
+fn payout_paypal(mut import: Import, data: &PathBuf) -> Result<Import, Error> {
+    let source = import.account("paypal")?;
+    let destination = import.account("bank")?;
+    let currency = import.currency(&source)?;
+
+    // PayPal exports need some preprocessing before they can be read as regular CSV.
+    for (line, x) in read_csv::<PayPalRow>(data)? {
+        let amount = parse_amount(&x.amount, currency, line, "Gross")?;
+        let date = parse_date(&format!("{} 00:00", &x.date), "%m/%d/%Y %H:%M", line, "Date")?;
 
         let mut hasher = crypto::sha2::Sha256::new();
         hasher.input_str("PayPal");
@@ -192,48 +139,80 @@ fn payout_paypal(ledger: &mut Ledger, data: &PathBuf) {
         let mut hash = vec![0; 32];
         hasher.result(&mut hash);
 
-        if !known_payouts.contains(&hash) {
-            let amount = -amount;
-
-            let meta = TransactionMetadata::Expense {
-                kind: ExpenseKind::Payout(hash),
-                towards: "Chase".to_owned(),
-                requester: "Treasurer".to_owned(),
-            };
-
-            payouts.push(Transaction {
-                amount: amount.clone(),
-                date: date.clone(),
-                meta,
-                description: "Payout to the Chase account".to_owned(),
-                fees: Vec::new(),
-            });
-
-            incomes.push(Transaction {
+        let amount = -amount;
+
+        let meta = TransactionMetadata::Transfer {
+            id: hash,
+            from: source.clone(),
+            to: destination.clone(),
+        };
+
+        let queued = import.push(
+            &source,
+            Transaction {
+                id: new_transaction_id(),
+                bank_id: None,
+                original: None,
+                cleared: false,
+                category: None,
                 amount,
                 date,
-                description: "Payout from PayPal".to_owned(),
+                meta,
+                description: format!("PayPal payout to the {} account", destination),
                 fees: Vec::new(),
-                meta: TransactionMetadata::Income {
-                    kind: IncomeKind::General,
-                    from: "PayPal payout".to_owned(),
-                },
-            })
-        } else {
+            },
+        )?;
+
+        if !queued {
             println!(
-                "WARNING: Payout from made on {} (entry {}) is already in the ledger.",
-                x.date, i
+                "WARNING: Payout from made on {} (line {}) is already in the ledger.",
+                x.date, line
             );
         }
     }
 
-    payouts.sort_by(|x, y| x.date.cmp(&y.date));
-    paypal.transactions.append(&mut payouts);
+    Ok(import)
+}
+
+/// Mapped payouts are sent from the mapping's account. Their fees are charged on that account.
+fn payout_mapped(mut import: Import, data: &PathBuf, mapping: &Mapping) -> Result<Import, Error> {
+    let source = mapping.account(&import);
+    let destination = match mapping.destination {
+        Some(ref x) => x.clone(),
+        None => import.account("bank")?,
+    };
+    let currency = import.currency(&source)?;
+
+    for x in mapping.read(data, currency)? {
+        let meta = TransactionMetadata::Transfer {
+            id: x.hash,
+            from: source.clone(),
+            to: destination.clone(),
+        };
+
+        let queued = import.push(
+            &source,
+            Transaction {
+                id: new_transaction_id(),
+                bank_id: None,
+                original: None,
+                cleared: false,
+                category: None,
+                amount: x.amount,
+                date: x.date,
+                meta,
+                description: format!("{} payout to the {} account", mapping.platform, destination),
+                fees: x.fees,
+            },
+        )?;
+
+        if !queued {
+            println!(
+                "WARNING: Payout made on {} (line {}) is already in the ledger.",
+                x.raw_date, x.line
+            );
+        }
+    }
 
-    incomes.sort_by(|x, y| x.date.cmp(&y.date));
-    ledger
-        .get_account_mut("Chase")
-        .expect("Account for Chase not found")
-        .transactions
-        .append(&mut incomes);
+    Ok(import)
 }
@@ -0,0 +1,140 @@
+use super::import::read_csv;
+use super::{
+    Account, CurrencyCode, Error, Ledger, Money, Settings, Transaction, TransactionMetadata,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_derive::Deserialize;
+use std::path::Path;
+
+/// Exchange rates read from the CSV file set by the `rates` setting, with the columns
+/// `date,from,to,rate`: one `from` was worth `rate` `to` from `date` (YYYY-MM-DD) onwards.
+#[derive(Clone, Default)]
+pub struct RateTable {
+    rates: Vec<Rate>,
+    /// Why the table could not be loaded, reported by conversions that needed it.
+    failure: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+struct Rate {
+    date: NaiveDate,
+    from: CurrencyCode,
+    to: CurrencyCode,
+    rate: f64,
+}
+
+impl RateTable {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut rates = Vec::new();
+
+        for (line, rate) in read_csv::<Rate>(path)? {
+            if !(rate.rate > 0.0) {
+                return Err(Error::BadCsvRow {
+                    line,
+                    column: None,
+                    message: "rates must be positive".to_owned(),
+                });
+            }
+            rates.push(rate);
+        }
+
+        Ok(RateTable { rates, failure: None })
+    }
+
+    /// An empty table for a file that could not be loaded.
+    pub fn failed(error: &Error) -> Self {
+        RateTable {
+            rates: Vec::new(),
+            failure: Some(error.to_string()),
+        }
+    }
+
+    /// Loads the rate table of a ledger, which is relative to the ledger file.
+    /// A ledger without one can only convert between identical currencies.
+    pub fn for_ledger(settings: &Settings, ledger_path: &Path) -> Result<Self, Error> {
+        if settings.rates == "" {
+            return Ok(RateTable::default());
+        }
+
+        let path = ledger_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&settings.rates);
+        RateTable::load(&path)
+    }
+
+    /// The latest rate from one currency to another known on a date, read both ways.
+    pub fn rate(&self, from: CurrencyCode, to: CurrencyCode, date: DateTime<Utc>) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        self.rates
+            .iter()
+            .filter(|x| x.date <= date.naive_utc().date())
+            .filter_map(|x| {
+                if x.from == from && x.to == to {
+                    Some((x.date, x.rate))
+                } else if x.from == to && x.to == from {
+                    Some((x.date, 1.0 / x.rate))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|x| x.0)
+            .map(|x| x.1)
+    }
+
+    pub fn convert(&self, amount: &Money, to: CurrencyCode, date: DateTime<Utc>) -> Result<Money, Error> {
+        if amount.currency() == to {
+            return Ok(amount.clone());
+        }
+
+        let rate = self.rate(amount.currency(), to, date).ok_or_else(|| match self.failure {
+            Some(ref message) => Error::BadRateTable(message.clone()),
+            None => Error::MissingRate {
+                from: amount.currency(),
+                to,
+                date: date.format("%Y-%m-%d").to_string(),
+            },
+        })?;
+
+        amount.convert(rate, to)
+    }
+
+    /// Gain or loss made by booking a transaction in a foreign currency at another rate than the
+    /// table's, in the base currency. Transactions in the currency of their account have none.
+    pub fn realized_difference(&self, account: &Account, transaction: &Transaction, base: CurrencyCode) -> Result<Money, Error> {
+        let original = match transaction.original {
+            Some(ref x) => x,
+            None => return Ok(Money::zero(base)),
+        };
+
+        let market = self.convert(original, base, transaction.date)?;
+        let booked = self.convert(&transaction.amount, base, transaction.date)?;
+
+        let incoming = match transaction.meta {
+            TransactionMetadata::Income { .. } => true,
+            TransactionMetadata::Expense { .. } => false,
+            TransactionMetadata::Transfer { ref to, .. } => *to == account.name,
+        };
+
+        // Receiving more or paying less than the market value is a gain.
+        if incoming {
+            booked.checked_sub(&market)
+        } else {
+            market.checked_sub(&booked)
+        }
+    }
+}
+
+/// Currency a report is made in: the given ISO 4217 code, or the ledger's base currency.
+pub fn report_currency(ledger: &Ledger, currency: Option<&str>) -> Result<CurrencyCode, Error> {
+    match currency {
+        Some(currency) => CurrencyCode::parse(currency).ok_or_else(|| Error::BadArgument {
+            argument: "currency".to_owned(),
+            value: currency.to_owned(),
+        }),
+        None => Ok(ledger.settings.base_currency),
+    }
+}
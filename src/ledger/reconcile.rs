@@ -0,0 +1,240 @@
+use super::bank::{closing_balance, StatementFormat};
+use super::structure::{CurrencyCode, Ledger, Money, ReconciledTransaction, Reconciliation};
+use super::Error;
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+
+/// Closing balance of a statement, typed by the user or read from a statement file.
+pub enum Statement<'a> {
+    Typed { date: DateTime<Utc>, balance: &'a str },
+    File { path: PathBuf, format: StatementFormat },
+}
+
+impl<'a> Statement<'a> {
+    /// The date of the statement and its balance, in the currency of the account.
+    fn read(self, currency: CurrencyCode) -> Result<(DateTime<Utc>, Money), Error> {
+        match self {
+            Statement::Typed { date, balance } => {
+                let amount = Money::parse(balance, currency).ok_or_else(|| Error::BadArgument {
+                    argument: "balance".to_owned(),
+                    value: balance.to_owned(),
+                })?;
+                Ok((date, amount))
+            }
+            Statement::File { path, format } => closing_balance(&path, format, currency),
+        }
+    }
+}
+
+/// Compares the account with a statement's closing balance.
+pub fn status(ledger_path: PathBuf, account: &str, statement: Statement) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let account = ledger.account(account)?;
+    let (date, balance) = statement.read(account.currency)?;
+
+    let current = account.balance_as_of(date, false)?;
+    let cleared = account.balance_as_of(date, true)?;
+
+    println!("Statement balance on {}: {}", date.format(crate::DATE_FORMAT), balance);
+    println!("Current balance on the same date: {}", current);
+    println!("Cleared balance: {}", cleared);
+    println!("Difference with the cleared balance: {}", balance.checked_sub(&cleared)?);
+
+    let uncleared: Vec<_> = account
+        .transactions
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.date <= date && !t.cleared)
+        .collect();
+    if !uncleared.is_empty() {
+        println!();
+        println!("Uncleared transactions:");
+        for (i, t) in uncleared {
+            println!(
+                "{}: [{}] {} {} {}",
+                i,
+                hex::encode(&t.id),
+                t.date.format(crate::DATE_FORMAT),
+                t.balance_change(&account.name)?,
+                t.description,
+            );
+        }
+    }
+
+    let changes = account.reconciliation_changes();
+    if !changes.is_empty() {
+        println!();
+        println!("Changes to reconciled transactions:");
+        for change in changes {
+            println!("{}", change);
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks transactions as cleared, or as uncleared if `undo` is set.
+/// Each leg of a transfer is cleared on its own account.
+pub fn clear(ledger_path: PathBuf, account: &str, indices: &[&str], undo: bool) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+
+    for index in indices {
+        let (account, position) = ledger.find_transaction(account, index)?;
+        ledger.accounts[account].transactions[position].cleared = !undo;
+    }
+
+    ledger.save(&ledger_path)?;
+    println!(
+        "Marked {} transaction(s) as {}.",
+        indices.len(),
+        if undo { "uncleared" } else { "cleared" }
+    );
+
+    Ok(())
+}
+
+/// Records a reconciliation of the account once its cleared balance matches the statement.
+pub fn finish(ledger_path: PathBuf, account: &str, statement: Statement) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let account = ledger.account_mut(account)?;
+    let (date, balance) = statement.read(account.currency)?;
+
+    let cleared = account.balance_as_of(date, true)?;
+    if cleared != balance {
+        return Err(Error::Unreconciled {
+            account: account.name.clone(),
+            statement: balance.to_string(),
+            cleared: cleared.to_string(),
+        });
+    }
+
+    // The new reconciliation acknowledges the changes made since the last one.
+    for change in account.reconciliation_changes() {
+        println!("{}", change);
+    }
+
+    let transactions: Vec<_> = account
+        .transactions
+        .iter()
+        .filter(|t| t.date <= date && t.cleared)
+        .map(|t| ReconciledTransaction {
+            id: t.id.clone(),
+            fingerprint: t.fingerprint(),
+        })
+        .collect();
+    println!(
+        "Reconciled {} transaction(s) of `{}` up to {}.",
+        transactions.len(),
+        account.name,
+        date.format(crate::DATE_FORMAT)
+    );
+
+    account.reconciliations.push(Reconciliation {
+        date,
+        balance,
+        made_on: Utc::now(),
+        transactions,
+    });
+
+    ledger.save(&ledger_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// Donations to Stripe, cleared but for the one of January 25.
+    fn stripe() -> Ledger {
+        let mut transactions = vec![
+            donation(1, "Ann", 1000, date(2019, 1, 5)),
+            donation(2, "Bob", 500, date(2019, 1, 20)),
+            donation(3, "Cat", 200, date(2019, 1, 25)),
+            donation(4, "Dan", 300, date(2019, 2, 3)),
+        ];
+        for (i, transaction) in transactions.iter_mut().enumerate() {
+            transaction.cleared = i != 2;
+        }
+
+        let mut ledger = ledger(vec![account("Stripe", transactions)]);
+        ledger.settings.backups = 0;
+        ledger
+    }
+
+    /// Reconciles Stripe with a statement of January 31, in a file named after the test.
+    fn reconcile(test: &str, ledger: &Ledger, balance: &str) -> Result<Ledger, Error> {
+        let path = env::temp_dir().join(format!("backertrack-{}-{}.json", process::id(), test));
+        ledger.save(&path).unwrap();
+        let statement = Statement::Typed {
+            date: date(2019, 1, 31),
+            balance,
+        };
+        let reconciled = finish(path.clone(), "Stripe", statement).and_then(|_| Ledger::load(&path));
+        fs::remove_file(&path).unwrap();
+        reconciled
+    }
+
+    #[test]
+    fn records_the_cleared_transactions_up_to_the_statement() {
+        let ledger = stripe();
+        let reconciled = reconcile("finish", &ledger, "15.00").unwrap();
+
+        let account = &reconciled.accounts[0];
+        assert_eq!(account.reconciliations.len(), 1);
+        let reconciliation = &account.reconciliations[0];
+        assert_eq!(reconciliation.date, date(2019, 1, 31));
+        assert_eq!(reconciliation.balance, usd(1500));
+
+        let ids: Vec<_> = reconciliation.transactions.iter().map(|x| &x.id).collect();
+        assert_eq!(ids, vec![&ledger.accounts[0].transactions[0].id, &ledger.accounts[0].transactions[1].id]);
+        assert_eq!(reconciliation.transactions[0].fingerprint, ledger.accounts[0].transactions[0].fingerprint());
+        assert!(account.reconciliation_changes().is_empty());
+    }
+
+    #[test]
+    fn rejects_a_statement_that_differs_from_the_cleared_balance() {
+        // The uncleared donation is not part of the cleared balance.
+        match reconcile("unreconciled", &stripe(), "17.00") {
+            Err(Error::Unreconciled { statement, cleared, .. }) => {
+                assert_eq!(statement, usd(1700).to_string());
+                assert_eq!(cleared, usd(1500).to_string());
+            }
+            _ => panic!("the statement should not be reconciled"),
+        }
+    }
+
+    #[test]
+    fn fingerprints_all_but_the_cleared_flag() {
+        let mut transaction = donation(1, "Ann", 1000, date(2019, 1, 5));
+        let fingerprint = transaction.fingerprint();
+
+        transaction.cleared = true;
+        assert_eq!(transaction.fingerprint(), fingerprint);
+        transaction.description = "Monthly donation".to_owned();
+        assert_ne!(transaction.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn reports_changes_since_the_last_reconciliation() {
+        let mut ledger = reconcile("changes", &stripe(), "15.00").unwrap();
+        let account = &mut ledger.accounts[0];
+
+        // Changes to transactions the reconciliation does not cover are not reported.
+        account.transactions[2].amount = usd(250);
+        account.transactions[3].cleared = false;
+        assert!(account.reconciliation_changes().is_empty());
+
+        account.transactions[0].amount = usd(900);
+        account.transactions[1].cleared = false;
+        let changes = account.reconciliation_changes();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].ends_with(" was changed after the reconciliation of 2019/01/31 00:00"));
+        assert!(changes[1].ends_with(" is no longer cleared after the reconciliation of 2019/01/31 00:00"));
+
+        account.transactions.remove(0);
+        assert!(account.reconciliation_changes()[0].ends_with(" was removed after the reconciliation of 2019/01/31 00:00"));
+    }
+}
@@ -0,0 +1,1044 @@
+use super::donors::{gifts, is_anonymous};
+use super::rates::report_currency;
+use super::{Account, CurrencyCode, Error, ExpenseKind, IncomeKind, Ledger, Money, Receipt, TransactionMetadata};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Period {
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Period {
+    pub const NAMES: &'static [&'static str] = &["month", "quarter", "year"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "month" => Some(Period::Month),
+            "quarter" => Some(Period::Quarter),
+            "year" => Some(Period::Year),
+            _ => None,
+        }
+    }
+
+    /// The period a date falls in, as a sortable key and a label. Quarters and years are fiscal
+    /// ones, starting on the given month. Fiscal years are named after the year they end in.
+    fn of(self, date: DateTime<Utc>, fiscal_year_start: u32) -> ((i32, u32), String) {
+        let start = fiscal_year_start - 1;
+        let fiscal_year = if start != 0 && date.month0() >= start {
+            date.year() + 1
+        } else {
+            date.year()
+        };
+        let fiscal_month = (date.month0() + 12 - start) % 12;
+        let year_label = if start == 0 {
+            fiscal_year.to_string()
+        } else {
+            format!("FY{}", fiscal_year)
+        };
+
+        match self {
+            Period::Month => ((date.year(), date.month0()), format!("{}-{:02}", date.year(), date.month())),
+            Period::Quarter => (
+                (fiscal_year, fiscal_month / 3),
+                format!("{} Q{}", year_label, fiscal_month / 3 + 1),
+            ),
+            Period::Year => ((fiscal_year, 0), year_label),
+        }
+    }
+}
+
+/// Income and expenses of a period, in the currency of the report.
+/// Refunds and chargebacks are given back donations, and fees are those of every transaction.
+struct Totals {
+    donations: Money,
+    refunds: Money,
+    other_income: Money,
+    expenses: Money,
+    payouts: Money,
+    fees: Money,
+}
+
+impl Totals {
+    fn new(currency: CurrencyCode) -> Self {
+        Totals {
+            donations: Money::zero(currency),
+            refunds: Money::zero(currency),
+            other_income: Money::zero(currency),
+            expenses: Money::zero(currency),
+            payouts: Money::zero(currency),
+            fees: Money::zero(currency),
+        }
+    }
+
+    fn add(&mut self, other: &Totals) -> Result<(), Error> {
+        self.donations = self.donations.checked_add(&other.donations)?;
+        self.refunds = self.refunds.checked_add(&other.refunds)?;
+        self.other_income = self.other_income.checked_add(&other.other_income)?;
+        self.expenses = self.expenses.checked_add(&other.expenses)?;
+        self.payouts = self.payouts.checked_add(&other.payouts)?;
+        self.fees = self.fees.checked_add(&other.fees)?;
+        Ok(())
+    }
+
+    fn net(&self) -> Result<Money, Error> {
+        self.donations
+            .checked_sub(&self.refunds)?
+            .checked_add(&self.other_income)?
+            .checked_sub(&self.expenses)?
+            .checked_sub(&self.payouts)?
+            .checked_sub(&self.fees)
+    }
+
+    fn columns(&self) -> [&Money; 6] {
+        [
+            &self.donations,
+            &self.refunds,
+            &self.other_income,
+            &self.expenses,
+            &self.payouts,
+            &self.fees,
+        ]
+    }
+}
+
+#[derive(Serialize)]
+struct IncomeRow<'a> {
+    period: &'a str,
+    currency: &'a str,
+    donations: String,
+    refunds: String,
+    other_income: String,
+    expenses: String,
+    payouts: String,
+    fees: String,
+    net: String,
+}
+
+const INCOME_HEADERS: &[&str] = &[
+    "Period",
+    "Donations",
+    "Refunds",
+    "Other income",
+    "Expenses",
+    "Payouts",
+    "Fees",
+    "Net",
+];
+
+/// Prints the income and expenses of some accounts by period, or their net income by category,
+/// converted to the given currency or the ledger's base currency on the date of each transaction.
+/// Transfers between accounts are left out, but their fees are not.
+pub fn income(
+    ledger_path: PathBuf,
+    accounts: &str,
+    period: Period,
+    currency: Option<&str>,
+    csv: bool,
+    by_category: bool,
+) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+
+    let accounts: Vec<&Account> = accounts
+        .split(',')
+        .map(|x| ledger.account(x))
+        .collect::<Result<_, _>>()?;
+    if by_category {
+        return income_by_category(&ledger, &accounts, period, base, csv);
+    }
+
+    let fiscal_year_start = ledger.settings.fiscal_year_start()?;
+    let mut periods: BTreeMap<(i32, u32), (String, Totals)> = BTreeMap::new();
+    for account in &accounts {
+        for transaction in &account.transactions {
+            let (key, label) = period.of(transaction.date, fiscal_year_start);
+            let totals = &mut periods.entry(key).or_insert_with(|| (label, Totals::new(base))).1;
+
+            let amount = ledger.rates.convert(&transaction.amount, base, transaction.date)?;
+            let column = match transaction.meta {
+                TransactionMetadata::Income {
+                    kind: IncomeKind::Donation(_),
+                    ..
+                } => Some(&mut totals.donations),
+                TransactionMetadata::Income {
+                    kind: IncomeKind::General,
+                    ..
+                } => Some(&mut totals.other_income),
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::General,
+                    ..
+                } => Some(&mut totals.expenses),
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Payout(_),
+                    ..
+                } => Some(&mut totals.payouts),
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Refund { .. },
+                    ..
+                }
+                | TransactionMetadata::Expense {
+                    kind: ExpenseKind::Chargeback { .. },
+                    ..
+                } => Some(&mut totals.refunds),
+                TransactionMetadata::Transfer { .. } => None,
+            };
+            if let Some(column) = column {
+                *column = column.checked_add(&amount)?;
+            }
+
+            for fee in &transaction.fees {
+                let fee = ledger.rates.convert(&fee.amount, base, transaction.date)?;
+                totals.fees = totals.fees.checked_add(&fee)?;
+            }
+        }
+    }
+
+    let mut total = Totals::new(base);
+    for (_, totals) in periods.values() {
+        total.add(totals)?;
+    }
+    let mut periods: Vec<(String, Totals)> = periods.into_iter().map(|(_, x)| x).collect();
+    periods.push(("Total".to_owned(), total));
+
+    if csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        for (label, totals) in &periods {
+            writer.serialize(IncomeRow {
+                period: label,
+                currency: base.as_str(),
+                donations: totals.donations.decimal(),
+                refunds: totals.refunds.decimal(),
+                other_income: totals.other_income.decimal(),
+                expenses: totals.expenses.decimal(),
+                payouts: totals.payouts.decimal(),
+                fees: totals.fees.decimal(),
+                net: totals.net()?.decimal(),
+            })?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    let mut rows: Vec<Vec<String>> = vec![INCOME_HEADERS.iter().map(|x| x.to_string()).collect()];
+    for (label, totals) in &periods {
+        let mut row = vec![label.clone()];
+        row.extend(totals.columns().iter().map(|x| x.to_string()));
+        row.push(totals.net()?.to_string());
+        rows.push(row);
+    }
+
+    let names: Vec<&str> = accounts.iter().map(|x| x.name.as_str()).collect();
+    println!(
+        "Income statement of {}, in {} converted on the date of each transaction.",
+        names.join(", "),
+        base
+    );
+    println!();
+    print_table(&rows);
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct CategoryRow<'a> {
+    period: &'a str,
+    category: &'a str,
+    parent: &'a str,
+    currency: &'a str,
+    net: String,
+}
+
+const UNCATEGORIZED: &str = "Uncategorized";
+
+/// Amounts by category, `None` being uncategorized or every category, and by period, `None` being
+/// every period.
+type CategoryTotals<'a> = BTreeMap<(Option<&'a str>, Option<(i32, u32)>), Money>;
+
+fn known_category<'a>(ledger: &Ledger, name: &'a Option<String>) -> Option<&'a str> {
+    name.as_deref().filter(|x| ledger.category(x).is_ok())
+}
+
+fn add_to<K: Ord>(totals: &mut BTreeMap<K, Money>, key: K, amount: &Money) -> Result<(), Error> {
+    let total = totals.entry(key).or_insert_with(|| Money::zero(amount.currency()));
+    *total = total.checked_add(amount)?;
+    Ok(())
+}
+
+/// Prints the net income of some accounts by category and period: income is positive, and
+/// expenses, refunds, payouts and fees negative. Fees are counted in their own category.
+/// In the table, categories include their subcategories. In CSV, they do not.
+fn income_by_category(
+    ledger: &Ledger,
+    accounts: &[&Account],
+    period: Period,
+    base: CurrencyCode,
+    csv: bool,
+) -> Result<(), Error> {
+    let fiscal_year_start = ledger.settings.fiscal_year_start()?;
+    let mut periods: BTreeMap<(i32, u32), String> = BTreeMap::new();
+    // Uncategorized amounts, and those of categories no longer in the ledger, are under `None`.
+    let mut own: BTreeMap<(Option<&str>, (i32, u32)), Money> = BTreeMap::new();
+
+    for account in accounts {
+        for transaction in &account.transactions {
+            let (key, label) = period.of(transaction.date, fiscal_year_start);
+            periods.entry(key).or_insert(label);
+
+            let amount = ledger.rates.convert(&transaction.amount, base, transaction.date)?;
+            match transaction.meta {
+                TransactionMetadata::Income { .. } => add_to(&mut own, (known_category(ledger, &transaction.category), key), &amount)?,
+                TransactionMetadata::Expense { .. } => {
+                    add_to(&mut own, (known_category(ledger, &transaction.category), key), &-amount)?
+                }
+                TransactionMetadata::Transfer { .. } => {}
+            }
+
+            for fee in &transaction.fees {
+                let amount = ledger.rates.convert(&fee.amount, base, transaction.date)?;
+                add_to(&mut own, (known_category(ledger, &fee.category), key), &-amount)?;
+            }
+        }
+    }
+
+    let tree = ledger.category_tree();
+    if csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        for (key, label) in &periods {
+            let rows = tree
+                .iter()
+                .map(|x| (Some(x.0.name.as_str()), x.0.parent.as_deref()))
+                .chain(Some((None, None)));
+            for (name, parent) in rows {
+                if let Some(net) = own.get(&(name, *key)) {
+                    writer.serialize(CategoryRow {
+                        period: label,
+                        category: name.unwrap_or(UNCATEGORIZED),
+                        parent: parent.unwrap_or(""),
+                        currency: base.as_str(),
+                        net: net.decimal(),
+                    })?;
+                }
+            }
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    // Amounts of categories and their subcategories, with the total of every period under `None`.
+    let mut rolled_up: CategoryTotals = BTreeMap::new();
+    for ((name, key), amount) in &own {
+        let ancestry = name.map(|x| ledger.category_ancestry(x)).unwrap_or_else(Vec::new);
+        for name in ancestry.into_iter().map(Some).chain(Some(None)) {
+            add_to(&mut rolled_up, (name, Some(*key)), amount)?;
+            add_to(&mut rolled_up, (name, None), amount)?;
+        }
+    }
+
+    let mut header = vec!["Category".to_owned()];
+    header.extend(periods.values().cloned());
+    header.push("Total".to_owned());
+    let mut rows = vec![header];
+    let mut push_row = |label: String, name: Option<&str>, amounts: &CategoryTotals| {
+        let mut row = vec![label];
+        row.extend(periods.keys().map(|key| {
+            amounts
+                .get(&(name, Some(*key)))
+                .map(Money::to_string)
+                .unwrap_or_default()
+        }));
+        row.push(amounts.get(&(name, None)).map(Money::to_string).unwrap_or_default());
+        rows.push(row);
+    };
+
+    for (category, depth) in &tree {
+        let name = Some(category.name.as_str());
+        if rolled_up.contains_key(&(name, None)) {
+            push_row(format!("{}{}", "  ".repeat(*depth), category.name), name, &rolled_up);
+        }
+    }
+    let uncategorized: BTreeMap<_, _> = own
+        .iter()
+        .filter(|x| (x.0).0.is_none())
+        .map(|((_, key), amount)| ((None, Some(*key)), amount.clone()))
+        .collect();
+    if !uncategorized.is_empty() {
+        let mut uncategorized = uncategorized;
+        let total = Money::sum(base, uncategorized.values())?;
+        uncategorized.insert((None, None), total);
+        push_row(UNCATEGORIZED.to_owned(), None, &uncategorized);
+    }
+    push_row("Total".to_owned(), None, &rolled_up);
+
+    let names: Vec<&str> = accounts.iter().map(|x| x.name.as_str()).collect();
+    println!(
+        "Net income of {} by category, in {} converted on the date of each transaction.",
+        names.join(", "),
+        base
+    );
+    println!();
+    print_table(&rows);
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct BalanceRow<'a> {
+    account: &'a str,
+    opening_date: String,
+    currency: &'a str,
+    opening_balance: String,
+    balance: String,
+    converted_balance: String,
+}
+
+const BALANCES_HEADERS: &[&str] = &["Account", "Opened", "Opening balance", "Balance", "Converted"];
+
+/// Prints the balance of every account opened by the given date, leaving out later transactions,
+/// and their sum in the given currency or the ledger's base currency, converted on that date.
+pub fn balances(ledger_path: PathBuf, as_of: DateTime<Utc>, currency: Option<&str>, csv: bool) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+
+    let mut balances = Vec::new();
+    let mut total = Money::zero(base);
+    for account in ledger.accounts.iter().filter(|x| x.opening_date <= as_of) {
+        let balance = account.balance_as_of(as_of, false)?;
+        let converted = ledger.rates.convert(&balance, base, as_of)?;
+        total = total.checked_add(&converted)?;
+        balances.push((account, balance, converted));
+    }
+
+    if csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        for (account, balance, converted) in &balances {
+            writer.serialize(BalanceRow {
+                account: &account.name,
+                opening_date: account.opening_date.format(crate::DATE_FORMAT).to_string(),
+                currency: account.currency.as_str(),
+                opening_balance: account.opening_balance.decimal(),
+                balance: balance.decimal(),
+                converted_balance: converted.decimal(),
+            })?;
+        }
+        writer.serialize(BalanceRow {
+            account: "Total",
+            opening_date: String::new(),
+            currency: base.as_str(),
+            opening_balance: String::new(),
+            balance: String::new(),
+            converted_balance: total.decimal(),
+        })?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    let mut rows: Vec<Vec<String>> = vec![BALANCES_HEADERS.iter().map(|x| x.to_string()).collect()];
+    for (account, balance, converted) in &balances {
+        rows.push(vec![
+            account.name.clone(),
+            account.opening_date.format(crate::DATE_FORMAT).to_string(),
+            account.opening_balance.to_string(),
+            balance.to_string(),
+            converted.to_string(),
+        ]);
+    }
+    rows.push(vec![
+        "Total".to_owned(),
+        String::new(),
+        String::new(),
+        String::new(),
+        total.to_string(),
+    ]);
+
+    println!(
+        "Balances as of {}, converted to {} on that date.",
+        as_of.format(crate::DATE_FORMAT),
+        base
+    );
+    println!();
+    print_table(&rows);
+
+    Ok(())
+}
+
+/// Lines of Part I of Form 990-EZ that transactions can be reported on. Lines 1 to 8 are revenue,
+/// and lines 10 to 16 expenses. The others are totals.
+pub const FORM990_LINES: &[(u32, &str)] = &[
+    (1, "Contributions, gifts, grants, and similar amounts received"),
+    (2, "Program service revenue including government fees and contracts"),
+    (3, "Membership dues and assessments"),
+    (4, "Investment income"),
+    (8, "Other revenue"),
+    (10, "Grants and similar amounts paid"),
+    (11, "Benefits paid to or for members"),
+    (12, "Salaries, other compensation, and employee benefits"),
+    (13, "Professional fees and other payments to independent contractors"),
+    (14, "Occupancy, rent, utilities, and maintenance"),
+    (15, "Printing, publications, postage, and shipping"),
+    (16, "Other expenses"),
+];
+
+const OTHER_REVENUE: u32 = 8;
+const OTHER_EXPENSES: u32 = 16;
+
+/// Description of a line transactions can be reported on.
+pub fn form990_line(line: u32) -> Option<&'static str> {
+    FORM990_LINES.iter().find(|x| x.0 == line).map(|x| x.1)
+}
+
+/// A transaction or fee reported on a line, converted to the currency of the report.
+struct LineItem<'a> {
+    date: DateTime<Utc>,
+    account: &'a str,
+    id: &'a [u8],
+    description: String,
+    amount: Money,
+}
+
+/// First and last instants of the fiscal year named after the calendar year it ends in.
+fn fiscal_year(year: i32, fiscal_year_start: u32) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start_year = if fiscal_year_start == 1 { year } else { year - 1 };
+    let start = Utc.ymd(start_year, fiscal_year_start, 1).and_hms(0, 0, 0);
+    let end = Utc.ymd(start_year + 1, fiscal_year_start, 1).and_hms(0, 0, 0) - Duration::seconds(1);
+    (start, end)
+}
+
+/// Prints the lines of Part I of Form 990-EZ for a fiscal year of the whole ledger, and the
+/// transactions reported on each of them.
+///
+/// Donations are contributions, less refunds and chargebacks. Other income and expenses go on the
+/// line their category or account is mapped to in the `form990` settings, or on other revenue and
+/// other expenses, as do fees unless their category or `fees` is mapped. Transfers between
+/// accounts are left out, but
+/// their fees are not. Net assets are the balances of every account, converted on the first and
+/// last day of the year. What they changed by besides the excess or deficit, such as payouts to
+/// accounts outside the ledger and exchange rate differences, is reported as other changes.
+pub fn form990(ledger_path: PathBuf, year: i32, currency: Option<&str>) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+    let (start, end) = fiscal_year(year, ledger.settings.fiscal_year_start()?);
+
+    // The line of the first of a category, its parents and a fallback name to be mapped to one of
+    // the lines.
+    let mapped = |category: &Option<String>, fallback: &str, lines: std::ops::RangeInclusive<u32>, default: u32| {
+        let mut names = known_category(&ledger, category)
+            .map(|x| ledger.category_ancestry(x))
+            .unwrap_or_else(Vec::new);
+        names.push(fallback);
+        names
+            .iter()
+            .filter_map(|x| ledger.settings.form990.get(*x))
+            .cloned()
+            .find(|x| lines.contains(x))
+            .unwrap_or(default)
+    };
+
+    let mut items: BTreeMap<u32, Vec<LineItem>> = BTreeMap::new();
+    for account in &ledger.accounts {
+        for transaction in account.transactions.iter().filter(|x| x.date >= start && x.date <= end) {
+            let income_line = mapped(&transaction.category, &account.name, 1..=8, OTHER_REVENUE);
+            let expenses_line = mapped(&transaction.category, &account.name, 10..=16, OTHER_EXPENSES);
+            let amount = ledger.rates.convert(&transaction.amount, base, transaction.date)?;
+            let item = |description: String, amount: Money| LineItem {
+                date: transaction.date,
+                account: &account.name,
+                id: &transaction.id,
+                description,
+                amount,
+            };
+
+            let line = match transaction.meta {
+                TransactionMetadata::Income {
+                    kind: IncomeKind::Donation(_),
+                    ..
+                } => Some((1, amount)),
+                TransactionMetadata::Income {
+                    kind: IncomeKind::General,
+                    ..
+                } => Some((income_line, amount)),
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Refund { .. },
+                    ..
+                }
+                | TransactionMetadata::Expense {
+                    kind: ExpenseKind::Chargeback { .. },
+                    ..
+                } => Some((1, -amount)),
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::General,
+                    ..
+                } => Some((expenses_line, amount)),
+                // Money moved to an account of ours outside the ledger is neither spent nor
+                // earned, and ends up in the other changes.
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Payout(_),
+                    ..
+                }
+                | TransactionMetadata::Transfer { .. } => None,
+            };
+            if let Some((line, amount)) = line {
+                items
+                    .entry(line)
+                    .or_insert_with(Vec::new)
+                    .push(item(transaction.description.clone(), amount));
+            }
+
+            for fee in &transaction.fees {
+                let amount = ledger.rates.convert(&fee.amount, base, transaction.date)?;
+                let description = if transaction.description == "" {
+                    format!("Fee towards {}", fee.towards)
+                } else {
+                    format!("Fee towards {} on: {}", fee.towards, transaction.description)
+                };
+                let line = mapped(&fee.category, "fees", 10..=16, OTHER_EXPENSES);
+                items.entry(line).or_insert_with(Vec::new).push(item(description, amount));
+            }
+        }
+    }
+
+    let mut totals = BTreeMap::new();
+    for (line, items) in &items {
+        totals.insert(*line, Money::sum(base, items.iter().map(|x| &x.amount))?);
+    }
+    let total = |lines: std::ops::RangeInclusive<u32>| Money::sum(base, totals.range(lines).map(|x| x.1));
+    let revenue = total(1..=8)?;
+    let expenses = total(10..=16)?;
+    let excess = revenue.checked_sub(&expenses)?;
+
+    let net_assets = |date: DateTime<Utc>| -> Result<Money, Error> {
+        let mut total = Money::zero(base);
+        for account in ledger.accounts.iter().filter(|x| x.opening_date <= date) {
+            let balance = ledger.rates.convert(&account.balance_as_of(date, false)?, base, date)?;
+            total = total.checked_add(&balance)?;
+        }
+        Ok(total)
+    };
+    let start_assets = net_assets(start - Duration::seconds(1))?;
+    let end_assets = net_assets(end)?;
+    let other_changes = end_assets.checked_sub(&start_assets)?.checked_sub(&excess)?;
+
+    let mut lines: Vec<(String, &str, Money)> = Vec::new();
+    for (line, description) in FORM990_LINES {
+        lines.push((
+            line.to_string(),
+            description,
+            totals.get(line).cloned().unwrap_or_else(|| Money::zero(base)),
+        ));
+        if *line == OTHER_REVENUE {
+            lines.push(("9".to_owned(), "Total revenue", revenue.clone()));
+        }
+    }
+    lines.push(("17".to_owned(), "Total expenses", expenses));
+    lines.push(("18".to_owned(), "Excess or (deficit) for the year", excess));
+    lines.push(("19".to_owned(), "Net assets at beginning of year", start_assets));
+    lines.push(("20".to_owned(), "Other changes in net assets", other_changes));
+    lines.push(("21".to_owned(), "Net assets at end of year", end_assets));
+
+    println!(
+        "Form 990-EZ, Part I, from {} to {}, in {} converted on the date of each transaction.",
+        start.format("%Y/%m/%d"),
+        end.format("%Y/%m/%d"),
+        base
+    );
+    println!();
+    let width = lines.iter().map(|x| x.1.len()).max().unwrap_or(0);
+    let amount_width = lines.iter().map(|x| x.2.to_string().chars().count()).max().unwrap_or(0);
+    for (line, description, amount) in &lines {
+        println!("{:>2}  {:<3$}  {:>4$}", line, description, amount.to_string(), width, amount_width);
+    }
+
+    let account_width = ledger.accounts.iter().map(|x| x.name.chars().count()).max().unwrap_or(0);
+    for (line, items) in &items {
+        println!();
+        println!("Line {}: {}", line, form990_line(*line).unwrap_or_default());
+        for item in items {
+            println!(
+                "  {}  {:<5$}  {}  {:>12}  {}",
+                item.date.format(crate::DATE_FORMAT),
+                item.account,
+                hex::encode(item.id),
+                item.amount.to_string(),
+                item.description,
+                account_width
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ContributorRow<'a> {
+    donor: &'a str,
+    anonymous: bool,
+    currency: &'a str,
+    total: String,
+    date: String,
+    amount: String,
+    platform: &'a str,
+    description: &'a str,
+}
+
+/// Prints as CSV the contributors of a fiscal year to list on Schedule B of Form 990, with their
+/// total and every gift they made. Refunds and chargebacks are negative gifts.
+///
+/// Under the general rule, contributors gave $5,000 or more. Under the special rule of
+/// organizations meeting the 33 1/3% support test, they also gave 2% or more of all
+/// contributions. Donors without a name, or named as anonymous, are flagged as every anonymous
+/// gift is counted under the same name.
+pub fn schedule_b(ledger_path: PathBuf, year: i32, special_rule: bool, currency: Option<&str>) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+    let (start, end) = fiscal_year(year, ledger.settings.fiscal_year_start()?);
+
+    let mut contributors = Vec::new();
+    let mut contributions = Money::zero(base);
+    for (name, gifts) in gifts(&ledger, base)? {
+        let gifts: Vec<_> = gifts.into_iter().filter(|x| x.date >= start && x.date <= end).collect();
+        if gifts.is_empty() {
+            continue;
+        }
+        let total = Money::sum(base, gifts.iter().map(|x| &x.amount))?;
+        contributions = contributions.checked_add(&total)?;
+        contributors.push((name, total, gifts));
+    }
+
+    let general = ledger
+        .rates
+        .convert(&Money::from_minor(500_000, CurrencyCode::USD), base, end)?;
+    let threshold = schedule_b_threshold(general, &contributions, special_rule)?;
+
+    contributors.retain(|x| x.1.minor() >= threshold.minor());
+    contributors.sort_by(|x, y| y.1.minor().cmp(&x.1.minor()).then_with(|| x.0.cmp(&y.0)));
+
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for (name, total, gifts) in &contributors {
+        for gift in gifts {
+            writer.serialize(ContributorRow {
+                donor: name,
+                anonymous: is_anonymous(name),
+                currency: base.as_str(),
+                total: total.decimal(),
+                date: gift.date.format(crate::DATE_FORMAT).to_string(),
+                amount: gift.amount.decimal(),
+                platform: &gift.platform,
+                description: &gift.description,
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Total a contributor gave at least to be listed on Schedule B, given the general rule's $5,000
+/// in the currency of the contributions.
+fn schedule_b_threshold(general: Money, contributions: &Money, special_rule: bool) -> Result<Money, Error> {
+    if !special_rule {
+        return Ok(general);
+    }
+
+    // 2% of the contributions, rounded up to the next minor unit.
+    let minor = contributions.minor().checked_mul(2).ok_or(Error::AmountOverflow)?;
+    let two_percent = Money::from_minor((minor + 99).div_euclid(100), contributions.currency());
+    Ok(if two_percent.minor() > general.minor() { two_percent } else { general })
+}
+
+/// Share of total support the contributions of a single donor count as public support up to.
+const PUBLIC_SUPPORT_CAP_PERCENT: i64 = 2;
+
+/// Prints the public support test of Schedule A, Part II, over a fiscal year and the four before.
+///
+/// Income is classified as for gross receipts: donations less their refunds and chargebacks are
+/// contributions, and other income is not public support but counts towards total support.
+/// Contributions of a donor beyond 2% of the total support of the five years are not public support,
+/// anonymous contributions being counted in full since they may come from any number of donors.
+pub fn public_support(ledger_path: PathBuf, year: i32, currency: Option<&str>) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let base = report_currency(&ledger, currency)?;
+    let fiscal_year_start = ledger.settings.fiscal_year_start()?;
+    let (start, _) = fiscal_year(year - 4, fiscal_year_start);
+    let (_, end) = fiscal_year(year, fiscal_year_start);
+    let years: Vec<i32> = (year - 4..=year).collect();
+
+    let Support {
+        contributions,
+        other_income,
+        donors,
+    } = support(&ledger, base, year)?;
+
+    let total_contributions = Money::sum(base, contributions.values())?;
+    let total_other_income = Money::sum(base, other_income.values())?;
+    let total_support = total_contributions.checked_add(&total_other_income)?;
+    let cap = public_support_cap(&total_support)?;
+
+    let excesses = excess_contributions(&donors, &cap)?;
+    let total_excess = Money::sum(base, excesses.iter().map(|x| &x.2))?;
+    let public_support = total_contributions.checked_sub(&total_excess)?;
+
+    let label = |year: i32| if fiscal_year_start == 1 { year.to_string() } else { format!("FY{}", year) };
+    let mut header = vec![String::new()];
+    header.extend(years.iter().map(|x| label(*x)));
+    header.push("Total".to_owned());
+
+    let mut rows = vec![header];
+    let mut by_year = |name: &str, values: &BTreeMap<i32, Money>, total: &Money| {
+        let mut row = vec![name.to_owned()];
+        row.extend(values.values().map(|x| x.to_string()));
+        row.push(total.to_string());
+        rows.push(row);
+    };
+    by_year("Contributions", &contributions, &total_contributions);
+    by_year("Other income", &other_income, &total_other_income);
+    let mut total_only = |name: &str, total: &Money| {
+        let mut row = vec![name.to_owned()];
+        row.extend(years.iter().map(|_| String::new()));
+        row.push(total.to_string());
+        rows.push(row);
+    };
+    total_only("Contributions over 2% of total support", &total_excess);
+    total_only("Public support", &public_support);
+    total_only("Total support", &total_support);
+
+    println!(
+        "Public support from {} to {}, in {} converted on the date of each transaction.",
+        start.format("%Y/%m/%d"),
+        end.format("%Y/%m/%d"),
+        base
+    );
+    println!();
+    print_table(&rows);
+    println!();
+
+    if total_support.minor() <= 0 {
+        println!("There is no support to compute a public support percentage from.");
+        return Ok(());
+    }
+    // In hundredths of a percent, rounded down.
+    let percentage = i128::from(public_support.minor()) * 10_000 / i128::from(total_support.minor());
+    println!(
+        "Public support percentage: {}.{:02}% ({} 33 1/3% test)",
+        percentage / 100,
+        percentage % 100,
+        if i128::from(public_support.minor()) * 3 >= i128::from(total_support.minor()) {
+            "meets the"
+        } else {
+            "fails the"
+        }
+    );
+
+    if !excesses.is_empty() {
+        println!();
+        println!("Donors who gave more than 2% of total support, {}:", cap);
+        println!();
+        let mut rows = vec![vec![
+            "Donor".to_owned(),
+            "Contributions".to_owned(),
+            "Over 2%".to_owned(),
+        ]];
+        for (name, total, excess) in &excesses {
+            rows.push(vec![(*name).clone(), total.to_string(), excess.to_string()]);
+        }
+        rows.push(vec![
+            "Total".to_owned(),
+            Money::sum(base, excesses.iter().map(|x| x.1))?.to_string(),
+            total_excess.to_string(),
+        ]);
+        print_table(&rows);
+    }
+
+    Ok(())
+}
+
+/// Support received over a fiscal year and the four before.
+struct Support {
+    /// Contributions by fiscal year.
+    contributions: BTreeMap<i32, Money>,
+    /// Other income by fiscal year.
+    other_income: BTreeMap<i32, Money>,
+    /// Contributions by named donor.
+    donors: BTreeMap<String, Money>,
+}
+
+fn support(ledger: &Ledger, base: CurrencyCode, year: i32) -> Result<Support, Error> {
+    let fiscal_year_start = ledger.settings.fiscal_year_start()?;
+    let (start, _) = fiscal_year(year - 4, fiscal_year_start);
+    let (_, end) = fiscal_year(year, fiscal_year_start);
+
+    let mut contributions: BTreeMap<i32, Money> = (year - 4..=year).map(|x| (x, Money::zero(base))).collect();
+    let mut other_income = contributions.clone();
+    let mut donors: BTreeMap<String, Money> = BTreeMap::new();
+    for account in &ledger.accounts {
+        for transaction in account.transactions.iter().filter(|x| x.date >= start && x.date <= end) {
+            let ((year, _), _) = Period::Year.of(transaction.date, fiscal_year_start);
+            let amount = ledger.rates.convert(&transaction.amount, base, transaction.date)?;
+            let (column, donor, amount) = match Receipt::of(&transaction.meta) {
+                Some(Receipt::Donation { from }) => (&mut contributions, Some(from), amount),
+                Some(Receipt::Refund { towards }) => (&mut contributions, Some(towards), -amount),
+                Some(Receipt::OtherIncome) => (&mut other_income, None, amount),
+                None => continue,
+            };
+
+            let total = column.get_mut(&year).expect("Unreachable: fiscal year of the range");
+            *total = total.checked_add(&amount)?;
+            // Anonymous gifts cannot be told apart by donor, so none of them are capped.
+            if let Some(donor) = donor.filter(|x| !is_anonymous(x)) {
+                let total = donors.entry(ledger.donor_name(donor)).or_insert_with(|| Money::zero(base));
+                *total = total.checked_add(&amount)?;
+            }
+        }
+    }
+
+    Ok(Support {
+        contributions,
+        other_income,
+        donors,
+    })
+}
+
+/// Contributions of a single donor counted as public support at most, rounded down.
+fn public_support_cap(total_support: &Money) -> Result<Money, Error> {
+    let minor = total_support
+        .minor()
+        .checked_mul(PUBLIC_SUPPORT_CAP_PERCENT)
+        .ok_or(Error::AmountOverflow)?;
+    Ok(Money::from_minor(minor.div_euclid(100), total_support.currency()))
+}
+
+/// Donors who gave more than the cap, with their contributions and the part over the cap,
+/// largest excess first.
+fn excess_contributions<'a>(
+    donors: &'a BTreeMap<String, Money>,
+    cap: &Money,
+) -> Result<Vec<(&'a String, &'a Money, Money)>, Error> {
+    let mut excesses = Vec::new();
+    for (name, total) in donors {
+        let excess = total.checked_sub(cap)?;
+        if excess.minor() > 0 {
+            excesses.push((name, total, excess));
+        }
+    }
+    excesses.sort_by(|x, y| y.2.minor().cmp(&x.2.minor()).then_with(|| x.0.cmp(y.0)));
+    Ok(excesses)
+}
+
+/// Prints rows as a table whose first row holds the headers and last row the totals.
+/// The first column is aligned to the left, and the others to the right.
+fn print_table(rows: &[Vec<String>]) {
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| rows.iter().map(|x| x[i].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for (i, row) in rows.iter().enumerate() {
+        if i == rows.len() - 1 {
+            println!("{}", "-".repeat(widths.iter().sum::<usize>() + 2 * (widths.len() - 1)));
+        }
+
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(j, (cell, width))| {
+                if j == 0 {
+                    format!("{:<1$}", cell, width)
+                } else {
+                    format!("{:>1$}", cell, width)
+                }
+            })
+            .collect();
+        println!("{}", cells.join("  "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::fixtures::*;
+
+    #[test]
+    fn lists_contributors_of_5000_dollars_under_the_general_rule() {
+        let threshold = schedule_b_threshold(usd(500_000), &usd(100_000_000), false).unwrap();
+        assert_eq!(threshold, usd(500_000));
+    }
+
+    #[test]
+    fn lists_contributors_of_2_percent_under_the_special_rule() {
+        let threshold = |contributions| schedule_b_threshold(usd(500_000), &usd(contributions), true).unwrap();
+
+        // 2% of $1,000,000.
+        assert_eq!(threshold(100_000_000), usd(2_000_000));
+        // Rounded up to the cent.
+        assert_eq!(threshold(100_000_001), usd(2_000_001));
+        assert_eq!(threshold(100_000_050), usd(2_000_001));
+        // Never less than the general rule's $5,000.
+        assert_eq!(threshold(10_000_000), usd(500_000));
+        assert_eq!(threshold(0), usd(500_000));
+    }
+
+    #[test]
+    fn totals_support_over_five_fiscal_years() {
+        let interest = TransactionMetadata::Income {
+            kind: IncomeKind::General,
+            from: "Chase".to_owned(),
+        };
+        let ledger = ledger(vec![account(
+            "Stripe",
+            vec![
+                donation(1, "Ann", 100_000, date(2014, 6, 1)),
+                donation(2, "Ann", 50_000, date(2015, 6, 1)),
+                donation(3, "Ann", 20_000, date(2019, 6, 1)),
+                refund(4, "Ann", Some(vec![3]), 5_000, date(2019, 6, 1)),
+                donation(5, "Anonymous", 70_000, date(2019, 6, 1)),
+                donation(6, "", 1_000, date(2019, 6, 1)),
+                transaction(300, date(2017, 6, 1), interest),
+            ],
+        )]);
+        let support = support(&ledger, CurrencyCode::USD, 2019).unwrap();
+
+        let years: Vec<_> = support.contributions.keys().cloned().collect();
+        assert_eq!(years, vec![2015, 2016, 2017, 2018, 2019]);
+        assert_eq!(support.contributions[&2015], usd(50_000));
+        assert_eq!(support.contributions[&2019], usd(86_000));
+        assert_eq!(support.other_income[&2017], usd(300));
+
+        // Anonymous gifts count as contributions, but not towards any donor.
+        assert_eq!(support.donors.len(), 1);
+        assert_eq!(support.donors["Ann"], usd(65_000));
+    }
+
+    #[test]
+    fn caps_donors_at_2_percent_of_total_support() {
+        assert_eq!(public_support_cap(&usd(1_000_000)).unwrap(), usd(20_000));
+        // Rounded down to the cent.
+        assert_eq!(public_support_cap(&usd(1_000_049)).unwrap(), usd(20_000));
+        assert_eq!(public_support_cap(&usd(1_000_050)).unwrap(), usd(20_001));
+
+        let mut donors = BTreeMap::new();
+        donors.insert("Ann".to_owned(), usd(25_000));
+        donors.insert("Bob".to_owned(), usd(20_000));
+        donors.insert("Cat".to_owned(), usd(60_000));
+        donors.insert("Dan".to_owned(), usd(5_000));
+        let excesses = excess_contributions(&donors, &usd(20_000)).unwrap();
+
+        let excesses: Vec<_> = excesses.iter().map(|x| (x.0.as_str(), x.1, &x.2)).collect();
+        assert_eq!(excesses, vec![("Cat", &usd(60_000), &usd(40_000)), ("Ann", &usd(25_000), &usd(5_000))]);
+    }
+
+
+    #[test]
+    fn rejects_a_fiscal_year_start_out_of_range() {
+        for month in &[0, 13] {
+            let mut ledger = ledger(Vec::new());
+            ledger.settings.fiscal_year_start = *month;
+            match support(&ledger, CurrencyCode::USD, 2019) {
+                Err(Error::BadSetting { key, .. }) => assert_eq!(key, "fiscal_year_start"),
+                _ => panic!("the fiscal year start should be rejected"),
+            }
+        }
+    }
+}
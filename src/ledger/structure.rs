@@ -1,33 +1,341 @@
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
 use serde_derive::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
-use currency::Currency;
 
-use std::error::Error;
+use super::rates::RateTable;
+use super::{backup, Error};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Neg;
 use std::path::Path;
 use std::fs;
+use std::io;
 
 pub type DonationID = Vec<u8>;
 pub type PayoutID = Vec<u8>;
+pub type TransferID = Vec<u8>;
+pub type RefundID = Vec<u8>;
+pub type TransactionID = Vec<u8>;
+pub type BankID = Vec<u8>;
 
-#[derive(Serialize, Deserialize)]
+/// ISO 4217 code of a currency, such as `USD`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct CurrencyCode([u8; 3]);
+
+impl CurrencyCode {
+    pub const USD: CurrencyCode = CurrencyCode(*b"USD");
+
+    /// Reads a code made of three ASCII letters, in any case.
+    pub fn parse(code: &str) -> Option<Self> {
+        let code = code.trim().as_bytes();
+        if code.len() != 3 || !code.iter().all(u8::is_ascii_alphabetic) {
+            return None;
+        }
+
+        Some(CurrencyCode([
+            code[0].to_ascii_uppercase(),
+            code[1].to_ascii_uppercase(),
+            code[2].to_ascii_uppercase(),
+        ]))
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).expect("Unreachable: currency codes are ASCII")
+    }
+
+    /// Number of digits after the decimal point, which is 2 for most currencies.
+    pub fn exponent(self) -> u32 {
+        match self.as_str() {
+            "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+            | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+            "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+            _ => 2,
+        }
+    }
+
+    /// Symbol amounts are written with in the country of the currency, which importers accept.
+    pub fn symbol(self) -> Option<char> {
+        match self.as_str() {
+            "USD" | "CAD" | "AUD" | "NZD" | "MXN" | "SGD" | "HKD" => Some('$'),
+            "EUR" => Some('€'),
+            "GBP" => Some('£'),
+            "JPY" | "CNY" => Some('¥'),
+            "INR" => Some('₹'),
+            "KRW" => Some('₩'),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CurrencyCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl serde::Serialize for CurrencyCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for CurrencyCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code: String = serde::Deserialize::deserialize(deserializer)?;
+        CurrencyCode::parse(&code)
+            .ok_or_else(|| de::Error::custom(format!("invalid currency code `{}`", code)))
+    }
+}
+
+/// An exact amount of money, counted in minor units of its currency such as cents.
+/// Amounts in different currencies are never added together.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Money {
+    minor: i64,
+    currency: CurrencyCode,
+}
+
+impl Money {
+    pub fn zero(currency: CurrencyCode) -> Self {
+        Money { minor: 0, currency }
+    }
+
+    pub fn from_minor(minor: i64, currency: CurrencyCode) -> Self {
+        Money { minor, currency }
+    }
+
+    pub fn minor(&self) -> i64 {
+        self.minor
+    }
+
+    pub fn currency(&self) -> CurrencyCode {
+        self.currency
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.minor < 0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor == 0
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, Error> {
+        if self.currency != other.currency {
+            return Err(Error::CurrencyMismatch {
+                expected: self.currency,
+                found: other.currency,
+            });
+        }
+
+        Ok(Money {
+            minor: self.minor.checked_add(other.minor).ok_or(Error::AmountOverflow)?,
+            currency: self.currency,
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, Error> {
+        self.checked_add(&-other)
+    }
+
+    /// Adds up amounts that must all be in the given currency.
+    pub fn sum<'a>(currency: CurrencyCode, amounts: impl IntoIterator<Item = &'a Money>) -> Result<Money, Error> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), |acc, x| acc.checked_add(x))
+    }
+
+    /// Converts to another currency at the given rate, rounding half away from zero.
+    pub fn convert(&self, rate: f64, to: CurrencyCode) -> Result<Money, Error> {
+        let scale = 10f64.powi(to.exponent() as i32 - self.currency.exponent() as i32);
+        let minor = (self.minor as f64 * rate * scale).round();
+        if !minor.is_finite() || minor.abs() >= i64::MAX as f64 {
+            return Err(Error::AmountOverflow);
+        }
+
+        Ok(Money {
+            minor: minor as i64,
+            currency: to,
+        })
+    }
+
+    /// Parses an amount written by a person or a platform, such as `12.50`, `-$1,250.00`,
+    /// `(5.00)` or `12.50 USD`. Rejects anything ambiguous instead of rounding it: more decimals
+    /// than the currency has, misplaced thousands separators, or another currency's code or symbol.
+    pub fn parse(value: &str, currency: CurrencyCode) -> Option<Money> {
+        let mut value = value.trim();
+        let mut negative = false;
+
+        if value.starts_with('(') && value.ends_with(')') {
+            value = value[1..value.len() - 1].trim();
+            negative = true;
+        }
+
+        // The currency code may come before or after the amount.
+        if value.len() > 3 && value.is_char_boundary(3) && CurrencyCode::parse(&value[..3]).is_some() {
+            if CurrencyCode::parse(&value[..3]) != Some(currency) {
+                return None;
+            }
+            value = value[3..].trim_start();
+        } else if value.len() > 3
+            && value.is_char_boundary(value.len() - 3)
+            && value[..value.len() - 3].ends_with(' ')
+        {
+            if CurrencyCode::parse(&value[value.len() - 3..]) != Some(currency) {
+                return None;
+            }
+            value = value[..value.len() - 3].trim_end();
+        }
+
+        let mut chars = value.chars().peekable();
+        let mut sign = |chars: &mut std::iter::Peekable<std::str::Chars>| match chars.peek() {
+            Some('-') if !negative => {
+                chars.next();
+                negative = true;
+            }
+            Some('+') => {
+                chars.next();
+            }
+            _ => {}
+        };
+
+        sign(&mut chars);
+        if let Some(&symbol) = chars.peek() {
+            if !symbol.is_ascii_digit() {
+                if Some(symbol) != currency.symbol() {
+                    return None;
+                }
+                chars.next();
+                sign(&mut chars);
+            }
+        }
+
+        let rest: String = chars.collect();
+        let (integer, fraction) = match rest.find('.') {
+            Some(point) => (&rest[..point], &rest[point + 1..]),
+            None => (rest.as_str(), ""),
+        };
+
+        // Thousands separators must separate groups of exactly three digits.
+        let groups: Vec<&str> = integer.split(',').collect();
+        let valid_groups = groups.iter().enumerate().all(|(i, x)| {
+            x.chars().all(|c| c.is_ascii_digit())
+                && if i == 0 { !x.is_empty() && (groups.len() == 1 || x.len() <= 3) } else { x.len() == 3 }
+        });
+        if !valid_groups || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let exponent = currency.exponent() as usize;
+        if fraction.len() > exponent && fraction[exponent..].chars().any(|c| c != '0') {
+            return None;
+        }
+
+        let mut digits = groups.concat();
+        digits.push_str(&fraction[..fraction.len().min(exponent)]);
+        for _ in fraction.len()..exponent {
+            digits.push('0');
+        }
+
+        let minor: i64 = digits.parse().ok()?;
+        Some(Money {
+            minor: if negative { -minor } else { minor },
+            currency,
+        })
+    }
+
+    /// The amount as a plain decimal number, such as `-1250.00`.
+    pub fn decimal(&self) -> String {
+        let exponent = self.currency.exponent() as usize;
+        let digits = format!("{:0width$}", self.minor.abs(), width = exponent + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - exponent);
+        let sign = if self.minor < 0 { "-" } else { "" };
+
+        if exponent == 0 {
+            format!("{}{}", sign, integer)
+        } else {
+            format!("{}{}.{}", sign, integer, fraction)
+        }
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        -&self
+    }
+}
+
+impl<'a> Neg for &'a Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money {
+            minor: -self.minor,
+            currency: self.currency,
+        }
+    }
+}
+
+/// Dollars, euros, pounds and yens are written with their symbol, other currencies with their code
+/// so that US dollars can be told apart from other dollars.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimal = self.decimal();
+        let (sign, digits) = if decimal.starts_with('-') {
+            ("-", &decimal[1..])
+        } else {
+            ("", decimal.as_str())
+        };
+
+        match self.currency.as_str() {
+            "USD" | "EUR" | "GBP" | "JPY" => {
+                let symbol = self.currency.symbol().expect("Unreachable: currency symbol");
+                write!(f, "{}{}{}", sign, symbol, digits)
+            }
+            _ => write!(f, "{}{} {}", sign, digits, self.currency),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Fee {
     pub towards: String,
-    pub amount: Currency,
+    pub amount: Money,
+    /// Left out when missing, so that fees without one are written as they were before categories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum IncomeKind {
     General,
     Donation(DonationID),
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum ExpenseKind {
     General,
     Payout(PayoutID),
+    /// Money given back to a donor, towards whom the expense is made.
+    /// `donation` is `None` when the refunded donation could not be found.
+    Refund {
+        id: RefundID,
+        #[serde(default)]
+        donation: Option<DonationID>,
+    },
+    /// Money taken back from us by the donor's bank after a dispute.
+    Chargeback {
+        id: RefundID,
+        #[serde(default)]
+        donation: Option<DonationID>,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum TransactionMetadata {
     Income {
         kind: IncomeKind,
@@ -38,40 +346,216 @@ pub enum TransactionMetadata {
         towards: String,
         requester: String,
     },
+    /// One leg of a transfer between two accounts of the ledger.
+    /// Both legs carry the same metadata, and only the source leg carries the fees.
+    Transfer {
+        id: TransferID,
+        from: String,
+        to: String,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Transaction {
+    /// Assigned when the transaction is created and kept through edits.
+    /// Each leg of a transfer has its own.
+    pub id: TransactionID,
+    /// Set on transactions imported from a bank statement, which are deduplicated on it.
+    #[serde(default)]
+    pub bank_id: Option<BankID>,
     pub date: DateTime<Utc>,
     pub description: String,
-    pub amount: Currency,
+    pub amount: Money,
     pub meta: TransactionMetadata,
     pub fees: Vec<Fee>,
+    /// Amount actually paid or received when it was not in the currency of the account.
+    /// `amount` and `fees` are then converted to the account's currency.
+    #[serde(default)]
+    pub original: Option<Money>,
+    /// Whether the transaction was seen on a statement of the account.
+    #[serde(default)]
+    pub cleared: bool,
+    /// Name of one of the ledger's categories.
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Account {
     pub name: String,
+    /// Currency the account is kept in. Its amounts are all in this currency.
+    #[serde(default = "default_currency")]
+    pub currency: CurrencyCode,
     pub opening_date: DateTime<Utc>,
-    pub opening_balance: Currency,
+    pub opening_balance: Money,
     pub transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub reconciliations: Vec<Reconciliation>,
+}
+
+/// A statement the cleared transactions of an account were checked against.
+#[derive(Serialize, Deserialize)]
+pub struct Reconciliation {
+    /// Date of the statement's closing balance.
+    pub date: DateTime<Utc>,
+    pub balance: Money,
+    /// When the reconciliation was made.
+    pub made_on: DateTime<Utc>,
+    pub transactions: Vec<ReconciledTransaction>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReconciledTransaction {
+    pub id: TransactionID,
+    /// `Transaction::fingerprint` at the time of the reconciliation.
+    pub fingerprint: Vec<u8>,
+}
+
+/// A category income and expenses are totalled under, within its parent category if it has one.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Category {
+    pub name: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Case insensitive regular expressions. Imported transactions whose description or
+    /// counterpart matches one, and fees whose payee does, are put in the category.
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+/// A person or organization giving to us, possibly under several names across platforms.
+/// Donations are recorded under the donor's name, and imported ones are matched onto it
+/// through the aliases.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Donor {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Amount of timestamped backups kept next to the ledger file.
+    pub backups: usize,
+    /// Name of the account each platform or payment processor imports into.
+    /// The `bank` entry is the account payouts are sent to.
+    pub accounts: BTreeMap<String, String>,
+    /// Currency reports are made in, and new accounts are kept in.
+    pub base_currency: CurrencyCode,
+    /// Path to the exchange rate table, relative to the ledger file. See `RateTable`.
+    pub rates: String,
+    /// Month the fiscal year starts in, from 1 for January to 12. Fiscal years are named after
+    /// the calendar year they end in.
+    pub fiscal_year_start: u32,
+    /// Name of the organization, as written on donor receipts.
+    pub organization: String,
+    /// Employer Identification Number of the organization, as written on donor receipts.
+    pub ein: String,
+    /// Line of Part I of Form 990-EZ general income and expenses are reported on, by category name
+    /// or account name, the category or its closest mapped parent taking precedence. Fees are
+    /// reported on the line of their category, or the one of the `fees` entry.
+    /// See `report::FORM990_LINES`.
+    pub form990: BTreeMap<String, u32>,
+}
+
+fn default_currency() -> CurrencyCode {
+    CurrencyCode::USD
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let mut accounts = BTreeMap::new();
+        accounts.insert("stripe".to_owned(), "Stripe".to_owned());
+        accounts.insert("paypal".to_owned(), "PayPal".to_owned());
+        accounts.insert("opencollective".to_owned(), "Stripe".to_owned());
+        accounts.insert("githubsponsors".to_owned(), "Stripe".to_owned());
+        accounts.insert("patreon".to_owned(), "Stripe".to_owned());
+        accounts.insert("bank".to_owned(), "Chase".to_owned());
+
+        Settings {
+            backups: 10,
+            accounts,
+            base_currency: default_currency(),
+            rates: String::new(),
+            fiscal_year_start: 1,
+            organization: String::new(),
+            ein: String::new(),
+            form990: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct Ledger {
     pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub donors: Vec<Donor>,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    #[serde(default)]
+    pub settings: Settings,
+    /// Loaded from the file set in the settings.
+    #[serde(skip)]
+    pub rates: RateTable,
+}
+
+#[derive(Serialize)]
+struct VersionedLedger<'a> {
+    version: u64,
+    #[serde(flatten)]
+    ledger: &'a Ledger,
 }
 
 impl Ledger {
-    pub fn load(from: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
-        let file = fs::File::open(from)?;
-        let ledger = serde_json::from_reader(file)?;
-        Ok(ledger)
+    pub fn load(from: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::load_migrated(from)?.0)
     }
 
-    pub fn save(&self, to: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
-        let file = fs::File::create(to)?;
-        serde_json::to_writer(file, self)?;
+    /// Loads a ledger, upgrading it to the latest schema version if needed.
+    /// Also returns a description of every change made by the migrations.
+    pub fn load_migrated(from: impl AsRef<Path>) -> Result<(Self, Vec<String>), Error> {
+        let file = fs::File::open(from.as_ref())?;
+        let mut value: Value = serde_json::from_reader(file)?;
+        let changes = migrate(&mut value)?;
+        let mut ledger: Ledger = serde_json::from_value(value)?;
+
+        // A broken rate table must not keep the ledger, and the `rates` setting, from being edited.
+        // Conversions report it instead.
+        ledger.rates = RateTable::for_ledger(&ledger.settings, from.as_ref()).unwrap_or_else(|e| {
+            eprintln!("WARNING: Could not load the exchange rate table: {}", e);
+            RateTable::failed(&e)
+        });
+
+        Ok((ledger, changes))
+    }
+
+    /// Writes the ledger to a temporary file first and then moves it into place,
+    /// so that a failure halfway through never leaves a truncated ledger behind.
+    pub fn save(&self, to: impl AsRef<Path>) -> Result<(), Error> {
+        let to = to.as_ref();
+        let temp = backup::temp_path(to);
+
+        let versioned = VersionedLedger {
+            version: LEDGER_VERSION,
+            ledger: self,
+        };
+        let written = fs::File::create(&temp).and_then(|mut file| {
+            serde_json::to_writer(&mut file, &versioned).map_err(io::Error::from)?;
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+
+        if to.exists() {
+            backup::backup(to, self.settings.backups)?;
+        }
+
+        fs::rename(&temp, to)?;
         Ok(())
     }
 
@@ -80,4 +564,651 @@ impl Ledger {
             account.sort_by_date();
         }
     }
-}
\ No newline at end of file
+}
+
+/// Upgrades a serialized ledger from one schema version to the next,
+/// returning a description of every change it made.
+type Migration = fn(&mut Value) -> Vec<String>;
+
+/// The migration at index `n` upgrades a ledger from version `n` to version `n + 1`.
+/// Ledgers written before versioning was introduced have no version and are version 0.
+const MIGRATIONS: &[Migration] = &[
+    link_legacy_payouts,
+    assign_transaction_ids,
+    exact_money_amounts,
+    unlink_missing_donations,
+];
+
+pub const LEDGER_VERSION: u64 = MIGRATIONS.len() as u64;
+
+fn migrate(ledger: &mut Value) -> Result<Vec<String>, Error> {
+    let version = match ledger.get("version") {
+        Some(x) => x.as_u64().ok_or_else(|| Error::UnsupportedVersion(x.to_string()))?,
+        None => 0,
+    };
+
+    if version > LEDGER_VERSION {
+        return Err(Error::UnsupportedVersion(version.to_string()));
+    }
+
+    let mut changes = Vec::new();
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        for change in migration(ledger) {
+            changes.push(format!("v{} to v{}: {}", i, i + 1, change));
+        }
+    }
+
+    if let Some(ledger) = ledger.as_object_mut() {
+        ledger.insert("version".to_owned(), json!(LEDGER_VERSION));
+    }
+
+    Ok(changes)
+}
+
+/// Payouts used to be recorded as a payout expense on the platform account and an unrelated
+/// general income on the bank account. This turns every such pair into the two legs of a transfer.
+fn link_legacy_payouts(ledger: &mut Value) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    let accounts = match ledger.get_mut("accounts").and_then(Value::as_array_mut) {
+        Some(x) => x,
+        None => return changes,
+    };
+
+    let names: Vec<String> = accounts
+        .iter()
+        .map(|x| x["name"].as_str().unwrap_or_default().to_owned())
+        .collect();
+
+    let mut payouts = Vec::new();
+    for (a, account) in accounts.iter().enumerate() {
+        for (t, txn) in account["transactions"].as_array().into_iter().flatten().enumerate() {
+            let expense = &txn["meta"]["Expense"];
+            if let (Some(id), Some(towards)) = (expense["kind"].get("Payout"), expense["towards"].as_str()) {
+                payouts.push((a, t, id.clone(), towards.to_owned()));
+            }
+        }
+    }
+
+    for (a, t, id, towards) in payouts {
+        let b = match names.iter().position(|x| *x == towards) {
+            Some(b) => b,
+            None => continue,
+        };
+
+        let date = accounts[a]["transactions"][t]["date"].clone();
+        let amount = accounts[a]["transactions"][t]["amount"].clone();
+        let leg = accounts[b]["transactions"].as_array().and_then(|txns| {
+            txns.iter().position(|x| {
+                x["date"] == date && x["amount"] == amount && x["meta"]["Income"]["kind"] == "General"
+            })
+        });
+
+        if let Some(leg) = leg {
+            let meta = json!({
+                "Transfer": {
+                    "id": id,
+                    "from": names[a],
+                    "to": towards,
+                }
+            });
+            accounts[a]["transactions"][t]["meta"] = meta.clone();
+            accounts[b]["transactions"][leg]["meta"] = meta;
+            changes.push(format!(
+                "Linked the payout from `{}` to `{}` on {} into a transfer",
+                names[a],
+                towards,
+                date.as_str().unwrap_or_default()
+            ));
+        }
+    }
+
+    changes
+}
+
+/// Transactions used to have no identity of their own. This gives each of them an ID.
+fn assign_transaction_ids(ledger: &mut Value) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    for account in ledger.get_mut("accounts").and_then(Value::as_array_mut).into_iter().flatten() {
+        let name = account["name"].as_str().unwrap_or_default().to_owned();
+        let mut assigned = 0;
+
+        for txn in account["transactions"].as_array_mut().into_iter().flatten() {
+            if let Some(txn) = txn.as_object_mut() {
+                txn.insert("id".to_owned(), json!(super::accounts::new_transaction_id()));
+                assigned += 1;
+            }
+        }
+
+        if assigned != 0 {
+            changes.push(format!("Assigned an ID to {} transaction(s) of `{}`", assigned, name));
+        }
+    }
+
+    changes
+}
+
+/// Amounts used to be stored by the `currency` crate, as a number of hundredths and a symbol that
+/// could not tell US dollars from other dollars. This stores them as exact minor units of the
+/// currency of their account, and refreshes the fingerprints of reconciled transactions.
+fn exact_money_amounts(ledger: &mut Value) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    for account in ledger.get_mut("accounts").and_then(Value::as_array_mut).into_iter().flatten() {
+        let name = account["name"].as_str().unwrap_or_default().to_owned();
+        let currency = account["currency"]
+            .as_str()
+            .and_then(CurrencyCode::parse)
+            .unwrap_or(CurrencyCode::USD);
+        let mut converted = 0;
+        let mut convert = |value: &mut Value, currency: CurrencyCode| {
+            if let Some(money) = legacy_money(value, currency) {
+                *value = json!(money);
+                converted += 1;
+            }
+        };
+
+        convert(&mut account["opening_balance"], currency);
+        for txn in account["transactions"].as_array_mut().into_iter().flatten() {
+            convert(&mut txn["amount"], currency);
+            for fee in txn["fees"].as_array_mut().into_iter().flatten() {
+                convert(&mut fee["amount"], currency);
+            }
+
+            // Foreign amounts were stored along with the code of their currency.
+            let original = txn["original"]["currency"].as_str().and_then(CurrencyCode::parse);
+            if let Some(original) = original {
+                let amount = txn["original"]["amount"].take();
+                txn["original"] = amount;
+                convert(&mut txn["original"], original);
+            }
+        }
+        for reconciliation in account.get_mut("reconciliations").and_then(Value::as_array_mut).into_iter().flatten() {
+            convert(&mut reconciliation["balance"], currency);
+        }
+
+        if converted != 0 {
+            changes.push(format!("Converted {} amount(s) of `{}` to exact {} amounts", converted, name, currency));
+        }
+
+        // Fingerprints hash amounts, which just changed representation.
+        let fingerprints: Vec<(Value, Vec<u8>)> = account["transactions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|x| {
+                let fields = json!([x["date"], x["description"], x["amount"], x["meta"], x["fees"]]);
+                (x["id"].clone(), super::accounts::fingerprint(&fields))
+            })
+            .collect();
+        let mut refreshed = 0;
+        for reconciliation in account.get_mut("reconciliations").and_then(Value::as_array_mut).into_iter().flatten() {
+            for reconciled in reconciliation["transactions"].as_array_mut().into_iter().flatten() {
+                if let Some((_, fingerprint)) = fingerprints.iter().find(|x| x.0 == reconciled["id"]) {
+                    reconciled["fingerprint"] = json!(fingerprint);
+                    refreshed += 1;
+                }
+            }
+        }
+
+        if refreshed != 0 {
+            changes.push(format!(
+                "Refreshed the fingerprints of {} reconciled transaction(s) of `{}`, earlier changes to them are no longer reported",
+                refreshed, name
+            ));
+        }
+    }
+
+    changes
+}
+
+/// Refunds and chargebacks whose donation could not be found used to be linked to an empty
+/// donation ID. This leaves them unlinked instead, and refreshes the fingerprints of those
+/// reconciled since, unless they were changed after their reconciliation.
+fn unlink_missing_donations(ledger: &mut Value) -> Vec<String> {
+    const DONATIONS: [&str; 2] = [
+        "/meta/Expense/kind/Refund/donation",
+        "/meta/Expense/kind/Chargeback/donation",
+    ];
+    let mut changes = Vec::new();
+
+    for account in ledger.get_mut("accounts").and_then(Value::as_array_mut).into_iter().flatten() {
+        let name = account["name"].as_str().unwrap_or_default().to_owned();
+
+        let mut unlinked = Vec::new();
+        for txn in account["transactions"].as_array_mut().into_iter().flatten() {
+            let empty = DONATIONS
+                .iter()
+                .find(|x| txn.pointer(x).and_then(Value::as_array).map_or(false, Vec::is_empty));
+            if let Some(pointer) = empty {
+                let before = json_fingerprint(txn);
+                if let Some(donation) = txn.pointer_mut(pointer) {
+                    *donation = Value::Null;
+                }
+                unlinked.push((txn["id"].clone(), before, json_fingerprint(txn)));
+            }
+        }
+
+        if unlinked.len() != 0 {
+            changes.push(format!(
+                "Unlinked {} refund(s) of `{}` from an empty donation ID",
+                unlinked.len(),
+                name
+            ));
+        }
+
+        for reconciliation in account.get_mut("reconciliations").and_then(Value::as_array_mut).into_iter().flatten() {
+            for reconciled in reconciliation["transactions"].as_array_mut().into_iter().flatten() {
+                let found = unlinked
+                    .iter()
+                    .find(|x| x.0 == reconciled["id"] && json!(x.1) == reconciled["fingerprint"]);
+                if let Some((_, _, fingerprint)) = found {
+                    reconciled["fingerprint"] = json!(fingerprint);
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+/// `Transaction::fingerprint` of a transaction the migrations only have as JSON.
+fn json_fingerprint(txn: &Value) -> Vec<u8> {
+    let mut fees = txn["fees"].clone();
+    for fee in fees.as_array_mut().into_iter().flatten().filter_map(Value::as_object_mut) {
+        fee.remove("category");
+    }
+
+    let fields = json!([txn["date"], txn["description"], txn["amount"], txn["meta"], fees]);
+    super::accounts::fingerprint(&fields)
+}
+
+/// Reads an amount of the `currency` crate, whose `coin` is a number of hundredths. It was
+/// serialized either as a number, a string, or a big integer as a sign and 32-bit digits.
+fn legacy_money(value: &Value, currency: CurrencyCode) -> Option<Money> {
+    let coin = &value.get("coin")?;
+    let hundredths = match coin {
+        Value::Number(x) => x.as_i64().or_else(|| x.as_f64().map(|x| x.round() as i64))?,
+        Value::String(x) => x.trim().parse().ok()?,
+        Value::Array(x) if x.len() == 2 => {
+            let sign = x[0].as_i64()?;
+            let mut magnitude: i64 = 0;
+            for digit in x[1].as_array()?.iter().rev() {
+                magnitude = magnitude.checked_mul(1 << 32)?.checked_add(digit.as_i64()?)?;
+            }
+            sign * magnitude
+        }
+        _ => return None,
+    };
+
+    let minor = match currency.exponent() {
+        0 => (hundredths as f64 / 100.0).round() as i64,
+        exponent => hundredths.checked_mul(10i64.pow(exponent - 2))?,
+    };
+    Some(Money::from_minor(minor, currency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    const EUR: CurrencyCode = CurrencyCode(*b"EUR");
+    const JPY: CurrencyCode = CurrencyCode(*b"JPY");
+    const BHD: CurrencyCode = CurrencyCode(*b"BHD");
+
+    fn payout_ledger() -> Value {
+        let payout = |date: &str, amount: i64| {
+            json!({
+                "date": date,
+                "description": "Payout",
+                "amount": { "symbol": "$", "coin": amount },
+                "meta": { "Expense": { "kind": { "Payout": [7] }, "towards": "Chase", "requester": "" } },
+                "fees": [],
+            })
+        };
+        let income = |date: &str, amount: i64, kind: Value| {
+            json!({
+                "date": date,
+                "description": "Deposit",
+                "amount": { "symbol": "$", "coin": amount },
+                "meta": { "Income": { "kind": kind, "from": "Stripe" } },
+                "fees": [],
+            })
+        };
+
+        json!({
+            "accounts": [
+                {
+                    "name": "Stripe",
+                    "transactions": [payout("2019-01-02T00:00:00Z", 1000), payout("2019-01-03T00:00:00Z", 2000)],
+                },
+                {
+                    "name": "Chase",
+                    "transactions": [
+                        income("2019-01-02T00:00:00Z", 1000, json!({ "Donation": [1] })),
+                        income("2019-01-02T00:00:00Z", 1000, json!("General")),
+                        income("2019-01-03T00:00:00Z", 2500, json!("General")),
+                    ],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn link_legacy_payouts_turns_matching_pairs_into_transfers() {
+        let mut ledger = payout_ledger();
+
+        let changes = link_legacy_payouts(&mut ledger);
+        assert_eq!(
+            changes,
+            vec!["Linked the payout from `Stripe` to `Chase` on 2019-01-02T00:00:00Z into a transfer".to_owned()]
+        );
+
+        let transfer = json!({ "Transfer": { "id": [7], "from": "Stripe", "to": "Chase" } });
+        let stripe = &ledger["accounts"][0]["transactions"];
+        let chase = &ledger["accounts"][1]["transactions"];
+        assert_eq!(stripe[0]["meta"], transfer);
+        assert_eq!(chase[1]["meta"], transfer);
+        // Donations and deposits of another amount are not payouts.
+        assert!(chase[0]["meta"]["Income"].is_object());
+        assert!(chase[2]["meta"]["Income"].is_object());
+        assert!(stripe[1]["meta"]["Expense"].is_object());
+    }
+
+    #[test]
+    fn link_legacy_payouts_skips_payouts_to_unknown_accounts() {
+        let mut ledger = payout_ledger();
+        ledger["accounts"][1]["name"] = json!("Wells Fargo");
+        let before = ledger.clone();
+
+        assert!(link_legacy_payouts(&mut ledger).is_empty());
+        assert_eq!(ledger, before);
+    }
+
+    #[test]
+    fn migrate_starts_from_the_ledger_version() {
+        let mut ledger = payout_ledger();
+        ledger["version"] = json!(LEDGER_VERSION);
+        let mut expected = ledger.clone();
+
+        assert!(migrate(&mut ledger).unwrap().is_empty());
+        assert_eq!(ledger, expected);
+
+        // Unversioned ledgers go through every migration.
+        let mut ledger = payout_ledger();
+        let changes = migrate(&mut ledger).unwrap();
+        assert!(changes[0].starts_with("v0 to v1: Linked the payout"));
+        assert_eq!(ledger["version"], json!(LEDGER_VERSION));
+
+        expected["version"] = json!(LEDGER_VERSION + 1);
+        match migrate(&mut expected) {
+            Err(Error::UnsupportedVersion(_)) => {}
+            _ => panic!("a ledger from a newer version must not be loaded"),
+        }
+    }
+
+    #[test]
+    fn assign_transaction_ids_gives_every_transaction_its_own_id() {
+        let mut ledger = payout_ledger();
+
+        let changes = assign_transaction_ids(&mut ledger);
+        assert_eq!(
+            changes,
+            vec![
+                "Assigned an ID to 2 transaction(s) of `Stripe`".to_owned(),
+                "Assigned an ID to 3 transaction(s) of `Chase`".to_owned(),
+            ]
+        );
+
+        let ids: Vec<&Value> = ledger["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|x| x["transactions"].as_array().unwrap())
+            .map(|x| &x["id"])
+            .collect();
+        assert_eq!(ids.len(), 5);
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(id.as_array().map(Vec::len), Some(16));
+            assert!(!ids[i + 1..].contains(id));
+        }
+    }
+
+    fn minor(value: &str, currency: CurrencyCode) -> Option<i64> {
+        Money::parse(value, currency).map(|x| x.minor())
+    }
+
+    #[test]
+    fn parses_plain_amounts() {
+        assert_eq!(minor("12.50", CurrencyCode::USD), Some(1250));
+        assert_eq!(minor("12.5", CurrencyCode::USD), Some(1250));
+        assert_eq!(minor("12", CurrencyCode::USD), Some(1200));
+        assert_eq!(minor(" 0.07 ", CurrencyCode::USD), Some(7));
+        assert_eq!(minor("12.500", CurrencyCode::USD), Some(1250));
+        assert_eq!(minor("1000", JPY), Some(1000));
+        assert_eq!(minor("1.234", BHD), Some(1234));
+    }
+
+    #[test]
+    fn parses_thousands_separators() {
+        assert_eq!(minor("1,250.00", CurrencyCode::USD), Some(125_000));
+        assert_eq!(minor("12,345,678", CurrencyCode::USD), Some(1_234_567_800));
+        assert_eq!(minor("1,000", JPY), Some(1000));
+    }
+
+    #[test]
+    fn parses_signs_and_parentheses() {
+        assert_eq!(minor("-5.00", CurrencyCode::USD), Some(-500));
+        assert_eq!(minor("+5.00", CurrencyCode::USD), Some(500));
+        assert_eq!(minor("-$1,250.00", CurrencyCode::USD), Some(-125_000));
+        assert_eq!(minor("$-5", CurrencyCode::USD), Some(-500));
+        assert_eq!(minor("(5.00)", CurrencyCode::USD), Some(-500));
+        assert_eq!(minor("($5.00)", CurrencyCode::USD), Some(-500));
+    }
+
+    #[test]
+    fn parses_currency_codes_and_symbols() {
+        assert_eq!(minor("12.50 USD", CurrencyCode::USD), Some(1250));
+        assert_eq!(minor("USD 12.50", CurrencyCode::USD), Some(1250));
+        assert_eq!(minor("usd12.50", CurrencyCode::USD), Some(1250));
+        assert_eq!(minor("-12.50 EUR", EUR), Some(-1250));
+        assert_eq!(minor("€12.50", EUR), Some(1250));
+    }
+
+    #[test]
+    fn rejects_ambiguous_amounts() {
+        for value in &[
+            "", "$", "abc", "12.505", "1.2.3", "12,50", "1,25.00", "1,2345", ",100", "1,000,", "--5",
+            "(-5)", "5-", "12.50 EUR", "EUR 12.50", "€12.50", "12.50 US", "1 000",
+        ] {
+            assert_eq!(minor(value, CurrencyCode::USD), None, "`{}` should be rejected", value);
+        }
+        assert_eq!(minor("1000.5", JPY), None);
+        assert_eq!(minor("$12.50", EUR), None);
+    }
+
+    #[test]
+    fn parses_what_it_displays() {
+        for money in &[
+            Money::from_minor(-125_050, CurrencyCode::USD),
+            Money::from_minor(7, EUR),
+            Money::from_minor(1000, JPY),
+            Money::from_minor(-1234, BHD),
+            Money::from_minor(1250, CurrencyCode(*b"CAD")),
+        ] {
+            assert_eq!(Money::parse(&money.to_string(), money.currency()).as_ref(), Some(money));
+            assert_eq!(Money::parse(&money.decimal(), money.currency()).as_ref(), Some(money));
+        }
+    }
+
+    fn legacy_transaction(id: u8, amount: Value) -> Value {
+        json!({
+            "id": [id],
+            "date": "2019-01-01T00:00:00Z",
+            "description": "",
+            "amount": amount,
+            "meta": { "Income": { "kind": "General", "from": "Ann" } },
+            "fees": [{ "towards": "Stripe", "amount": { "symbol": "$", "coin": "30" } }],
+        })
+    }
+
+    #[test]
+    fn exact_money_amounts_reads_every_legacy_encoding() {
+        let mut ledger = json!({
+            "accounts": [{
+                "name": "Stripe",
+                "opening_date": "2019-01-01T00:00:00Z",
+                "opening_balance": { "symbol": "$", "coin": 0 },
+                "transactions": [
+                    legacy_transaction(1, json!({ "symbol": "$", "coin": 1250 })),
+                    legacy_transaction(2, json!({ "symbol": "$", "coin": "-500" })),
+                    legacy_transaction(3, json!({ "symbol": "$", "coin": [1, [1, 1]] })),
+                    legacy_transaction(4, json!({ "symbol": "$", "coin": [-1, [700]] })),
+                ],
+            }],
+        });
+
+        let changes = exact_money_amounts(&mut ledger);
+        assert_eq!(changes, vec!["Converted 9 amount(s) of `Stripe` to exact USD amounts".to_owned()]);
+
+        let account: Account = serde_json::from_value(ledger["accounts"][0].take()).unwrap();
+        assert_eq!(account.currency, CurrencyCode::USD);
+        assert_eq!(account.opening_balance, Money::zero(CurrencyCode::USD));
+        let amounts: Vec<i64> = account.transactions.iter().map(|x| x.amount.minor()).collect();
+        assert_eq!(amounts, vec![1250, -500, (1 << 32) + 1, -700]);
+        assert!(account.transactions.iter().all(|x| x.fees[0].amount == Money::from_minor(30, CurrencyCode::USD)));
+    }
+
+    #[test]
+    fn exact_money_amounts_uses_the_exponent_of_each_currency() {
+        let mut transaction = legacy_transaction(1, json!({ "coin": 12300 }));
+        transaction["original"] = json!({ "amount": { "coin": 123_456 }, "currency": "BHD" });
+        let mut ledger = json!({
+            "accounts": [{
+                "name": "Japan",
+                "currency": "JPY",
+                "opening_date": "2019-01-01T00:00:00Z",
+                "opening_balance": { "coin": 100_050 },
+                "transactions": [transaction],
+            }],
+        });
+
+        exact_money_amounts(&mut ledger);
+
+        let account: Account = serde_json::from_value(ledger["accounts"][0].take()).unwrap();
+        assert_eq!(account.opening_balance, Money::from_minor(1001, JPY));
+        assert_eq!(account.transactions[0].amount, Money::from_minor(123, JPY));
+        assert_eq!(account.transactions[0].original, Some(Money::from_minor(1_234_560, BHD)));
+    }
+
+    #[test]
+    fn exact_money_amounts_refreshes_reconciled_fingerprints() {
+        let mut ledger = json!({
+            "accounts": [{
+                "name": "Stripe",
+                "opening_date": "2019-01-01T00:00:00Z",
+                "opening_balance": { "symbol": "$", "coin": 0 },
+                "transactions": [legacy_transaction(1, json!({ "symbol": "$", "coin": 1250 }))],
+                "reconciliations": [{
+                    "date": "2019-01-31T00:00:00Z",
+                    "balance": { "symbol": "$", "coin": 1220 },
+                    "made_on": "2019-02-01T00:00:00Z",
+                    "transactions": [{ "id": [1], "fingerprint": [0] }],
+                }],
+            }],
+        });
+
+        let changes = exact_money_amounts(&mut ledger);
+        assert_eq!(changes.len(), 2);
+
+        let account: Account = serde_json::from_value(ledger["accounts"][0].take()).unwrap();
+        let reconciliation = &account.reconciliations[0];
+        assert_eq!(reconciliation.balance, Money::from_minor(1220, CurrencyCode::USD));
+        assert_eq!(reconciliation.transactions[0].fingerprint, account.transactions[0].fingerprint());
+    }
+
+    #[test]
+    fn exact_money_amounts_leaves_exact_amounts_alone() {
+        let mut ledger = json!({
+            "accounts": [{
+                "name": "Stripe",
+                "opening_date": "2019-01-01T00:00:00Z",
+                "opening_balance": { "minor": 0, "currency": "USD" },
+                "transactions": [],
+            }],
+        });
+        let before = ledger.clone();
+
+        assert!(exact_money_amounts(&mut ledger).is_empty());
+        assert_eq!(ledger, before);
+    }
+
+
+    #[test]
+    fn unlink_missing_donations_leaves_refunds_without_a_donation_unlinked() {
+        use crate::ledger::fixtures::*;
+
+        let unlinked = refund(1, "Ann", Some(Vec::new()), 1000, date(2019, 1, 2));
+        let linked = refund(2, "Ann", Some(vec![3]), 1000, date(2019, 1, 3));
+        let mut changed = refund(4, "Ann", Some(Vec::new()), 1000, date(2019, 1, 4));
+        let reconciled = |txn: &Transaction| ReconciledTransaction {
+            id: txn.id.clone(),
+            fingerprint: txn.fingerprint(),
+        };
+        let mut stripe = account("Stripe", Vec::new());
+        stripe.reconciliations.push(Reconciliation {
+            date: date(2019, 1, 31),
+            balance: usd(-3000),
+            made_on: date(2019, 2, 1),
+            transactions: vec![reconciled(&unlinked), reconciled(&linked), reconciled(&changed)],
+        });
+        changed.description = "Changed since".to_owned();
+        stripe.transactions = vec![unlinked, linked, changed];
+        let mut ledger = json!({ "accounts": [serde_json::to_value(&stripe).unwrap()] });
+
+        let changes = unlink_missing_donations(&mut ledger);
+        assert_eq!(changes, vec!["Unlinked 2 refund(s) of `Stripe` from an empty donation ID".to_owned()]);
+
+        let account: Account = serde_json::from_value(ledger["accounts"][0].take()).unwrap();
+        let donations: Vec<Option<DonationID>> = account
+            .transactions
+            .iter()
+            .map(|x| match x.meta {
+                TransactionMetadata::Expense {
+                    kind: ExpenseKind::Refund { ref donation, .. },
+                    ..
+                } => donation.clone(),
+                _ => panic!("only refunds are in the account"),
+            })
+            .collect();
+        assert_eq!(donations, vec![None, Some(vec![3]), None]);
+
+        // Transactions changed since their reconciliation are still reported as such.
+        let fingerprints = &account.reconciliations[0].transactions;
+        assert_eq!(fingerprints[0].fingerprint, account.transactions[0].fingerprint());
+        assert_eq!(fingerprints[1].fingerprint, account.transactions[1].fingerprint());
+        assert_ne!(fingerprints[2].fingerprint, account.transactions[2].fingerprint());
+    }
+
+
+    #[test]
+    fn reports_a_broken_rate_table_on_conversions() {
+        let path = env::temp_dir().join(format!("backertrack-{}-broken-rates.json", process::id()));
+        let mut ledger = Ledger::default();
+        ledger.settings.rates = "missing-rates.csv".to_owned();
+        ledger.save(&path).unwrap();
+
+        let (ledger, _) = Ledger::load_migrated(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let amount = Money::from_minor(1000, EUR);
+        match ledger.rates.convert(&amount, CurrencyCode::USD, Utc::now()) {
+            Err(Error::BadRateTable(_)) => {}
+            _ => panic!("the conversion should report the missing rate table"),
+        }
+        assert_eq!(ledger.rates.convert(&amount, EUR, Utc::now()).unwrap(), amount);
+    }
+}
@@ -0,0 +1,559 @@
+use super::{
+    BankID, CurrencyCode, Error, ExpenseKind, Fee, IncomeKind, Ledger, Money, RefundID,
+    Transaction, TransactionID, TransactionMetadata,
+};
+use chrono::{offset::TimeZone, DateTime, Utc};
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+lazy_static! {
+    static ref FEES_REGEX: Regex = Regex::new(r"([^\[]+)\[([^\]]+)\](?:\(([^)]*)\))?").unwrap();
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TransactionKind {
+    Income,
+    Expense,
+    Donation,
+    Payout,
+    Refund,
+    Chargeback,
+    Transfer,
+}
+
+impl TransactionKind {
+    pub const NAMES: &'static [&'static str] = &[
+        "income",
+        "expense",
+        "donation",
+        "payout",
+        "refund",
+        "chargeback",
+        "transfer",
+    ];
+
+    pub fn of(transaction: &Transaction) -> Self {
+        match transaction.meta {
+            TransactionMetadata::Income {
+                kind: IncomeKind::General,
+                ..
+            } => TransactionKind::Income,
+            TransactionMetadata::Income {
+                kind: IncomeKind::Donation(_),
+                ..
+            } => TransactionKind::Donation,
+            TransactionMetadata::Expense {
+                kind: ExpenseKind::General,
+                ..
+            } => TransactionKind::Expense,
+            TransactionMetadata::Expense {
+                kind: ExpenseKind::Payout(_),
+                ..
+            } => TransactionKind::Payout,
+            TransactionMetadata::Expense {
+                kind: ExpenseKind::Refund { .. },
+                ..
+            } => TransactionKind::Refund,
+            TransactionMetadata::Expense {
+                kind: ExpenseKind::Chargeback { .. },
+                ..
+            } => TransactionKind::Chargeback,
+            TransactionMetadata::Transfer { .. } => TransactionKind::Transfer,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "income" => Some(TransactionKind::Income),
+            "expense" => Some(TransactionKind::Expense),
+            "donation" => Some(TransactionKind::Donation),
+            "payout" => Some(TransactionKind::Payout),
+            "refund" => Some(TransactionKind::Refund),
+            "chargeback" => Some(TransactionKind::Chargeback),
+            "transfer" => Some(TransactionKind::Transfer),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TransactionKind::Income => "income",
+            TransactionKind::Expense => "expense",
+            TransactionKind::Donation => "donation",
+            TransactionKind::Payout => "payout",
+            TransactionKind::Refund => "refund",
+            TransactionKind::Chargeback => "chargeback",
+            TransactionKind::Transfer => "transfer",
+        }
+    }
+}
+
+/// The editable contents of a transaction, flattened regardless of its kind.
+/// For transfers, `towards` is the destination account.
+/// `id` is the donation, payout or transfer ID, and `transaction_id` the transaction's own.
+/// Refunds and chargebacks are made towards the donor, and `id` is the refunded donation, empty if unlinked.
+pub struct TransactionFields {
+    pub transaction_id: TransactionID,
+    pub refund_id: RefundID,
+    pub bank_id: Option<BankID>,
+    pub cleared: bool,
+    pub kind: TransactionKind,
+    pub date: DateTime<Utc>,
+    pub description: String,
+    /// Amount in the currency the transaction was made in.
+    pub amount: Money,
+    /// Amount in the account's currency when `amount` is in another one.
+    /// Converted with the rate table if missing.
+    pub converted: Option<Money>,
+    /// Fees, in the account's currency.
+    pub fees: Vec<Fee>,
+    pub category: Option<String>,
+    pub from: String,
+    pub towards: String,
+    pub requester: String,
+    pub id: Vec<u8>,
+}
+
+impl TransactionFields {
+    /// Fields of a new transaction on an account in the given currency.
+    pub fn new(currency: CurrencyCode) -> Self {
+        TransactionFields {
+            transaction_id: super::accounts::new_transaction_id(),
+            refund_id: Vec::new(),
+            bank_id: None,
+            cleared: false,
+            kind: TransactionKind::Income,
+            date: Utc::now(),
+            description: String::new(),
+            amount: Money::zero(currency),
+            converted: None,
+            fees: Vec::new(),
+            category: None,
+            from: String::new(),
+            towards: String::new(),
+            requester: String::new(),
+            id: Vec::new(),
+        }
+    }
+
+    pub fn from_transaction(transaction: &Transaction) -> Self {
+        let mut fields = TransactionFields {
+            transaction_id: transaction.id.clone(),
+            refund_id: Vec::new(),
+            bank_id: transaction.bank_id.clone(),
+            cleared: transaction.cleared,
+            kind: TransactionKind::of(transaction),
+            date: transaction.date,
+            description: transaction.description.clone(),
+            amount: transaction.amount.clone(),
+            converted: None,
+            fees: transaction.fees.clone(),
+            category: transaction.category.clone(),
+            from: String::new(),
+            towards: String::new(),
+            requester: String::new(),
+            id: Vec::new(),
+        };
+
+        if let Some(ref original) = transaction.original {
+            fields.amount = original.clone();
+            fields.converted = Some(transaction.amount.clone());
+        }
+
+        match transaction.meta {
+            TransactionMetadata::Income { ref kind, ref from } => {
+                fields.from = from.clone();
+                if let IncomeKind::Donation(id) = kind {
+                    fields.id = id.clone();
+                }
+            }
+            TransactionMetadata::Expense {
+                ref kind,
+                ref towards,
+                ref requester,
+            } => {
+                fields.towards = towards.clone();
+                fields.requester = requester.clone();
+                match kind {
+                    ExpenseKind::Payout(id) => fields.id = id.clone(),
+                    ExpenseKind::Refund { id, donation } | ExpenseKind::Chargeback { id, donation } => {
+                        fields.refund_id = id.clone();
+                        fields.id = donation.clone().unwrap_or_default();
+                    }
+                    ExpenseKind::General => {}
+                }
+            }
+            TransactionMetadata::Transfer {
+                ref id,
+                ref from,
+                ref to,
+            } => {
+                fields.from = from.clone();
+                fields.towards = to.clone();
+                fields.id = id.clone();
+            }
+        }
+
+        fields
+    }
+
+    /// Builds the transaction to record on the given account.
+    /// Transfers are built as their source leg, and get a new ID if they have none.
+    /// Refunds and chargebacks get a new refund ID if they have none.
+    /// Amounts in another currency than the account's are converted.
+    pub fn into_transaction(self, ledger: &Ledger, account: &str) -> Result<Transaction, Error> {
+        let currency = ledger.account(account)?.currency;
+
+        for fee in &self.fees {
+            if fee.amount.currency() != currency {
+                return Err(Error::CurrencyMismatch {
+                    expected: currency,
+                    found: fee.amount.currency(),
+                });
+            }
+        }
+        for category in self.fees.iter().map(|x| &x.category).chain(Some(&self.category)) {
+            if let Some(category) = category {
+                ledger.category(category)?;
+            }
+        }
+
+        let (amount, original) = if self.amount.currency() == currency {
+            (self.amount, None)
+        } else {
+            let amount = match self.converted {
+                Some(converted) => converted,
+                None => ledger.rates.convert(&self.amount, currency, self.date)?,
+            };
+            (amount, Some(self.amount))
+        };
+
+        let refund_id = if self.refund_id.len() == 0 {
+            super::accounts::new_transaction_id()
+        } else {
+            self.refund_id
+        };
+
+        let meta = match self.kind {
+            TransactionKind::Income => TransactionMetadata::Income {
+                kind: IncomeKind::General,
+                from: self.from,
+            },
+            TransactionKind::Donation => TransactionMetadata::Income {
+                kind: IncomeKind::Donation(self.id),
+                from: self.from,
+            },
+            TransactionKind::Expense => TransactionMetadata::Expense {
+                kind: ExpenseKind::General,
+                towards: self.towards,
+                requester: self.requester,
+            },
+            TransactionKind::Payout => TransactionMetadata::Expense {
+                kind: ExpenseKind::Payout(self.id),
+                towards: self.towards,
+                requester: self.requester,
+            },
+            TransactionKind::Refund => TransactionMetadata::Expense {
+                kind: ExpenseKind::Refund {
+                    id: refund_id,
+                    donation: Some(self.id).filter(|x| x.len() != 0),
+                },
+                towards: self.towards,
+                requester: self.requester,
+            },
+            TransactionKind::Chargeback => TransactionMetadata::Expense {
+                kind: ExpenseKind::Chargeback {
+                    id: refund_id,
+                    donation: Some(self.id).filter(|x| x.len() != 0),
+                },
+                towards: self.towards,
+                requester: self.requester,
+            },
+            TransactionKind::Transfer => {
+                ledger.account(&self.towards)?;
+                if self.towards == account {
+                    return Err(Error::BadArgument {
+                        argument: "towards".to_owned(),
+                        value: self.towards,
+                    });
+                }
+
+                TransactionMetadata::Transfer {
+                    id: if self.id.len() == 0 {
+                        super::accounts::new_transfer_id(account, &self.towards)
+                    } else {
+                        self.id
+                    },
+                    from: account.to_owned(),
+                    to: self.towards,
+                }
+            }
+        };
+
+        Ok(Transaction {
+            id: self.transaction_id,
+            bank_id: self.bank_id,
+            date: self.date,
+            description: self.description,
+            amount,
+            meta,
+            fees: self.fees,
+            original,
+            cleared: self.cleared,
+            category: self.category,
+        })
+    }
+}
+
+/// Raw transaction fields as typed by the user. Missing fields are left untouched.
+#[derive(Default)]
+pub struct TransactionArgs<'a> {
+    pub kind: Option<&'a str>,
+    pub date: Option<&'a str>,
+    pub amount: Option<&'a str>,
+    pub currency: Option<&'a str>,
+    pub converted: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub fees: Option<&'a str>,
+    pub category: Option<&'a str>,
+    pub from: Option<&'a str>,
+    pub towards: Option<&'a str>,
+    pub requester: Option<&'a str>,
+    pub id: Option<&'a str>,
+}
+
+impl<'a> TransactionArgs<'a> {
+    /// Applies the arguments on the fields of a transaction of an account in the given currency.
+    /// Amounts are in the currency of the transaction, and fees in the account's.
+    pub fn apply(&self, fields: &mut TransactionFields, account_currency: CurrencyCode) -> Result<(), Error> {
+        let bad_argument = |argument: &str, value: &str| Error::BadArgument {
+            argument: argument.to_owned(),
+            value: value.to_owned(),
+        };
+
+        if let Some(kind) = self.kind {
+            let kind = TransactionKind::parse(kind).ok_or_else(|| bad_argument("kind", kind))?;
+            if kind != fields.kind {
+                // A donation ID is not a payout or transfer ID.
+                fields.id.clear();
+                fields.kind = kind;
+            }
+        }
+        if let Some(date) = self.date {
+            fields.date = Utc
+                .datetime_from_str(date, crate::DATE_FORMAT)
+                .map_err(|_| bad_argument("date", date))?;
+        }
+        let currency = match self.currency {
+            Some(currency) if currency.trim() == "" => account_currency,
+            Some(currency) => CurrencyCode::parse(currency).ok_or_else(|| bad_argument("currency", currency))?,
+            None => fields.amount.currency(),
+        };
+        // An amount given without one is kept and moved to the new currency.
+        let amount = match self.amount {
+            Some(amount) => parse_amount(amount, currency).ok_or_else(|| bad_argument("amount", amount))?,
+            None => parse_amount(&fields.amount.decimal(), currency)
+                .ok_or_else(|| bad_argument("currency", currency.as_str()))?,
+        };
+        if amount != fields.amount {
+            fields.converted = None;
+        }
+        fields.amount = amount;
+
+        if let Some(converted) = self.converted {
+            fields.converted = match converted.trim() {
+                "" => None,
+                _ => Some(parse_amount(converted, account_currency).ok_or_else(|| bad_argument("converted", converted))?),
+            };
+        }
+        if let Some(description) = self.description {
+            fields.description = description.to_owned();
+        }
+        if let Some(fees) = self.fees {
+            fields.fees = parse_fees(fees, account_currency).ok_or_else(|| bad_argument("fees", fees))?;
+        }
+        if let Some(category) = self.category {
+            fields.category = match category.trim() {
+                "" => None,
+                category => Some(category.to_owned()),
+            };
+        }
+        if let Some(from) = self.from {
+            fields.from = from.to_owned();
+        }
+        if let Some(towards) = self.towards {
+            fields.towards = towards.to_owned();
+        }
+        if let Some(requester) = self.requester {
+            fields.requester = requester.to_owned();
+        }
+        if let Some(id) = self.id {
+            fields.id = hex::decode(id).map_err(|_| bad_argument("id", id))?;
+        }
+
+        match fields.kind {
+            TransactionKind::Donation | TransactionKind::Payout if fields.id.len() == 0 => {
+                Err(Error::MissingArgument("id".to_owned()))
+            }
+            TransactionKind::Transfer if fields.towards == "" => {
+                Err(Error::MissingArgument("towards".to_owned()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn parse_amount(amount: &str, currency: CurrencyCode) -> Option<Money> {
+    Money::parse(amount, currency)
+}
+
+/// Parses fees written as `amount[towards](category);amount[towards];...`, categories being optional.
+pub fn parse_fees(fees_str: &str, currency: CurrencyCode) -> Option<Vec<Fee>> {
+    let mut fees = Vec::new();
+
+    for x in fees_str.split(';') {
+        if x.trim() != "" {
+            let c = FEES_REGEX.captures(x)?;
+            fees.push(Fee {
+                amount: parse_amount(c.get(1)?.as_str(), currency)?,
+                towards: c.get(2)?.as_str().to_owned(),
+                category: c.get(3).map(|x| x.as_str().trim()).filter(|x| *x != "").map(str::to_owned),
+            });
+        }
+    }
+
+    Some(fees)
+}
+
+/// Hex ID of the donation a refund gives back, or `unlinked` if it could not be found.
+pub fn format_donation(donation: &[u8]) -> String {
+    if donation.is_empty() {
+        "unlinked".to_owned()
+    } else {
+        hex::encode(donation)
+    }
+}
+
+pub fn format_fees(fees: &[Fee]) -> String {
+    let mut res = String::new();
+    for f in fees {
+        res.push_str(&format!("{}[{}]", &f.amount.to_string(), f.towards));
+        if let Some(ref category) = f.category {
+            res.push_str(&format!("({})", category));
+        }
+        res.push(';');
+    }
+    res.pop();
+    res
+}
+
+pub fn add(ledger_path: PathBuf, account: &str, args: TransactionArgs) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let currency = ledger.account(account)?.currency;
+
+    let mut fields = TransactionFields::new(currency);
+    args.apply(&mut fields, currency)?;
+
+    let transaction = fields.into_transaction(&ledger, account)?;
+    if let TransactionMetadata::Transfer { .. } = transaction.meta {
+        ledger.push_transfer(transaction)?;
+    } else {
+        ledger.account_mut(account)?.transactions.push(transaction);
+    }
+
+    ledger.sort_by_date();
+    ledger.save(&ledger_path)
+}
+
+pub fn list(ledger_path: PathBuf, account: &str) -> Result<(), Error> {
+    let ledger = Ledger::load(&ledger_path)?;
+    let account = ledger.account(account)?;
+
+    for (i, t) in account.transactions.iter().enumerate() {
+        let fields = TransactionFields::from_transaction(t);
+
+        let counterpart = match fields.kind {
+            TransactionKind::Income | TransactionKind::Donation => format!("from {}", fields.from),
+            TransactionKind::Expense | TransactionKind::Payout => format!(
+                "to {} requested by {}",
+                fields.towards, fields.requester
+            ),
+            TransactionKind::Refund | TransactionKind::Chargeback => format!(
+                "to {} for donation {}",
+                fields.towards,
+                format_donation(&fields.id)
+            ),
+            TransactionKind::Transfer if fields.from == account.name => {
+                format!("to {}", fields.towards)
+            }
+            TransactionKind::Transfer => format!("from {}", fields.from),
+        };
+
+        let amount = match t.original {
+            Some(ref original) => format!("{} ({})", t.amount, original),
+            None => t.amount.to_string(),
+        };
+
+        let kind = match t.category {
+            Some(ref category) => format!("{} ({})", fields.kind.name(), category),
+            None => fields.kind.name().to_owned(),
+        };
+
+        println!(
+            "{}: [{}] {}{} {} {} {} (fees: {}) {}",
+            i,
+            hex::encode(&t.id),
+            if t.cleared { "* " } else { "" },
+            t.date.format(crate::DATE_FORMAT),
+            kind,
+            amount,
+            counterpart,
+            format_fees(&t.fees),
+            t.description,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn edit(
+    ledger_path: PathBuf,
+    account: &str,
+    index: &str,
+    args: TransactionArgs,
+) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let (account, index) = ledger.locate_transaction(account, index)?;
+    warn_reconciled(&ledger, account, index);
+
+    let mut fields = TransactionFields::from_transaction(&ledger.accounts[account].transactions[index]);
+    args.apply(&mut fields, ledger.accounts[account].currency)?;
+
+    let name = ledger.accounts[account].name.clone();
+    let transaction = fields.into_transaction(&ledger, &name)?;
+    ledger.replace_transaction_at(account, index, transaction)?;
+
+    ledger.sort_by_date();
+    ledger.save(&ledger_path)
+}
+
+pub fn remove(ledger_path: PathBuf, account: &str, index: &str) -> Result<(), Error> {
+    let mut ledger = Ledger::load(&ledger_path)?;
+    let (account, index) = ledger.locate_transaction(account, index)?;
+    warn_reconciled(&ledger, account, index);
+
+    ledger.remove_transaction_at(account, index);
+    ledger.save(&ledger_path)
+}
+
+/// Warns about changes to a transaction, or to the other leg of its transfer, that was reconciled.
+fn warn_reconciled(ledger: &Ledger, account: usize, index: usize) {
+    for (name, reconciliation) in ledger.reconciliations_of(account, index) {
+        println!(
+            "WARNING: This transaction was reconciled on `{}` with the statement of {}.",
+            name,
+            reconciliation.date.format(crate::DATE_FORMAT)
+        );
+    }
+}
@@ -1,5 +1,5 @@
-use chrono::{TimeZone, Utc};
-use clap::{App, AppSettings, Arg, SubCommand};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 use std::path::PathBuf;
 
@@ -21,9 +21,7 @@ mod ui {
     }
 }
 
-use crate::utils::path_exists_or_panic;
-
-fn main() -> Result<(), Box<std::error::Error>> {
+fn main() {
     let ledger_subcommand = SubCommand::with_name("ledger")
         .about("Manage the transaction ledger")
         .setting(AppSettings::ArgRequiredElseHelp)
@@ -37,6 +35,48 @@ fn main() -> Result<(), Box<std::error::Error>> {
                         .help("Path to create the ledger file at"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Show or change the settings stored in the ledger")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .arg(
+                    Arg::with_name("LEDGER")
+                        .required(true)
+                        .help("Path to the ledger file"),
+                )
+                .arg(
+                    Arg::with_name("KEY")
+                        .help("Setting to show or change (all settings are shown if omitted)"),
+                )
+                .arg(
+                    Arg::with_name("VALUE")
+                        .help("New value for the setting"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate")
+                .about("Upgrade a ledger file to the latest schema version")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .arg(
+                    Arg::with_name("LEDGER")
+                        .required(true)
+                        .help("Path to the ledger file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("List the backups of a ledger or roll it back to one of them")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .arg(
+                    Arg::with_name("LEDGER")
+                        .required(true)
+                        .help("Path to the ledger file"),
+                )
+                .arg(
+                    Arg::with_name("BACKUP")
+                        .help("Number or file name of the backup to restore (backups are listed if omitted)"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("export")
                 .about("Export the ledger to an accountant-friendly format")
@@ -50,7 +90,8 @@ fn main() -> Result<(), Box<std::error::Error>> {
                     Arg::with_name("OUTPUT")
                         .required(true)
                         .help("Path to send the output CSV to"),
-                ),
+                )
+                .arg(currency_arg()),
         )
         .subcommand(
             SubCommand::with_name("info")
@@ -65,11 +106,12 @@ fn main() -> Result<(), Box<std::error::Error>> {
                     Arg::with_name("ACCOUNTS")
                         .required(true)
                         .help("Accounts to get info from (account1,account2,...)"),
-                ),
+                )
+                .arg(currency_arg()),
         )
         .subcommand(
             SubCommand::with_name("payout")
-                .about("Import new payouts to Chase into the ledger")
+                .about("Import new payouts to the bank account into the ledger")
                 .setting(AppSettings::ArgRequiredElseHelp)
                 .arg(
                     Arg::with_name("LEDGER")
@@ -83,9 +125,35 @@ fn main() -> Result<(), Box<std::error::Error>> {
                 )
                 .arg(
                     Arg::with_name("PLATFORM")
-                        .required(true)
+                        .required_unless("mapping")
                         .help("Origin platform of the payout")
                         .possible_values(&["stripe", "paypal"]),
+                )
+                .arg(
+                    Arg::with_name("mapping")
+                        .long("mapping")
+                        .takes_value(true)
+                        .conflicts_with("PLATFORM")
+                        .help("Path to a JSON file describing the columns of the imported CSV, instead of a platform"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Print what would be imported without modifying the ledger"),
+                )
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Account to use for a processor, overriding the ledger settings (processor=account)"),
+                )
+                .arg(
+                    Arg::with_name("destination")
+                        .long("destination")
+                        .takes_value(true)
+                        .help("Account the payouts are sent to, overriding the ledger settings"),
                 ),
         )
         .subcommand(
@@ -108,9 +176,501 @@ fn main() -> Result<(), Box<std::error::Error>> {
                         )
                         .arg(
                             Arg::with_name("PLATFORM")
-                                .required(true)
+                                .required_unless("mapping")
                                 .help("Platform the imported data is from")
-                                .possible_values(&["donorbox", "opencollective"]),
+                                .possible_values(&[
+                                    "donorbox",
+                                    "opencollective",
+                                    "githubsponsors",
+                                    "patreon",
+                                    "liberapay",
+                                    "kofi",
+                                ]),
+                        )
+                        .arg(
+                            Arg::with_name("mapping")
+                                .long("mapping")
+                                .takes_value(true)
+                                .conflicts_with("PLATFORM")
+                                .help("Path to a JSON file describing the columns of the imported CSV, instead of a platform"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Print what would be imported without modifying the ledger"),
+                        )
+                        .arg(
+                            Arg::with_name("account")
+                                .long("account")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("Account to use for a platform or processor, overriding the ledger settings (processor=account)"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bank")
+                .about("Manage bank accounts")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Import the transactions of a bank statement into an account")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("FILE")
+                                .required(true)
+                                .help("Path to the statement to import"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account the statement is for"),
+                        )
+                        .arg(
+                            Arg::with_name("format")
+                                .long("format")
+                                .takes_value(true)
+                                .possible_values(&["ofx", "qfx", "qif", "camt"])
+                                .help("Format of the statement, guessed from the file extension by default"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Print what would be imported without modifying the ledger"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reconcile")
+                .about("Reconcile an account with a bank statement")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Compare the balance of an account with a statement's closing balance")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account to reconcile"),
+                        )
+                        .args(&statement_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Mark transactions as cleared by the bank")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account the transactions are in"),
+                        )
+                        .arg(
+                            Arg::with_name("TRANSACTION")
+                                .required(true)
+                                .multiple(true)
+                                .help("Indices or hexadecimal IDs of the transactions"),
+                        )
+                        .arg(
+                            Arg::with_name("undo")
+                                .long("undo")
+                                .help("Mark the transactions as uncleared instead"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("finish")
+                        .about("Record a reconciliation once the cleared balance matches the statement")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account to reconcile"),
+                        )
+                        .args(&statement_args()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("categories")
+                .about("Manage the categories income and expenses are totalled under")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List every category under its parent, with its rules")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a category, possibly within another one")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("NAME")
+                                .required(true)
+                                .help("Name of the category"),
+                        )
+                        .args(&category_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("edit")
+                        .about("Move a category under another one or add rules to it")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("NAME")
+                                .required(true)
+                                .help("Name of the category"),
+                        )
+                        .args(&category_args()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("donors")
+                .about("Manage the donor registry and see the giving history of donors")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the lifetime and yearly totals, gift dates and platforms of every donor")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(currency_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Register a donor, whose aliases are matched onto it")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("NAME")
+                                .required(true)
+                                .help("Name donations of the donor are recorded under"),
+                        )
+                        .args(&donor_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("edit")
+                        .about("Add aliases to a donor or change its email")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("NAME")
+                                .required(true)
+                                .help("Name or alias of the donor"),
+                        )
+                        .args(&donor_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("receipts")
+                        .about("Write the yearly acknowledgment of their gifts for every donor, from a template")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Calendar year of the gifts"),
+                        )
+                        .arg(
+                            Arg::with_name("template")
+                                .long("template")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Markdown, HTML or plain text template of a receipt, using {{donor}}, {{email}}, {{year}}, {{gifts}}, {{total}}, {{organization}}, {{ein}}, {{statement}} and {{date}}"),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Directory to write the receipts to"),
+                        )
+                        .arg(currency_arg()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Produce financial reports from the ledger")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("income")
+                        .about("Break income and expenses down by month, quarter or fiscal year")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNTS")
+                                .required(true)
+                                .help("Accounts to report on (account1,account2,...)"),
+                        )
+                        .arg(
+                            Arg::with_name("period")
+                                .long("period")
+                                .takes_value(true)
+                                .default_value("month")
+                                .possible_values(ledger::report::Period::NAMES)
+                                .help("Length of the periods, quarters and years being fiscal ones"),
+                        )
+                        .arg(currency_arg())
+                        .arg(
+                            Arg::with_name("csv")
+                                .long("csv")
+                                .help("Print the report as CSV instead of a text table"),
+                        )
+                        .arg(
+                            Arg::with_name("by-category")
+                                .long("by-category")
+                                .help("Break the net income of each period down by category"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("balances")
+                        .about("List the balance of every account on a date, and their sum")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("as-of")
+                                .long("as-of")
+                                .takes_value(true)
+                                .required(true)
+                                .help("UTC date of the balances (YYYY/MM/DD HH:MM), or a day to take its end (YYYY/MM/DD)"),
+                        )
+                        .arg(
+                            Arg::with_name("currency")
+                                .long("currency")
+                                .takes_value(true)
+                                .help("ISO 4217 code of the currency to sum in, the ledger's base currency by default"),
+                        )
+                        .arg(
+                            Arg::with_name("csv")
+                                .long("csv")
+                                .help("Print the report as CSV instead of a text table"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("form990")
+                        .about("Report a fiscal year on the lines of Form 990-EZ, Part I, with their transactions")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Fiscal year, named after the calendar year it ends in"),
+                        )
+                        .arg(currency_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("schedule-b")
+                        .about("List as CSV the contributors of $5,000 or more in a fiscal year, with their gifts")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Fiscal year, named after the calendar year it ends in"),
+                        )
+                        .arg(
+                            Arg::with_name("special-rule")
+                                .long("special-rule")
+                                .help("Only list contributors of 2% or more of all contributions too, for organizations meeting the 33 1/3% support test"),
+                        )
+                        .arg(currency_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("public-support")
+                        .about("Compute the public support percentage of a fiscal year and the four before")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("year")
+                                .long("year")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Last fiscal year, named after the calendar year it ends in"),
+                        )
+                        .arg(currency_arg()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("txn")
+                .about("Manage the transactions of an account")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a transaction to an account")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account to add the transaction to"),
+                        )
+                        .arg(
+                            Arg::with_name("kind")
+                                .required(true)
+                                .possible_values(ledger::transactions::TransactionKind::NAMES)
+                                .help("Kind of the transaction"),
+                        )
+                        .arg(
+                            Arg::with_name("date")
+                                .required(true)
+                                .help("UTC date of the transaction (YYYY/MM/DD HH:MM)"),
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .required(true)
+                                .help("Gross amount of the transaction"),
+                        )
+                        .args(&transaction_field_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the transactions of an account")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account to list the transactions of"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("edit")
+                        .about("Change some fields of a transaction")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account the transaction is in"),
+                        )
+                        .arg(
+                            Arg::with_name("TRANSACTION")
+                                .required(true)
+                                .help("Number of the transaction, as shown by `txn list`"),
+                        )
+                        .arg(
+                            Arg::with_name("kind")
+                                .long("kind")
+                                .takes_value(true)
+                                .possible_values(ledger::transactions::TransactionKind::NAMES)
+                                .help("Kind of the transaction"),
+                        )
+                        .arg(
+                            Arg::with_name("date")
+                                .long("date")
+                                .takes_value(true)
+                                .help("UTC date of the transaction (YYYY/MM/DD HH:MM)"),
+                        )
+                        .arg(
+                            Arg::with_name("amount")
+                                .long("amount")
+                                .takes_value(true)
+                                .help("Gross amount of the transaction"),
+                        )
+                        .args(&transaction_field_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("rm")
+                        .about("Remove a transaction, and the other leg of a transfer")
+                        .setting(AppSettings::ArgRequiredElseHelp)
+                        .arg(
+                            Arg::with_name("LEDGER")
+                                .required(true)
+                                .help("Path to the ledger file"),
+                        )
+                        .arg(
+                            Arg::with_name("ACCOUNT")
+                                .required(true)
+                                .help("Account the transaction is in"),
+                        )
+                        .arg(
+                            Arg::with_name("TRANSACTION")
+                                .required(true)
+                                .help("Number of the transaction, as shown by `txn list`"),
                         ),
                 ),
         )
@@ -143,6 +703,12 @@ fn main() -> Result<(), Box<std::error::Error>> {
                                 .short("b")
                                 .takes_value(true)
                                 .help("Opening balance for the account"),
+                        )
+                        .arg(
+                            Arg::with_name("currency")
+                                .long("currency")
+                                .takes_value(true)
+                                .help("ISO 4217 code of the account's currency, the ledger's base currency by default"),
                         ),
                 )
                 .subcommand(
@@ -182,53 +748,433 @@ fn main() -> Result<(), Box<std::error::Error>> {
     let matches = app.get_matches();
 
     if let Some(ledger_match) = matches.subcommand_matches("ledger") {
-        if let Some(donations_match) = ledger_match.subcommand_matches("donations") {
-            if let Some(import_match) = donations_match.subcommand_matches("import") {
-                ledger::donations::import(
-                    path_exists_or_panic(import_match.value_of("LEDGER").unwrap()),
-                    path_exists_or_panic(import_match.value_of("FILE").unwrap()),
-                    import_match.value_of("PLATFORM").unwrap().into(),
-                );
-            }
-        } else if let Some(accounts_match) = ledger_match.subcommand_matches("accounts") {
-            if let Some(new_match) = accounts_match.subcommand_matches("new") {
-                ledger::accounts::new(
-                    path_exists_or_panic(new_match.value_of("LEDGER").unwrap()),
-                    new_match.value_of("NAME").unwrap(),
-                    new_match
-                        .value_of("balance")
-                        .map(currency::Currency::from_str)
-                        .and_then(Result::ok)
-                        .unwrap_or_else(|| currency::Currency::from(0, '$')),
-                    new_match
-                        .value_of("DATE")
-                        .and_then(|x| Utc.datetime_from_str(x, DATE_FORMAT).ok())
-                        .expect("Invalid opening date, expected format: YYYY/MM/DD HH:MM"),
-                );
-            }
-        } else if let Some(new_match) = ledger_match.subcommand_matches("new") {
-            ledger::new(PathBuf::from(new_match.value_of("LEDGER").unwrap()));
-        } else if let Some(export_match) = ledger_match.subcommand_matches("export") {
-            ledger::export(
-                PathBuf::from(export_match.value_of("LEDGER").unwrap()),
-                PathBuf::from(export_match.value_of("OUTPUT").unwrap()),
-            );
-        } else if let Some(info_match) = ledger_match.subcommand_matches("info") {
-            ledger::info(
-                PathBuf::from(info_match.value_of("LEDGER").unwrap()),
-                info_match.value_of("ACCOUNTS").unwrap(),
-            );
-        } else if let Some(payout_match) = ledger_match.subcommand_matches("payout") {
-            ledger::payout::payout(
-                path_exists_or_panic(payout_match.value_of("LEDGER").unwrap()),
-                path_exists_or_panic(payout_match.value_of("FILE").unwrap()),
-                payout_match.value_of("PLATFORM").unwrap().into(),
-            );
+        if let Err(e) = run_ledger(ledger_match) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
     } else {
         let ledger = matches.value_of("ledger").map(PathBuf::from);
-        ui::start_handle_panic(ledger)?;
+        if let Err(e) = ui::start_handle_panic(ledger) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_ledger(ledger_match: &ArgMatches) -> Result<(), ledger::Error> {
+    if let Some(donations_match) = ledger_match.subcommand_matches("donations") {
+        if let Some(import_match) = donations_match.subcommand_matches("import") {
+            ledger::donations::import(
+                existing_path(import_match.value_of("LEDGER").unwrap())?,
+                existing_path(import_match.value_of("FILE").unwrap())?,
+                match import_match.value_of("mapping") {
+                    Some(mapping) => ledger::donations::DonationOrigin::Mapping(existing_path(mapping)?),
+                    None => import_match.value_of("PLATFORM").unwrap().into(),
+                },
+                &account_overrides(import_match)?,
+                import_match.is_present("dry-run"),
+            )?;
+        }
+    } else if let Some(bank_match) = ledger_match.subcommand_matches("bank") {
+        if let Some(import_match) = bank_match.subcommand_matches("import") {
+            let data = existing_path(import_match.value_of("FILE").unwrap())?;
+            let format = match import_match.value_of("format") {
+                Some(format) => format.into(),
+                None => ledger::bank::StatementFormat::of(&data),
+            };
+
+            ledger::bank::import(
+                existing_path(import_match.value_of("LEDGER").unwrap())?,
+                data,
+                import_match.value_of("ACCOUNT").unwrap(),
+                format,
+                import_match.is_present("dry-run"),
+            )?;
+        }
+    } else if let Some(reconcile_match) = ledger_match.subcommand_matches("reconcile") {
+        if let Some(status_match) = reconcile_match.subcommand_matches("status") {
+            ledger::reconcile::status(
+                existing_path(status_match.value_of("LEDGER").unwrap())?,
+                status_match.value_of("ACCOUNT").unwrap(),
+                statement(status_match)?,
+            )?;
+        } else if let Some(clear_match) = reconcile_match.subcommand_matches("clear") {
+            ledger::reconcile::clear(
+                existing_path(clear_match.value_of("LEDGER").unwrap())?,
+                clear_match.value_of("ACCOUNT").unwrap(),
+                &clear_match.values_of("TRANSACTION").unwrap().collect::<Vec<_>>(),
+                clear_match.is_present("undo"),
+            )?;
+        } else if let Some(finish_match) = reconcile_match.subcommand_matches("finish") {
+            ledger::reconcile::finish(
+                existing_path(finish_match.value_of("LEDGER").unwrap())?,
+                finish_match.value_of("ACCOUNT").unwrap(),
+                statement(finish_match)?,
+            )?;
+        }
+    } else if let Some(categories_match) = ledger_match.subcommand_matches("categories") {
+        if let Some(list_match) = categories_match.subcommand_matches("list") {
+            ledger::categories::list(existing_path(list_match.value_of("LEDGER").unwrap())?)?;
+        } else if let Some(add_match) = categories_match.subcommand_matches("add") {
+            ledger::categories::add(
+                existing_path(add_match.value_of("LEDGER").unwrap())?,
+                add_match.value_of("NAME").unwrap(),
+                add_match.value_of("parent"),
+                &add_match.values_of("rule").map(Iterator::collect).unwrap_or_else(Vec::new),
+            )?;
+        } else if let Some(edit_match) = categories_match.subcommand_matches("edit") {
+            ledger::categories::edit(
+                existing_path(edit_match.value_of("LEDGER").unwrap())?,
+                edit_match.value_of("NAME").unwrap(),
+                edit_match.value_of("parent"),
+                &edit_match.values_of("rule").map(Iterator::collect).unwrap_or_else(Vec::new),
+            )?;
+        }
+    } else if let Some(donors_match) = ledger_match.subcommand_matches("donors") {
+        if let Some(list_match) = donors_match.subcommand_matches("list") {
+            ledger::donors::list(
+                existing_path(list_match.value_of("LEDGER").unwrap())?,
+                list_match.value_of("currency"),
+            )?;
+        } else if let Some(add_match) = donors_match.subcommand_matches("add") {
+            ledger::donors::add(
+                existing_path(add_match.value_of("LEDGER").unwrap())?,
+                add_match.value_of("NAME").unwrap(),
+                add_match.value_of("email"),
+                &add_match.values_of("alias").map(Iterator::collect).unwrap_or_else(Vec::new),
+            )?;
+        } else if let Some(edit_match) = donors_match.subcommand_matches("edit") {
+            ledger::donors::edit(
+                existing_path(edit_match.value_of("LEDGER").unwrap())?,
+                edit_match.value_of("NAME").unwrap(),
+                edit_match.value_of("email"),
+                &edit_match.values_of("alias").map(Iterator::collect).unwrap_or_else(Vec::new),
+            )?;
+        } else if let Some(receipts_match) = donors_match.subcommand_matches("receipts") {
+            ledger::donors::receipts(
+                existing_path(receipts_match.value_of("LEDGER").unwrap())?,
+                year(receipts_match)?,
+                existing_path(receipts_match.value_of("template").unwrap())?,
+                PathBuf::from(receipts_match.value_of("output").unwrap()),
+                receipts_match.value_of("currency"),
+            )?;
+        }
+    } else if let Some(report_match) = ledger_match.subcommand_matches("report") {
+        if let Some(income_match) = report_match.subcommand_matches("income") {
+            ledger::report::income(
+                existing_path(income_match.value_of("LEDGER").unwrap())?,
+                income_match.value_of("ACCOUNTS").unwrap(),
+                ledger::report::Period::parse(income_match.value_of("period").unwrap())
+                    .expect("Unreachable: period possible values"),
+                income_match.value_of("currency"),
+                income_match.is_present("csv"),
+                income_match.is_present("by-category"),
+            )?;
+        } else if let Some(balances_match) = report_match.subcommand_matches("balances") {
+            let as_of = balances_match.value_of("as-of").unwrap();
+            ledger::report::balances(
+                existing_path(balances_match.value_of("LEDGER").unwrap())?,
+                Utc.datetime_from_str(as_of, DATE_FORMAT)
+                    .or_else(|_| Utc.datetime_from_str(&format!("{} 23:59", as_of), DATE_FORMAT))
+                    .map_err(|_| ledger::Error::BadArgument {
+                        argument: "as-of".to_owned(),
+                        value: as_of.to_owned(),
+                    })?,
+                balances_match.value_of("currency"),
+                balances_match.is_present("csv"),
+            )?;
+        } else if let Some(form990_match) = report_match.subcommand_matches("form990") {
+            ledger::report::form990(
+                existing_path(form990_match.value_of("LEDGER").unwrap())?,
+                year(form990_match)?,
+                form990_match.value_of("currency"),
+            )?;
+        } else if let Some(schedule_b_match) = report_match.subcommand_matches("schedule-b") {
+            ledger::report::schedule_b(
+                existing_path(schedule_b_match.value_of("LEDGER").unwrap())?,
+                year(schedule_b_match)?,
+                schedule_b_match.is_present("special-rule"),
+                schedule_b_match.value_of("currency"),
+            )?;
+        } else if let Some(public_support_match) = report_match.subcommand_matches("public-support") {
+            ledger::report::public_support(
+                existing_path(public_support_match.value_of("LEDGER").unwrap())?,
+                year(public_support_match)?,
+                public_support_match.value_of("currency"),
+            )?;
+        }
+    } else if let Some(txn_match) = ledger_match.subcommand_matches("txn") {
+        if let Some(add_match) = txn_match.subcommand_matches("add") {
+            ledger::transactions::add(
+                existing_path(add_match.value_of("LEDGER").unwrap())?,
+                add_match.value_of("ACCOUNT").unwrap(),
+                transaction_args(add_match),
+            )?;
+        } else if let Some(list_match) = txn_match.subcommand_matches("list") {
+            ledger::transactions::list(
+                existing_path(list_match.value_of("LEDGER").unwrap())?,
+                list_match.value_of("ACCOUNT").unwrap(),
+            )?;
+        } else if let Some(edit_match) = txn_match.subcommand_matches("edit") {
+            ledger::transactions::edit(
+                existing_path(edit_match.value_of("LEDGER").unwrap())?,
+                edit_match.value_of("ACCOUNT").unwrap(),
+                edit_match.value_of("TRANSACTION").unwrap(),
+                transaction_args(edit_match),
+            )?;
+        } else if let Some(rm_match) = txn_match.subcommand_matches("rm") {
+            ledger::transactions::remove(
+                existing_path(rm_match.value_of("LEDGER").unwrap())?,
+                rm_match.value_of("ACCOUNT").unwrap(),
+                rm_match.value_of("TRANSACTION").unwrap(),
+            )?;
+        }
+    } else if let Some(accounts_match) = ledger_match.subcommand_matches("accounts") {
+        if let Some(new_match) = accounts_match.subcommand_matches("new") {
+            ledger::accounts::new(
+                existing_path(new_match.value_of("LEDGER").unwrap())?,
+                new_match.value_of("NAME").unwrap(),
+                new_match.value_of("currency"),
+                new_match.value_of("balance"),
+                opening_date(new_match.value_of("DATE").unwrap())?,
+            )?;
+        }
+    } else if let Some(new_match) = ledger_match.subcommand_matches("new") {
+        ledger::new(PathBuf::from(new_match.value_of("LEDGER").unwrap()))?;
+    } else if let Some(config_match) = ledger_match.subcommand_matches("config") {
+        ledger::config(
+            existing_path(config_match.value_of("LEDGER").unwrap())?,
+            config_match.value_of("KEY"),
+            config_match.value_of("VALUE"),
+        )?;
+    } else if let Some(migrate_match) = ledger_match.subcommand_matches("migrate") {
+        ledger::migrate(existing_path(migrate_match.value_of("LEDGER").unwrap())?)?;
+    } else if let Some(restore_match) = ledger_match.subcommand_matches("restore") {
+        ledger::restore(
+            PathBuf::from(restore_match.value_of("LEDGER").unwrap()),
+            restore_match.value_of("BACKUP"),
+        )?;
+    } else if let Some(export_match) = ledger_match.subcommand_matches("export") {
+        ledger::export(
+            PathBuf::from(export_match.value_of("LEDGER").unwrap()),
+            PathBuf::from(export_match.value_of("OUTPUT").unwrap()),
+            export_match.value_of("currency"),
+        )?;
+    } else if let Some(info_match) = ledger_match.subcommand_matches("info") {
+        ledger::info(
+            PathBuf::from(info_match.value_of("LEDGER").unwrap()),
+            info_match.value_of("ACCOUNTS").unwrap(),
+            info_match.value_of("currency"),
+        )?;
+    } else if let Some(payout_match) = ledger_match.subcommand_matches("payout") {
+        ledger::payout::payout(
+            existing_path(payout_match.value_of("LEDGER").unwrap())?,
+            existing_path(payout_match.value_of("FILE").unwrap())?,
+            match payout_match.value_of("mapping") {
+                Some(mapping) => ledger::payout::PayoutOrigin::Mapping(existing_path(mapping)?),
+                None => payout_match.value_of("PLATFORM").unwrap().into(),
+            },
+            &account_overrides(payout_match)?,
+            payout_match.is_present("dry-run"),
+        )?;
     }
 
     Ok(())
 }
+
+/// Path to a file given on the command line, which must exist.
+fn existing_path(input: &str) -> Result<PathBuf, ledger::Error> {
+    let path = PathBuf::from(input);
+    if !path.exists() {
+        return Err(ledger::Error::MissingFile(path));
+    }
+
+    Ok(path)
+}
+
+/// Collects the `--account processor=account` and `--destination account` overrides of an import.
+fn account_overrides(matches: &ArgMatches) -> Result<Vec<(String, String)>, ledger::Error> {
+    let mut overrides = Vec::new();
+
+    for value in matches.values_of("account").into_iter().flatten() {
+        let mut split = value.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(processor), Some(account)) if processor != "" && account != "" => {
+                overrides.push((processor.to_owned(), account.to_owned()));
+            }
+            _ => {
+                return Err(ledger::Error::BadArgument {
+                    argument: "account".to_owned(),
+                    value: value.to_owned(),
+                })
+            }
+        }
+    }
+
+    if let Some(destination) = matches.value_of("destination") {
+        overrides.push(("bank".to_owned(), destination.to_owned()));
+    }
+
+    Ok(overrides)
+}
+
+/// Optional fields shared by the commands that write a transaction.
+fn transaction_field_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("description")
+            .long("description")
+            .short("d")
+            .takes_value(true)
+            .help("Description of the transaction"),
+        Arg::with_name("currency")
+            .long("currency")
+            .takes_value(true)
+            .help("ISO 4217 code of the amount if it is not in the account's currency"),
+        Arg::with_name("converted")
+            .long("converted")
+            .takes_value(true)
+            .help("Amount in the account's currency, converted with the rate table by default"),
+        Arg::with_name("fees")
+            .long("fees")
+            .takes_value(true)
+            .help("Fees of the transaction (amount[towards](category);amount[towards];...), categories being optional"),
+        Arg::with_name("category")
+            .long("category")
+            .takes_value(true)
+            .help("Category of the transaction, an empty one removing it"),
+        Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .help("Payer of an income or donation"),
+        Arg::with_name("towards")
+            .long("towards")
+            .takes_value(true)
+            .help("Payee of an expense or payout, or destination account of a transfer"),
+        Arg::with_name("requester")
+            .long("requester")
+            .takes_value(true)
+            .help("Person who requested an expense or payout"),
+        Arg::with_name("id")
+            .long("id")
+            .takes_value(true)
+            .help("Hexadecimal donation, payout or transfer ID, or the refunded donation ID if known"),
+    ]
+}
+
+fn transaction_args<'a>(matches: &'a ArgMatches) -> ledger::transactions::TransactionArgs<'a> {
+    ledger::transactions::TransactionArgs {
+        kind: matches.value_of("kind"),
+        date: matches.value_of("date"),
+        amount: matches.value_of("amount"),
+        currency: matches.value_of("currency"),
+        converted: matches.value_of("converted"),
+        description: matches.value_of("description"),
+        fees: matches.value_of("fees"),
+        category: matches.value_of("category"),
+        from: matches.value_of("from"),
+        towards: matches.value_of("towards"),
+        requester: matches.value_of("requester"),
+        id: matches.value_of("id"),
+    }
+}
+
+fn opening_date(value: &str) -> Result<DateTime<Utc>, ledger::Error> {
+    Utc.datetime_from_str(value, DATE_FORMAT).map_err(|_| ledger::Error::BadDate {
+        line: None,
+        column: "DATE",
+        value: value.to_owned(),
+    })
+}
+
+fn year(matches: &ArgMatches) -> Result<i32, ledger::Error> {
+    let year = matches.value_of("year").unwrap();
+    year.parse().map_err(|_| ledger::Error::BadArgument {
+        argument: "year".to_owned(),
+        value: year.to_owned(),
+    })
+}
+
+fn currency_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("currency")
+        .long("currency")
+        .takes_value(true)
+        .help("ISO 4217 code of the currency to report in, the ledger's base currency by default")
+}
+
+fn category_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("parent")
+            .long("parent")
+            .takes_value(true)
+            .help("Category the category is within, an empty one making it a top level category"),
+        Arg::with_name("rule")
+            .long("rule")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Case insensitive regular expression matched against the description and counterpart of imported transactions, and the payee of their fees"),
+    ]
+}
+
+fn donor_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("alias")
+            .long("alias")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Another name the donor gives under, such as on another platform"),
+        Arg::with_name("email")
+            .long("email")
+            .takes_value(true)
+            .help("Email address of the donor, an empty one removing it"),
+    ]
+}
+
+fn statement_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("date")
+            .long("date")
+            .takes_value(true)
+            .required_unless("statement")
+            .requires("balance")
+            .help("UTC date of the statement's closing balance (YYYY/MM/DD HH:MM)"),
+        Arg::with_name("balance")
+            .long("balance")
+            .takes_value(true)
+            .requires("date")
+            .help("Closing balance of the statement"),
+        Arg::with_name("statement")
+            .long("statement")
+            .takes_value(true)
+            .conflicts_with_all(&["date", "balance"])
+            .help("Path to an OFX or CAMT.053 statement to read the closing balance from"),
+        Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .requires("statement")
+            .possible_values(&["ofx", "qfx", "camt"])
+            .help("Format of the statement, guessed from the file extension by default"),
+    ]
+}
+
+/// Closing balance of a statement, typed by the user or read from a statement file.
+fn statement<'a>(matches: &'a ArgMatches) -> Result<ledger::reconcile::Statement<'a>, ledger::Error> {
+    if let Some(statement) = matches.value_of("statement") {
+        let path = existing_path(statement)?;
+        let format = match matches.value_of("format") {
+            Some(format) => format.into(),
+            None => ledger::bank::StatementFormat::of(&path),
+        };
+        return Ok(ledger::reconcile::Statement::File { path, format });
+    }
+
+    let date = matches.value_of("date").unwrap();
+    Ok(ledger::reconcile::Statement::Typed {
+        date: Utc.datetime_from_str(date, DATE_FORMAT).map_err(|_| ledger::Error::BadArgument {
+            argument: "date".to_owned(),
+            value: date.to_owned(),
+        })?,
+        balance: matches.value_of("balance").unwrap(),
+    })
+}
@@ -1,6 +1,6 @@
 use super::{
     super::tui_utils::Event,
-    text::{generate_help_text, generate_info_text, generate_input_fields_text},
+    text::{generate_help_text, generate_info_text, generate_input_fields_text, generate_status_text},
     utils::{decrease_modular, increase_modular},
     LedgerList, LedgerTab, LedgerTabState, Trans,
 };
@@ -11,17 +11,18 @@ pub fn event(tab: &mut LedgerTab, event: Event<Key>) -> Trans {
     match event {
         Event::Input(Key::Ctrl('Q')) => Trans::Quit,
         Event::Input(Key::Ctrl('S')) => {
-            tab.ledger
-                .save(&tab.ledger_path)
-                .unwrap_or_else(|e| panic!("Could not save ledger!\n{}", e));
+            if let Err(e) = tab.ledger.save(&tab.ledger_path) {
+                generate_status_text(tab, &e);
+            }
             Trans::None
         }
-        Event::Input(Key::Esc) => {
-            tab.ledger
-                .save(&tab.ledger_path)
-                .unwrap_or_else(|e| panic!("Could not save ledger!\n{}", e));
-            Trans::Quit
-        }
+        Event::Input(Key::Esc) => match tab.ledger.save(&tab.ledger_path) {
+            Ok(()) => Trans::Quit,
+            Err(e) => {
+                generate_status_text(tab, &e);
+                Trans::None
+            }
+        },
         Event::Input(Key::Up) => match tab.active_list {
             LedgerList::Accounts => {
                 decrease_modular(&mut tab.account_cursor, 1, tab.accounts_names.len());
@@ -150,6 +151,29 @@ pub fn event(tab: &mut LedgerTab, event: Event<Key>) -> Trans {
             }
             Trans::None
         }
+        Event::Input(Key::Char('e')) => {
+            if tab.active_list == LedgerList::Transactions {
+                super::new_txn::edit_selected(tab);
+            }
+            Trans::None
+        }
+        Event::Input(Key::Char('c')) => {
+            if tab.active_list == LedgerList::Transactions {
+                let cursor = tab.accounts_cursors.get(tab.account_cursor).cloned();
+                let transaction = cursor.and_then(|cursor| {
+                    tab.ledger
+                        .accounts
+                        .get_mut(tab.account_cursor)?
+                        .transactions
+                        .get_mut(cursor)
+                });
+                if let Some(transaction) = transaction {
+                    transaction.cleared = !transaction.cleared;
+                    generate_info_text(tab);
+                }
+            }
+            Trans::None
+        }
         Event::Input(Key::Char('-')) => {
             if tab.accounts_names.len() != 0 {
                 tab.state = LedgerTabState::Delete;
@@ -1,5 +1,5 @@
 use super::{tui_utils::Event, MainTab, OrdinaryFrame, Trans};
-use crate::ledger::Ledger;
+use crate::ledger::{self, Ledger};
 use num_derive::FromPrimitive;
 use termion::event::Key;
 use tui::{
@@ -27,6 +27,7 @@ enum LedgerTabState {
     Normal,
     NewAccount,
     NewTransaction(u8, NewTransactionKind),
+    EditTransaction(u8, NewTransactionKind),
     Delete,
 }
 
@@ -36,6 +37,9 @@ pub enum NewTransactionKind {
     GeneralExpense = 1,
     DonationIncome = 2,
     PayoutExpense = 3,
+    Transfer = 4,
+    Refund = 5,
+    Chargeback = 6,
 }
 
 pub struct LedgerTab<'a> {
@@ -54,16 +58,16 @@ pub struct LedgerTab<'a> {
 
     help_text: Vec<Text<'a>>,
     info_text: Vec<Text<'a>>,
+    status_text: Vec<Text<'a>>,
 }
 
 impl<'a> LedgerTab<'a> {
-    pub fn new(ledger_path: std::path::PathBuf) -> LedgerTab<'a> {
+    pub fn new(ledger_path: std::path::PathBuf) -> Result<LedgerTab<'a>, ledger::Error> {
         let ledger = if !ledger_path.exists() {
-            crate::ledger::new(ledger_path.clone());
+            ledger::new(ledger_path.clone())?;
             Ledger::default()
         } else {
-            Ledger::load(ledger_path.clone())
-                .unwrap_or_else(|e| panic!("Failed to load ledger!\n{}", e))
+            Ledger::load(ledger_path.clone())?
         };
 
         let mut new = LedgerTab {
@@ -82,12 +86,13 @@ impl<'a> LedgerTab<'a> {
 
             help_text: Vec::new(),
             info_text: Vec::new(),
+            status_text: Vec::new(),
         };
 
         text::generate_help_text(&mut new);
         text::generate_info_text(&mut new);
 
-        new
+        Ok(new)
     }
 }
 
@@ -97,6 +102,22 @@ impl<'a> MainTab for LedgerTab<'a> {
     }
 
     fn render(&self, f: &mut OrdinaryFrame, frame: Rect) {
+        let frame = if self.status_text.len() != 0 {
+            let status_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+                .split(frame);
+
+            Paragraph::new(self.status_text.iter())
+                .block(Block::default().borders(Borders::ALL).title("Error"))
+                .wrap(true)
+                .render(f, status_chunks[1]);
+
+            status_chunks[0]
+        } else {
+            frame
+        };
+
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
@@ -157,6 +178,16 @@ impl<'a> MainTab for LedgerTab<'a> {
                         .wrap(true)
                         .render(f, chunks[1]);
                 }
+                LedgerTabState::EditTransaction(_, _) => {
+                    Paragraph::new(self.rendered_fields.iter())
+                        .block(
+                            Block::default()
+                                .borders(Borders::ALL)
+                                .title("Edit transaction"),
+                        )
+                        .wrap(true)
+                        .render(f, chunks[1]);
+                }
                 _ => {}
             }
         }
@@ -208,10 +239,16 @@ impl<'a> MainTab for LedgerTab<'a> {
     }
 
     fn event(&mut self, event: Event<Key>) -> Trans {
+        if event != Event::Tick {
+            self.status_text.clear();
+        }
+
         match self.state {
             LedgerTabState::Normal => list_nav::event(self, event),
             LedgerTabState::NewAccount => new_account::event(self, event),
-            LedgerTabState::NewTransaction(_, _) => new_txn::event(self, event),
+            LedgerTabState::NewTransaction(_, _) | LedgerTabState::EditTransaction(_, _) => {
+                new_txn::event(self, event)
+            }
             LedgerTabState::Delete => delete::event(self, event),
         }
     }
@@ -1,6 +1,6 @@
 use super::{
     super::tui_utils::Event,
-    text::{generate_help_text, generate_info_text, generate_input_fields_text},
+    text::{generate_help_text, generate_info_text, generate_input_fields_text, generate_status_text},
     utils::{decrease_modular, increase_modular},
     LedgerTab, LedgerTabState, Trans,
 };
@@ -32,12 +32,12 @@ pub fn event(tab: &mut LedgerTab, event: Event<Key>) -> Trans {
                         .expect("Unreachable: new_account field1"),
                     crate::DATE_FORMAT,
                 ) {
-                    if let Ok(mut amount) = currency::Currency::from_str(&tab
-                        .text_input_fields
-                        .get(2)
-                        .expect("Unreachable: new_account field2"))
-                    {
-                        amount.set_symbol('$');
+                    if let Some(amount) = crate::ledger::Money::parse(
+                        &tab.text_input_fields
+                            .get(2)
+                            .expect("Unreachable: new_account field2"),
+                        tab.ledger.settings.base_currency,
+                    ) {
                         let name = tab
                             .text_input_fields
                             .get(0)
@@ -45,15 +45,19 @@ pub fn event(tab: &mut LedgerTab, event: Event<Key>) -> Trans {
                         if name.trim() != ""
                             && !tab.ledger.accounts.iter().any(|x| x.name == *name)
                         {
-                            tab.ledger.new_account(name, amount, date);
-                            tab.accounts_cursors.push(0);
-                            tab.transactions_names.push(Vec::new());
-                            tab.accounts_names.push(name.clone());
-                            tab.text_input_fields.clear();
-                            tab.selected_field = 0;
-                            tab.state = LedgerTabState::Normal;
-                            generate_info_text(tab);
-                            generate_help_text(tab);
+                            match tab.ledger.new_account(name, amount, date) {
+                                Ok(()) => {
+                                    tab.accounts_cursors.push(0);
+                                    tab.transactions_names.push(Vec::new());
+                                    tab.accounts_names.push(name.clone());
+                                    tab.text_input_fields.clear();
+                                    tab.selected_field = 0;
+                                    tab.state = LedgerTabState::Normal;
+                                    generate_info_text(tab);
+                                    generate_help_text(tab);
+                                }
+                                Err(e) => generate_status_text(tab, &e),
+                            }
                         }
                     }
                 }
@@ -1,21 +1,23 @@
 use super::{
     super::tui_utils::Event,
-    text::{generate_help_text, generate_info_text, generate_input_fields_text},
+    text::{
+        generate_help_text, generate_info_text, generate_input_fields_text, generate_status_text,
+        regenerate_transaction_names,
+    },
     utils::{decrease_modular, increase_modular},
     LedgerTab, LedgerTabState, NewTransactionKind, Trans,
 };
-use crate::ledger;
+use crate::ledger::{
+    self,
+    transactions::{format_fees, TransactionArgs, TransactionFields, TransactionKind},
+};
 use crate::utils::GetOrDefault;
 use num::traits::FromPrimitive;
 use termion::event::Key;
 
-use lazy_static::lazy_static;
-use regex::Regex;
-lazy_static! {
-    static ref FEES_REGEX: Regex = { Regex::new(r"([^\[]+)\[([^\]]+)\]").unwrap() };
-}
-
 pub const FIELDS: &'static [&'static str] = &["Kind"];
+/// Last field of every kind, whose value is chosen among the ledger's categories.
+const CATEGORY_FIELD: &str = "Category (left/right to choose)";
 pub const FIELDS_KIND: &'static [&'static [&'static str]] = &[
     &[
         "UTC date (YYYY/MM/DD HH:MM)",
@@ -23,6 +25,7 @@ pub const FIELDS_KIND: &'static [&'static [&'static str]] = &[
         "Amount",
         "Fees",
         "From",
+        CATEGORY_FIELD,
     ],
     &[
         "UTC date (YYYY/MM/DD HH:MM)",
@@ -31,6 +34,7 @@ pub const FIELDS_KIND: &'static [&'static [&'static str]] = &[
         "Fees",
         "Towards",
         "Requester",
+        CATEGORY_FIELD,
     ],
     &[
         "UTC date (YYYY/MM/DD HH:MM)",
@@ -39,6 +43,7 @@ pub const FIELDS_KIND: &'static [&'static [&'static str]] = &[
         "Fees",
         "From",
         "Donation ID",
+        CATEGORY_FIELD,
     ],
     &[
         "UTC date (YYYY/MM/DD HH:MM)",
@@ -48,6 +53,33 @@ pub const FIELDS_KIND: &'static [&'static [&'static str]] = &[
         "Towards",
         "Requester",
         "Payout ID",
+        CATEGORY_FIELD,
+    ],
+    &[
+        "UTC date (YYYY/MM/DD HH:MM)",
+        "Description",
+        "Amount",
+        "Fees",
+        "Destination account",
+        CATEGORY_FIELD,
+    ],
+    &[
+        "UTC date (YYYY/MM/DD HH:MM)",
+        "Description",
+        "Amount",
+        "Fees",
+        "Donor",
+        "Refunded donation ID (empty if unknown)",
+        CATEGORY_FIELD,
+    ],
+    &[
+        "UTC date (YYYY/MM/DD HH:MM)",
+        "Description",
+        "Amount",
+        "Fees",
+        "Donor",
+        "Refunded donation ID (empty if unknown)",
+        CATEGORY_FIELD,
     ],
 ];
 
@@ -58,229 +90,308 @@ impl NewTransactionKind {
             NewTransactionKind::GeneralExpense => "Expense",
             NewTransactionKind::GeneralIncome => "Income",
             NewTransactionKind::PayoutExpense => "Payout Expense",
+            NewTransactionKind::Transfer => "Transfer",
+            NewTransactionKind::Refund => "Refund",
+            NewTransactionKind::Chargeback => "Chargeback",
+        }
+    }
+
+    fn of(kind: TransactionKind) -> Self {
+        match kind {
+            TransactionKind::Income => NewTransactionKind::GeneralIncome,
+            TransactionKind::Expense => NewTransactionKind::GeneralExpense,
+            TransactionKind::Donation => NewTransactionKind::DonationIncome,
+            TransactionKind::Payout => NewTransactionKind::PayoutExpense,
+            TransactionKind::Refund => NewTransactionKind::Refund,
+            TransactionKind::Chargeback => NewTransactionKind::Chargeback,
+            TransactionKind::Transfer => NewTransactionKind::Transfer,
+        }
+    }
+
+    fn transaction_kind(self) -> TransactionKind {
+        match self {
+            NewTransactionKind::GeneralIncome => TransactionKind::Income,
+            NewTransactionKind::GeneralExpense => TransactionKind::Expense,
+            NewTransactionKind::DonationIncome => TransactionKind::Donation,
+            NewTransactionKind::PayoutExpense => TransactionKind::Payout,
+            NewTransactionKind::Refund => TransactionKind::Refund,
+            NewTransactionKind::Chargeback => TransactionKind::Chargeback,
+            NewTransactionKind::Transfer => TransactionKind::Transfer,
+        }
+    }
+}
+
+/// Opens the transaction form on the selected transaction.
+/// The destination leg of a transfer is edited through its source leg.
+pub fn edit_selected(tab: &mut LedgerTab) {
+    let cursor = *tab
+        .accounts_cursors
+        .get(tab.account_cursor)
+        .expect("Unreachable: edit_txn cursor bounds");
+    let (account, position) = match tab.ledger.locate_transaction_at(tab.account_cursor, cursor) {
+        Ok(x) => x,
+        Err(e) => {
+            generate_status_text(tab, &e);
+            return;
+        }
+    };
+
+    let kind = TransactionKind::of(&tab.ledger.accounts[account].transactions[position]);
+    let selected = NewTransactionKind::of(kind);
+    tab.state = LedgerTabState::EditTransaction(0, selected);
+    *tab.text_input_fields.get_mut_or_default(0) = selected.get_name().to_owned();
+    generate_input_fields_text(tab, FIELDS, "Next");
+}
+
+/// Location of the transaction being edited, if any.
+fn edited_transaction(tab: &LedgerTab) -> Option<Result<(usize, usize), ledger::Error>> {
+    if let LedgerTabState::EditTransaction(_, _) = tab.state {
+        let cursor = *tab
+            .accounts_cursors
+            .get(tab.account_cursor)
+            .expect("Unreachable: edit_txn cursor bounds");
+        Some(tab.ledger.locate_transaction_at(tab.account_cursor, cursor))
+    } else {
+        None
+    }
+}
+
+/// Lays out the fields of a transaction in the order of `FIELDS_KIND`.
+fn form_values(fields: &TransactionFields, kind: NewTransactionKind) -> Vec<String> {
+    let mut values = vec![
+        fields.date.format(crate::DATE_FORMAT).to_string(),
+        fields.description.clone(),
+        fields.amount.to_string(),
+        format_fees(&fields.fees),
+    ];
+    let id = hex::encode(&fields.id);
+
+    match kind {
+        NewTransactionKind::GeneralIncome => values.push(fields.from.clone()),
+        NewTransactionKind::GeneralExpense => {
+            values.push(fields.towards.clone());
+            values.push(fields.requester.clone());
+        }
+        NewTransactionKind::DonationIncome => {
+            values.push(fields.from.clone());
+            values.push(id);
+        }
+        NewTransactionKind::PayoutExpense => {
+            values.push(fields.towards.clone());
+            values.push(fields.requester.clone());
+            values.push(id);
+        }
+        NewTransactionKind::Refund | NewTransactionKind::Chargeback => {
+            values.push(fields.towards.clone());
+            values.push(id);
+        }
+        NewTransactionKind::Transfer => values.push(fields.towards.clone()),
+    }
+    values.push(fields.category.clone().unwrap_or_default());
+
+    values
+}
+
+/// Reads the form back, in the order of `FIELDS_KIND`.
+fn form_args(values: &[String], kind: NewTransactionKind) -> TransactionArgs<'_> {
+    let value = |i: usize| Some(values.get(i).map(String::as_str).unwrap_or(""));
+
+    let mut args = TransactionArgs {
+        kind: Some(kind.transaction_kind().name()),
+        date: value(0),
+        description: value(1),
+        amount: value(2),
+        fees: value(3),
+        ..TransactionArgs::default()
+    };
+
+    match kind {
+        NewTransactionKind::GeneralIncome => args.from = value(4),
+        NewTransactionKind::GeneralExpense => {
+            args.towards = value(4);
+            args.requester = value(5);
+        }
+        NewTransactionKind::DonationIncome => {
+            args.from = value(4);
+            args.id = value(5);
+        }
+        NewTransactionKind::PayoutExpense => {
+            args.towards = value(4);
+            args.requester = value(5);
+            args.id = value(6);
+        }
+        NewTransactionKind::Refund | NewTransactionKind::Chargeback => {
+            args.towards = value(4);
+            args.id = value(5);
+        }
+        NewTransactionKind::Transfer => args.towards = value(4),
+    }
+    args.category = value(FIELDS_KIND[kind as usize].len() - 1);
+
+    args
+}
+
+/// Records the form, either as a new transaction on the selected account
+/// or in place of the transaction being edited.
+fn confirm(tab: &mut LedgerTab, kind: NewTransactionKind) -> Result<(), ledger::Error> {
+    let (account, position) = match edited_transaction(tab) {
+        Some(location) => {
+            let (account, position) = location?;
+            (account, Some(position))
+        }
+        None => (tab.account_cursor, None),
+    };
+
+    let mut fields = match position {
+        Some(position) => {
+            TransactionFields::from_transaction(&tab.ledger.accounts[account].transactions[position])
+        }
+        None => TransactionFields::new(tab.ledger.accounts[account].currency),
+    };
+    form_args(&tab.text_input_fields, kind).apply(&mut fields, tab.ledger.accounts[account].currency)?;
+
+    let name = tab.ledger.accounts[account].name.clone();
+    let transaction = fields.into_transaction(&tab.ledger, &name)?;
+
+    match position {
+        Some(position) => tab.ledger.replace_transaction_at(account, position, transaction),
+        None => {
+            if let ledger::TransactionMetadata::Transfer { .. } = transaction.meta {
+                tab.ledger.push_transfer(transaction)
+            } else {
+                tab.ledger.accounts[account].transactions.push(transaction);
+                Ok(())
+            }
         }
     }
 }
 
 pub fn event(tab: &mut LedgerTab, event: Event<Key>) -> Trans {
-    if let LedgerTabState::NewTransaction(ref mut state, ref mut selected) = tab.state {
-        match state {
-            0 => match event {
-                Event::Input(Key::Esc) => {
-                    tab.text_input_fields.clear();
+    let (state, selected) = match tab.state {
+        LedgerTabState::NewTransaction(ref mut state, ref mut selected)
+        | LedgerTabState::EditTransaction(ref mut state, ref mut selected) => (state, selected),
+        _ => return Trans::None,
+    };
+
+    match state {
+        0 => match event {
+            Event::Input(Key::Esc) => {
+                tab.text_input_fields.clear();
+                tab.selected_field = 0;
+                tab.state = LedgerTabState::Normal;
+            }
+            Event::Input(Key::Up) => {
+                decrease_modular(&mut tab.selected_field, 1, FIELDS.len() + 1);
+                generate_input_fields_text(tab, FIELDS, "Next");
+            }
+            Event::Input(Key::Down) => {
+                increase_modular(&mut tab.selected_field, 1, FIELDS.len() + 1);
+                generate_input_fields_text(tab, FIELDS, "Next");
+            }
+            Event::Input(Key::Left) => {
+                let mut selected_int = *selected as usize;
+                decrease_modular(&mut selected_int, 1, FIELDS_KIND.len());
+                *selected = NewTransactionKind::from_usize(selected_int)
+                    .expect("Unreachable: from_usize left");
+                *tab.text_input_fields.get_mut_or_default(0) = selected.get_name().to_owned();
+                generate_input_fields_text(tab, FIELDS, "Next");
+            }
+            Event::Input(Key::Right) => {
+                let mut selected_int = *selected as usize;
+                increase_modular(&mut selected_int, 1, FIELDS_KIND.len());
+                *selected = NewTransactionKind::from_usize(selected_int)
+                    .expect("Unreachable: from_usize right");
+                *tab.text_input_fields.get_mut_or_default(0) = selected.get_name().to_owned();
+                generate_input_fields_text(tab, FIELDS, "Next");
+            }
+            Event::Input(Key::Char('\n')) => {
+                if tab.selected_field == FIELDS.len() {
+                    *state = 1;
+                    let kind = *selected;
+                    let fields = FIELDS_KIND[kind as usize];
+
+                    tab.text_input_fields = match edited_transaction(tab) {
+                        Some(Ok((account, position))) => form_values(
+                            &TransactionFields::from_transaction(
+                                &tab.ledger.accounts[account].transactions[position],
+                            ),
+                            kind,
+                        ),
+                        _ => Vec::new(),
+                    };
                     tab.selected_field = 0;
-                    tab.state = LedgerTabState::Normal;
-                }
-                Event::Input(Key::Up) => {
-                    decrease_modular(&mut tab.selected_field, 1, FIELDS.len() + 1);
-                    generate_input_fields_text(tab, FIELDS, "Next");
-                }
-                Event::Input(Key::Down) => {
-                    increase_modular(&mut tab.selected_field, 1, FIELDS.len() + 1);
-                    generate_input_fields_text(tab, FIELDS, "Next");
-                }
-                Event::Input(Key::Left) => {
-                    let mut selected_int = *selected as usize;
-                    decrease_modular(&mut selected_int, 1, FIELDS_KIND.len());
-                    *selected = NewTransactionKind::from_usize(selected_int)
-                        .expect("Unreachable: from_usize left");
-                    *tab.text_input_fields.get_mut_or_default(0) = selected.get_name().to_owned();
-                    generate_input_fields_text(tab, FIELDS, "Next");
-                }
-                Event::Input(Key::Right) => {
-                    let mut selected_int = *selected as usize;
-                    increase_modular(&mut selected_int, 1, FIELDS_KIND.len());
-                    *selected = NewTransactionKind::from_usize(selected_int)
-                        .expect("Unreachable: from_usize right");
-                    *tab.text_input_fields.get_mut_or_default(0) = selected.get_name().to_owned();
-                    generate_input_fields_text(tab, FIELDS, "Next");
+                    generate_input_fields_text(tab, fields, "Confirm");
                 }
-                Event::Input(Key::Char('\n')) => {
-                    if tab.selected_field == FIELDS.len() {
-                        tab.text_input_fields.clear();
-                        let fields = FIELDS_KIND[*selected as usize];
-                        tab.selected_field = 0;
-                        *state = 1;
-                        generate_input_fields_text(tab, fields, "Confirm");
+            }
+            _ => {}
+        },
+        1 => match event {
+            Event::Input(Key::Esc) => {
+                tab.text_input_fields.clear();
+                tab.selected_field = 0;
+                tab.state = LedgerTabState::Normal;
+            }
+            Event::Input(Key::Up) => {
+                let fields = FIELDS_KIND[*selected as usize];
+                decrease_modular(&mut tab.selected_field, 1, fields.len() + 1);
+                generate_input_fields_text(tab, fields, "Confirm");
+            }
+            Event::Input(Key::Down) => {
+                let fields = FIELDS_KIND[*selected as usize];
+                increase_modular(&mut tab.selected_field, 1, fields.len() + 1);
+                generate_input_fields_text(tab, fields, "Confirm");
+            }
+            Event::Input(Key::Left) | Event::Input(Key::Right) => {
+                let fields = FIELDS_KIND[*selected as usize];
+                if tab.selected_field == fields.len() - 1 {
+                    // Cycles through no category and every category of the ledger.
+                    let mut names = vec![String::new()];
+                    names.extend(tab.ledger.category_tree().iter().map(|x| x.0.name.clone()));
+                    let field = tab.text_input_fields.get_mut_or_default(tab.selected_field);
+                    let mut i = names.iter().position(|x| x == field).unwrap_or(0);
+                    if event == Event::Input(Key::Left) {
+                        decrease_modular(&mut i, 1, names.len());
+                    } else {
+                        increase_modular(&mut i, 1, names.len());
                     }
+                    *field = names.swap_remove(i);
+                    generate_input_fields_text(tab, fields, "Confirm");
                 }
-                _ => {}
-            },
-            1 => match event {
-                Event::Input(Key::Esc) => {
+            }
+            Event::Input(Key::Char('\n')) => {
+                if tab.selected_field == FIELDS_KIND[*selected as usize].len() {
+                    let kind = *selected;
+                    if let Err(e) = confirm(tab, kind) {
+                        generate_status_text(tab, &e);
+                        return Trans::None;
+                    }
+
                     tab.text_input_fields.clear();
                     tab.selected_field = 0;
                     tab.state = LedgerTabState::Normal;
+
+                    regenerate_transaction_names(tab);
+                    generate_info_text(tab);
+                    generate_help_text(tab);
                 }
-                Event::Input(Key::Up) => {
+            }
+            Event::Input(Key::Char(x)) => {
+                if let Some(field) = tab.text_input_fields.get_mut(tab.selected_field) {
+                    field.push(x);
                     let fields = FIELDS_KIND[*selected as usize];
-                    decrease_modular(&mut tab.selected_field, 1, fields.len() + 1);
                     generate_input_fields_text(tab, fields, "Confirm");
                 }
-                Event::Input(Key::Down) => {
+            }
+            Event::Input(Key::Backspace) => {
+                if let Some(field) = tab.text_input_fields.get_mut(tab.selected_field) {
+                    field.pop();
                     let fields = FIELDS_KIND[*selected as usize];
-                    increase_modular(&mut tab.selected_field, 1, fields.len() + 1);
                     generate_input_fields_text(tab, fields, "Confirm");
                 }
-                Event::Input(Key::Char('\n')) => {
-                    if tab.selected_field == FIELDS_KIND[*selected as usize].len() {
-                        use chrono::offset::TimeZone;
-                        if let Ok(date) = chrono::Utc.datetime_from_str(
-                            &tab.text_input_fields.get_or_default(0),
-                            crate::DATE_FORMAT,
-                        ) {
-                            if let Ok(mut amount) = currency::Currency::from_str(&tab
-                                .text_input_fields
-                                .get(2)
-                                .expect("Unreachable: new_txn amount")) {
-                                    amount.set_symbol('$');
-                                    if let Some(fees) = parse_fees(
-                                        tab.text_input_fields
-                                            .get(3)
-                                            .expect("Unreachable: new_txn fees"),
-                                    ) {
-                                        if let Some(meta) = match selected {
-                                            NewTransactionKind::GeneralExpense => {
-                                                Some(ledger::TransactionMetadata::Expense {
-                                                    kind: ledger::ExpenseKind::General,
-                                                    towards: tab
-                                                        .text_input_fields
-                                                        .get(4)
-                                                        .expect("Unreachable: new_txn towards")
-                                                        .to_owned(),
-                                                    requester: tab
-                                                        .text_input_fields
-                                                        .get(5)
-                                                        .expect("Unreachable: new_txn requester")
-                                                        .to_owned(),
-                                                })
-                                            }
-                                            NewTransactionKind::GeneralIncome => {
-                                                Some(ledger::TransactionMetadata::Income {
-                                                    kind: ledger::IncomeKind::General,
-                                                    from: tab
-                                                        .text_input_fields
-                                                        .get(4)
-                                                        .expect("Unreachable: new_txn from general")
-                                                        .to_owned(),
-                                                })
-                                            }
-                                            NewTransactionKind::DonationIncome => hex::decode(
-                                                tab.text_input_fields
-                                                    .get(5)
-                                                    .expect("Unreachable: new_txn donation id"),
-                                            )
-                                            .ok()
-                                            .map(|x| ledger::TransactionMetadata::Income {
-                                                kind: ledger::IncomeKind::Donation(x),
-                                                from: tab
-                                                    .text_input_fields
-                                                    .get(4)
-                                                    .expect("Unreachable: new_txn from donation")
-                                                    .to_owned(),
-                                            }),
-                                            NewTransactionKind::PayoutExpense => hex::decode(
-                                                tab.text_input_fields
-                                                    .get(6)
-                                                    .expect("Unreachable: new_txn payout id"),
-                                            )
-                                            .ok()
-                                            .map(|x| ledger::TransactionMetadata::Expense {
-                                                    kind: ledger::ExpenseKind::Payout(x),
-                                                    towards: tab
-                                                        .text_input_fields
-                                                        .get(4)
-                                                        .expect("Unreachable: new_txn towards payout")
-                                                        .to_owned(),
-                                                    requester: tab
-                                                        .text_input_fields
-                                                        .get(5)
-                                                        .expect("Unreachable: new_txn requester payout")
-                                                        .to_owned(),
-                                                })
-                                        } {
-                                            let account = tab
-                                                .ledger
-                                                .accounts
-                                                .get_mut(tab.account_cursor)
-                                                .expect("Unreachable: new_txn acc cursor bounds");
-                                            account.transactions.push(ledger::Transaction {
-                                                amount,
-                                                date,
-                                                description: tab
-                                                    .text_input_fields
-                                                    .get(1)
-                                                    .expect("Unreachable: new_txn description")
-                                                    .to_owned(),
-                                                meta,
-                                                fees,
-                                            });
-                                            tab.text_input_fields.clear();
-                                            tab.selected_field = 0;
-                                            tab.state = LedgerTabState::Normal;
-                                            tab.transactions_names =
-                                                super::text::generate_transaction_names(
-                                                    &tab.ledger,
-                                                );
-                                            generate_info_text(tab);
-                                            generate_help_text(tab);
-                                        }
-                                    }
-                                
-                            }
-                        }
-                    }
-                }
-                Event::Input(Key::Char(x)) => {
-                    if let Some(field) = tab.text_input_fields.get_mut(tab.selected_field) {
-                        field.push(x);
-                        let fields = FIELDS_KIND[*selected as usize];
-                        generate_input_fields_text(tab, fields, "Confirm");
-                    }
-                }
-                Event::Input(Key::Backspace) => {
-                    if let Some(field) = tab.text_input_fields.get_mut(tab.selected_field) {
-                        field.pop();
-                        let fields = FIELDS_KIND[*selected as usize];
-                        generate_input_fields_text(tab, fields, "Confirm");
-                    }
-                }
-                _ => {}
-            },
-            x => panic!("Unreachable: invalid new_txn state {}", x),
-        }
-    }
-    Trans::None
-}
-
-fn parse_fees(fees_str: &str) -> Option<Vec<ledger::Fee>> {
-    let mut fees = Vec::new();
-
-    for x in fees_str.split(';') {
-        if x.trim() != "" {
-            if let Some(c) = FEES_REGEX.captures(x) {
-                if let Some(amount) = c.get(1) {
-                    if let Ok(mut amount) = currency::Currency::from_str(amount.as_str()) {
-                            amount.set_symbol('$');
-                            if let Some(towards) = c.get(2) {
-                                fees.push(ledger::Fee {
-                                    amount,
-                                    towards: towards.as_str().to_owned(),
-                                });
-                            } else {
-                                return None;
-                            }
-                    } else {
-                        return None;
-                    }
-                } else {
-                    return None;
-                }
-            } else {
-                return None;
             }
-        }
+            _ => {}
+        },
+        x => panic!("Unreachable: invalid new_txn state {}", x),
     }
-
-    Some(fees)
+    Trans::None
 }
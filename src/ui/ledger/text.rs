@@ -1,4 +1,5 @@
 use super::{LedgerList, LedgerTab, LedgerTabState};
+use crate::ledger::transactions::format_donation;
 use crate::ledger::{ExpenseKind, IncomeKind, Ledger, TransactionMetadata};
 use tui::{
     style::{Color, Modifier, Style},
@@ -24,7 +25,7 @@ pub fn generate_help_text(tab: &mut LedgerTab) {
                     tab.help_text.push(Text::raw("\n"));
                 }
                 LedgerList::Transactions => {
-                    tab.help_text.push(Text::raw("   Press + to add an account.\n   Press Enter to add a transaction.\n   Press - then Del to delete the selected transaction.\n\n"));
+                    tab.help_text.push(Text::raw("   Press + to add an account.\n   Press Enter to add a transaction.\n   Press e to edit the selected transaction.\n   Press c to mark the selected transaction as cleared or uncleared.\n   Press - then Del to delete the selected transaction.\n\n"));
                 }
             }
         }
@@ -54,10 +55,14 @@ pub fn generate_info_text(tab: &mut LedgerTab) {
             .get(tab.account_cursor)
             .expect("Unreachable: account cursor out of bounds for info text");
         tab.info_text.push(Text::raw(format!(
-            "   Account name: {}\n   Opening balance: {}\n   Current balance: {}\n\n\n",
+            "   Account name: {}\n   Currency: {}\n   Opening balance: {}\n   Current balance: {}\n\n\n",
             account.name,
+            account.currency,
             &account.opening_balance.to_string(),
-            &account.current_balance().to_string(),
+            account
+                .current_balance()
+                .map(|x| x.to_string())
+                .unwrap_or_else(|e| e.to_string()),
         )));
 
         if tab.active_list == LedgerList::Transactions {
@@ -83,20 +88,32 @@ pub fn generate_info_text(tab: &mut LedgerTab) {
                 .get(txn_cursor)
                 .expect("Unreachable: txn_name 2");
 
-            let mut fees = currency::Currency::from(0, '$');
-            for x in &txn.fees {
-                fees = fees + &x.amount;
-            }
+            let fees = crate::ledger::Money::sum(account.currency, txn.fees.iter().map(|x| &x.amount))
+                .map(|x| x.to_string())
+                .unwrap_or_else(|e| e.to_string());
             
             tab.info_text.push(Text::raw(format!(
-                "   {}\n   {}\n   Date: {}\n   Gross amount: {}\n   Fees: {}\n",
+                "   {}\n   {}\n   Transaction ID: {}\n   Date: {}\n   Gross amount: {}\n   Fees: {}\n   Cleared: {}\n",
                 txn_name,
                 txn.description,
+                hex::encode(&txn.id),
                 txn.date.date(),
                 &txn.amount.to_string(),
-                &fees.to_string(),
+                fees,
+                if txn.cleared { "yes" } else { "no" },
             )));
 
+            if let Some(ref original) = txn.original {
+                tab.info_text.push(Text::raw(format!(
+                    "   Original amount: {}\n",
+                    original
+                )));
+            }
+
+            if let Some(ref category) = txn.category {
+                tab.info_text.push(Text::raw(format!("   Category: {}\n", category)));
+            }
+
             if let TransactionMetadata::Income {
                 kind: IncomeKind::Donation(ref uuid),
                 ..
@@ -115,17 +132,51 @@ pub fn generate_info_text(tab: &mut LedgerTab) {
                     "   Payout ID: {}\n",
                     hex::encode(uuid)
                 )));
+            } else if let TransactionMetadata::Expense {
+                kind: ExpenseKind::Refund { ref donation, .. },
+                ..
+            }
+            | TransactionMetadata::Expense {
+                kind: ExpenseKind::Chargeback { ref donation, .. },
+                ..
+            } = &txn.meta
+            {
+                tab.info_text.push(Text::raw(format!(
+                    "   Refunded donation ID: {}\n",
+                    format_donation(donation.as_deref().unwrap_or_default())
+                )));
+            } else if let TransactionMetadata::Transfer {
+                ref id,
+                ref from,
+                ref to,
+            } = &txn.meta
+            {
+                tab.info_text.push(Text::raw(format!(
+                    "   From: {}\n   To: {}\n   Transfer ID: {}\n",
+                    from,
+                    to,
+                    hex::encode(id)
+                )));
             }
         }
     }
 }
 
+pub fn generate_status_text(tab: &mut LedgerTab, error: &crate::ledger::Error) {
+    tab.status_text.clear();
+    tab.status_text.push(Text::styled(
+        format!(" {}", error),
+        Style::default().fg(Color::Red),
+    ));
+}
+
 pub fn generate_transaction_names(ledger: &Ledger) -> Vec<Vec<String>> {
     ledger
         .accounts
         .iter()
-        .map(|x| {
-            x.transactions
+        .map(|account| {
+            account
+                .transactions
                 .iter()
                 .map(|x| match x.meta {
                     TransactionMetadata::Income {
@@ -157,12 +208,44 @@ pub fn generate_transaction_names(ledger: &Ledger) -> Vec<Vec<String>> {
                         towards,
                         &x.amount.to_string(),
                     ),
+                    TransactionMetadata::Expense {
+                        kind: ExpenseKind::Refund { .. },
+                        ref towards,
+                        ..
+                    } => format!("Refund to {} ({})", towards, &x.amount.to_string()),
+                    TransactionMetadata::Expense {
+                        kind: ExpenseKind::Chargeback { .. },
+                        ref towards,
+                        ..
+                    } => format!("Chargeback by {} ({})", towards, &x.amount.to_string()),
+                    TransactionMetadata::Transfer {
+                        ref from,
+                        ref to,
+                        ..
+                    } => {
+                        if *from == account.name {
+                            format!("Transfer to {} ({})", to, &x.amount.to_string())
+                        } else {
+                            format!("Transfer from {} ({})", from, &x.amount.to_string())
+                        }
+                    }
                 })
                 .collect()
         })
         .collect()
 }
 
+/// Regenerates the transaction names of every account, as changing a transfer changes both of
+/// its accounts, and moves the cursors left past the end of their list to its last transaction.
+pub fn regenerate_transaction_names(tab: &mut LedgerTab) {
+    tab.transactions_names = generate_transaction_names(&tab.ledger);
+    for (cursor, names) in tab.accounts_cursors.iter_mut().zip(tab.transactions_names.iter()) {
+        if *cursor >= names.len() && *cursor != 0 {
+            *cursor = names.len().saturating_sub(1);
+        }
+    }
+}
+
 pub fn generate_input_fields_text(ledger: &mut LedgerTab, fields: &[&str], next: &'static str) {
     ledger.rendered_fields.clear();
     ledger.rendered_fields.push(Text::raw("\n"));
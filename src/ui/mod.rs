@@ -66,7 +66,7 @@ pub fn start(ledger: Option<std::path::PathBuf>) -> Result<(), Box<dyn Error>> {
     let mut tabs: Vec<Box<dyn MainTab>> = Vec::new();
 
     if let Some(ledger) = ledger {
-        tabs.push(Box::new(self::ledger::LedgerTab::new(ledger)));
+        tabs.push(Box::new(self::ledger::LedgerTab::new(ledger)?));
     }
 
     if tabs.len() == 0 {
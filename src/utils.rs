@@ -1,15 +1,3 @@
-use std::path::PathBuf;
-
-pub fn path_exists_or_panic(input: &str) -> PathBuf {
-    let path = PathBuf::from(input);
-
-    if !&path.exists() {
-        panic!("File at `{}` does not exist", input);
-    }
-
-    path
-}
-
 pub trait GetOrDefault<T: Default> {
     fn get_or_default(&'_ mut self, index: usize) -> &'_ T;
     fn get_mut_or_default(&'_ mut self, index: usize) -> &'_ mut T;